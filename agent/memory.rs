@@ -5,7 +5,7 @@
 
 use solana_sdk::pubkey::Pubkey;
 use crate::{
-    swap::DexType,
+    swap::{DexType, VolatilitySource},
     SwapRoute,
     Result,
     AgentSwapError,
@@ -16,6 +16,9 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
+/// Successful swaps of a pair needed to estimate its volatility
+const MIN_VOLATILITY_SAMPLES: usize = 3;
+
 /// Represents a historical swap record
 #[derive(Debug, Clone)]
 pub struct SwapRecord {
@@ -174,10 +177,33 @@ impl Memory {
     }
 }
 
+impl VolatilitySource for Memory {
+    /// Relative standard deviation of the execution rate of recent
+    /// successful swaps of the pair, across all DEXes
+    fn volatility_bps(&self, token_in: &Pubkey, token_out: &Pubkey) -> Option<u16> {
+        let rates: Vec<f64> = self.records
+            .iter()
+            .filter(|r| r.success && r.amount_in > 0)
+            .filter(|r| r.token_in == *token_in && r.token_out == *token_out)
+            .map(|r| r.amount_out as f64 / r.amount_in as f64)
+            .collect();
+        if rates.len() < MIN_VOLATILITY_SAMPLES {
+            return None;
+        }
+
+        let mean = rates.iter().sum::<f64>() / rates.len() as f64;
+        if mean == 0.0 {
+            return None;
+        }
+        let variance = rates.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / rates.len() as f64;
+        Some((variance.sqrt() / mean * 10_000.0).min(u16::MAX as f64) as u16)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
+    use solana_sdk::{signature::Keypair, transaction::VersionedTransaction};
 
     fn create_test_route() -> SwapRoute {
         SwapRoute {
@@ -187,7 +213,7 @@ mod tests {
             amount_out: 900000,
             price_impact_bps: 100,
             dex_type: DexType::Raydium,
-            transaction: Transaction::default(),
+            transaction: VersionedTransaction::default(),
         }
     }
 
@@ -218,4 +244,35 @@ mod tests {
         );
         assert_eq!(rate, 0.5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_volatility() {
+        let mut memory = Memory::new(10);
+        let mut route = create_test_route();
+        let (token_in, token_out) = (route.token_in, route.token_out);
+
+        for amount_out in [1_000_000, 1_000_000] {
+            route.amount_out = amount_out;
+            memory.add_swap(route.clone(), true).unwrap();
+        }
+        // Too few samples
+        assert_eq!(memory.volatility_bps(&token_in, &token_out), None);
+
+        route.amount_out = 1_000_000;
+        memory.add_swap(route.clone(), true).unwrap();
+        assert_eq!(memory.volatility_bps(&token_in, &token_out), Some(0));
+
+        // Rates of 0.98, 1.0, 1.0 and 1.02
+        for amount_out in [980_000, 1_020_000] {
+            route.amount_out = amount_out;
+            memory.add_swap(route.clone(), true).unwrap();
+        }
+        assert_eq!(memory.volatility_bps(&token_in, &token_out), Some(126));
+
+        // Failed swaps and other pairs are ignored
+        route.amount_out = 1;
+        memory.add_swap(route.clone(), false).unwrap();
+        assert_eq!(memory.volatility_bps(&token_in, &token_out), Some(126));
+        assert_eq!(memory.volatility_bps(&token_out, &token_in), None);
+    }
+}
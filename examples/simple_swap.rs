@@ -5,6 +5,7 @@ use agent_swap::{
     utils::{setup_wallet, parse_amount},
 };
 
+use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
     pubkey::Pubkey,
     signature::Keypair,
@@ -26,10 +27,15 @@ async fn main() -> Result<()> {
     let config = Config {
         rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
         max_slippage_bps: 100, // 1%
+        dynamic_slippage: true,
         use_ai_optimization: true,
         ..Config::default()
     };
 
+    // Swaps are confirmed at the configured commitment
+    let rpc = RpcClient::new_with_commitment(config.rpc_url.clone(), config.commitment);
+    let slippage = config.slippage();
+
     // Initialize agent and engine
     println!("Initializing agent and swap engine...");
     let (agent, engine) = agent_swap::init(config).await?;
@@ -51,7 +57,7 @@ async fn main() -> Result<()> {
     // Get best quote
     println!("\nGetting best quote...");
     let start = Instant::now();
-    let quote = engine.get_best_quote(&usdc, &sol, amount).await?;
+    let quote = engine.get_best_quote(&usdc, &sol, amount, slippage).await?;
     
     println!("Quote received in {:?}", start.elapsed());
     println!("Best route found on {:?}", quote.dex_type);
@@ -71,10 +77,11 @@ async fn main() -> Result<()> {
         let start = Instant::now();
         
         // Execute the swap
-        match engine.execute_swap(&quote, &wallet).await {
-            Ok(signature) => {
+        match engine.execute_swap(&rpc, &quote, &wallet).await {
+            Ok(receipt) => {
                 println!("Swap successful!");
-                println!("Transaction signature: {}", signature);
+                println!("Transaction signature: {}", receipt.signature);
+                println!("Confirmed in slot: {}", receipt.slot);
                 println!("Execution time: {:?}", start.elapsed());
 
                 // Record successful swap
@@ -99,7 +106,7 @@ async fn main() -> Result<()> {
     // Example of error handling
     println!("\nTesting error handling...");
     let result = engine
-        .get_best_quote(&Pubkey::new_unique(), &Pubkey::new_unique(), 1)
+        .get_best_quote(&Pubkey::new_unique(), &Pubkey::new_unique(), 1, slippage)
         .await;
     
     match result {
@@ -11,7 +11,7 @@ use anchor_client::Client;
 use solana_sdk::{
     pubkey::Pubkey,
     signature::Keypair,
-    transaction::Transaction,
+    transaction::VersionedTransaction,
 };
 use thiserror::Error;
 
@@ -39,6 +39,29 @@ pub enum AgentSwapError {
         actual: f64,
     },
 
+    #[error("Price moved beyond the slippage tolerance on {dex_type:?}")]
+    SlippageToleranceExceeded {
+        dex_type: swap::DexType,
+    },
+
+    #[error("Insufficient liquidity on {dex_type:?}")]
+    InsufficientLiquidity {
+        dex_type: swap::DexType,
+    },
+
+    #[error("{dex_type:?} program error {code:#x}: {reason}")]
+    ProgramError {
+        dex_type: swap::DexType,
+        code: u32,
+        reason: &'static str,
+    },
+
+    #[error("Simulation failed: {error}")]
+    SimulationFailed {
+        error: solana_sdk::transaction::TransactionError,
+        logs: Vec<String>,
+    },
+
     #[error("Agent error: {0}")]
     AgentError(String),
 
@@ -62,6 +85,25 @@ pub struct SwapConfig {
     pub use_ai: bool,
 }
 
+impl SwapConfig {
+    /// Slippage tolerance for quotes made under this configuration
+    pub fn slippage(&self) -> swap::Slippage {
+        swap::Slippage::Fixed(self.slippage_bps)
+    }
+
+    /// Time each venue gets to quote, a tenth of the swap timeout so
+    /// quoting leaves time to execute
+    pub fn quote_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.timeout_seconds) / 10
+    }
+
+    /// Time within which a swap whose blockhash expired is re-quoted and
+    /// sent again
+    pub fn confirm_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.timeout_seconds)
+    }
+}
+
 impl Default for SwapConfig {
     fn default() -> Self {
         Self {
@@ -88,8 +130,8 @@ pub struct SwapRoute {
     pub price_impact_bps: u16,
     /// DEX to use for the swap
     pub dex_type: swap::DexType,
-    /// Prepared transaction
-    pub transaction: Transaction,
+    /// Prepared v0 transaction
+    pub transaction: VersionedTransaction,
 }
 
 /// Core trait for swap execution
@@ -156,8 +198,11 @@ mod tests {
     fn test_swap_config_default() {
         let config = SwapConfig::default();
         assert_eq!(config.slippage_bps, 100);
+        assert_eq!(config.slippage(), swap::Slippage::Fixed(100));
         assert_eq!(config.min_amount, 1000);
         assert_eq!(config.timeout_seconds, 60);
+        assert_eq!(config.quote_timeout(), swap::DEFAULT_QUOTE_TIMEOUT);
+        assert_eq!(config.confirm_timeout(), swap::DEFAULT_CONFIRM_TIMEOUT);
         assert!(config.use_ai);
     }
 
@@ -2,16 +2,19 @@ use agent_swap::{
     agent::{Memory, SwapAgent},
     swap::{DexType, SwapEngine},
     utils::solana::{setup_client, setup_wallet},
+    Config,
 };
 
 use anchor_client::Client;
 use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
     pubkey::Pubkey,
     signature::{read_keypair_file, Keypair},
 };
 use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 use tracing::{info, warn, error};
 
 // Configuration constants
@@ -28,8 +31,17 @@ async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
     info!("Starting Agent-Swap");
 
+    // Quote within the configured slippage tolerance
+    let config = Config {
+        rpc_url: RPC_URL.to_string(),
+        dynamic_slippage: true,
+        ..Config::default()
+    };
+    let slippage = config.slippage();
+
     // Setup Solana client
     let client = setup_client(RPC_URL, CommitmentConfig::confirmed())?;
+    let rpc = RpcClient::new_with_commitment(RPC_URL.to_string(), CommitmentConfig::confirmed());
     info!("Connected to Solana network");
 
     // Load wallet
@@ -46,10 +58,14 @@ async fn main() -> Result<()> {
     let swap_engine = SwapEngine::new(client.clone())?;
     info!("Swap engine initialized");
 
+    // Dynamic slippage follows the volatility in the agent's memory
+    let memory = Arc::new(RwLock::new(Memory::default()));
+    swap_engine.set_volatility_source(memory.clone());
+
     // Initialize agent with Gloom
     let agent = SwapAgent::new(
         client,
-        Memory::default(),
+        memory,
         wallet.pubkey(),
     )?;
     info!("Agent initialized with Gloom integration");
@@ -60,36 +76,36 @@ async fn main() -> Result<()> {
     let amount = 100_000_000; // 100 USDC (6 decimals)
 
     info!("Requesting quote for USDC -> SOL swap");
-    match swap_engine.get_best_route(&usdc, &sol, amount).await {
-        Ok(route) => {
+    match swap_engine.get_best_quote(&usdc, &sol, amount, slippage).await {
+        Ok(quote) => {
             info!(
-                "Best route found: {} -> {} via {:?}",
-                "USDC", "SOL", route.dex_type
+                "Best quote found: {} -> {} via {:?}",
+                "USDC", "SOL", quote.dex_type
             );
             
-            // Let agent evaluate the route
-            if agent.evaluate_route(&route).await? {
-                info!("Agent approved route, executing swap...");
+            // Let agent evaluate the quote
+            if agent.evaluate_route(&quote).await? {
+                info!("Agent approved quote, executing swap...");
                 
-                match swap_engine.execute_swap(&route, &wallet).await {
-                    Ok(signature) => {
-                        info!("Swap executed successfully! Signature: {}", signature);
+                match swap_engine.execute_swap(&rpc, &quote, &wallet).await {
+                    Ok(receipt) => {
+                        info!("Swap executed successfully! Signature: {}", receipt.signature);
                         
                         // Update agent memory with successful swap
-                        agent.record_swap(route, signature).await?;
+                        agent.record_swap(quote, receipt.signature.to_string()).await?;
                     }
                     Err(e) => {
                         error!("Swap execution failed: {}", e);
                         // Update agent memory with failed attempt
-                        agent.record_failure(route, e.to_string()).await?;
+                        agent.record_failure(quote, e.to_string()).await?;
                     }
                 }
             } else {
-                warn!("Agent rejected route based on analysis");
+                warn!("Agent rejected quote based on analysis");
             }
         }
         Err(e) => {
-            error!("Failed to find swap route: {}", e);
+            error!("Failed to get quote: {}", e);
         }
     }
 
@@ -0,0 +1,185 @@
+//! DEX adapter interface
+//!
+//! Every venue the engine can route through implements [`DexAdapter`].
+//! Raydium and Orca ship with the crate; other venues can be added by
+//! registering their own adapter with the engine.
+
+use anchor_client::solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use anyhow::Result;
+
+use super::{discovery::AccountProvider, DexType, Quote};
+
+/// Why a venue's program rejected a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapFailure {
+    /// The price moved beyond the quote's slippage tolerance
+    SlippageExceeded,
+    /// The pool cannot fill the swap
+    InsufficientLiquidity,
+    /// Any other program error, by name
+    Other(&'static str),
+}
+
+/// A venue the swap engine can quote and route through.
+///
+/// Engines share adapters between tasks and update them copy-on-write,
+/// so adapters must be `Clone`.
+#[async_trait::async_trait]
+pub trait DexAdapter: CloneAdapter + Send + Sync {
+    /// Venue identifier, unique within an engine
+    fn dex_type(&self) -> DexType;
+
+    /// Mint pairs this adapter has pools for
+    fn supported_pairs(&self) -> Vec<(Pubkey, Pubkey)>;
+
+    /// Whether the adapter has a pool for the pair, in either order
+    fn supports_pair(&self, token_a: &Pubkey, token_b: &Pubkey) -> bool {
+        self.supported_pairs()
+            .iter()
+            .any(|&(a, b)| (a, b) == (*token_a, *token_b) || (a, b) == (*token_b, *token_a))
+    }
+
+    /// Quote swapping exactly `amount` of `token_in` into `token_out`,
+    /// accepting `slippage_bps` less output
+    async fn quote(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage_bps: u16,
+    ) -> Result<Quote>;
+
+    /// Quote receiving exactly `amount_out` of `token_out`, accepting
+    /// `slippage_bps` more input
+    async fn quote_exact_out(
+        &self,
+        _token_in: &Pubkey,
+        _token_out: &Pubkey,
+        _amount_out: u64,
+        _slippage_bps: u16,
+    ) -> Result<Quote> {
+        anyhow::bail!("{:?} does not support exact-output swaps", self.dex_type())
+    }
+
+    /// Instructions executing `quote` on behalf of `user`
+    fn build_swap_instructions(&self, quote: &Quote, user: &Pubkey) -> Result<Vec<Instruction>>;
+
+    /// Why custom error `code` from `program_id` rejected a swap, or `None`
+    /// if the program is not this venue's
+    fn decode_error(&self, _program_id: &Pubkey, _code: u32) -> Option<SwapFailure> {
+        None
+    }
+
+    /// Reload the state of known pools from chain
+    async fn refresh(&mut self, rpc: &dyn AccountProvider) -> Result<()>;
+}
+
+/// Object-safe cloning of adapters, implemented for every `Clone` adapter
+pub trait CloneAdapter {
+    /// Copy of the adapter, including its pool state
+    fn clone_adapter(&self) -> Box<dyn DexAdapter>;
+}
+
+impl<T: DexAdapter + Clone + 'static> CloneAdapter for T {
+    fn clone_adapter(&self) -> Box<dyn DexAdapter> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::swap::{slippage, SwapMode};
+    use futures::future;
+
+    /// Exact-input quote of 1_000 `token_in` for 2_000 `token_out`
+    /// through a fresh Raydium pool, accepting 1% slippage
+    pub(crate) fn quote(token_in: Pubkey, token_out: Pubkey) -> Quote {
+        Quote {
+            dex_type: DexType::Raydium,
+            mode: SwapMode::ExactIn,
+            token_in,
+            token_out,
+            token_program_in: anchor_spl::token::ID,
+            token_program_out: anchor_spl::token::ID,
+            pool: Pubkey::new_unique(),
+            amount_in: 1_000,
+            amount_out: 2_000,
+            price_impact_bps: 0,
+            minimum_out: 1_980,
+            maximum_in: 1_000,
+            extra_accounts: Vec::new(),
+        }
+    }
+
+    /// How a [`Stub`] venue answers quotes
+    #[derive(Clone, Copy)]
+    pub(crate) enum Behaviour {
+        /// Fixed output whatever the input
+        Quote(u64),
+        /// Output of `rate` per unit of input
+        Rate(u64),
+        /// Fee-less constant product pool with equal reserves
+        Pool(u64),
+        /// Every quote fails
+        Fail,
+        /// Quotes never finish
+        Stall,
+    }
+
+    /// Venue with a pool for one pair, answering as `behaviour` says
+    #[derive(Clone)]
+    pub(crate) struct Stub {
+        pub(crate) name: &'static str,
+        pub(crate) pair: (Pubkey, Pubkey),
+        pub(crate) behaviour: Behaviour,
+    }
+
+    #[async_trait::async_trait]
+    impl DexAdapter for Stub {
+        fn dex_type(&self) -> DexType {
+            DexType::Custom(self.name)
+        }
+
+        fn supported_pairs(&self) -> Vec<(Pubkey, Pubkey)> {
+            vec![self.pair]
+        }
+
+        async fn quote(
+            &self,
+            token_in: &Pubkey,
+            token_out: &Pubkey,
+            amount: u64,
+            slippage_bps: u16,
+        ) -> Result<Quote> {
+            let (amount_out, price_impact_bps) = match self.behaviour {
+                Behaviour::Quote(amount_out) => (amount_out, 0),
+                Behaviour::Rate(rate) => (amount * rate, 0),
+                Behaviour::Pool(reserve) => (
+                    (amount as u128 * reserve as u128 / (reserve as u128 + amount as u128)) as u64,
+                    (amount as u128 * 10_000 / reserve as u128) as u16,
+                ),
+                Behaviour::Fail => anyhow::bail!("pool closed"),
+                Behaviour::Stall => future::pending().await,
+            };
+            Ok(Quote {
+                dex_type: self.dex_type(),
+                pool: Pubkey::default(),
+                amount_in: amount,
+                amount_out,
+                price_impact_bps,
+                minimum_out: slippage::minimum_out(amount_out, slippage_bps)?,
+                maximum_in: amount,
+                ..quote(*token_in, *token_out)
+            })
+        }
+
+        fn build_swap_instructions(&self, _quote: &Quote, _user: &Pubkey) -> Result<Vec<Instruction>> {
+            Ok(Vec::new())
+        }
+
+        async fn refresh(&mut self, _rpc: &dyn AccountProvider) -> Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Quote cache
+//!
+//! Bounded, least-recently-used cache of best quotes. Entries expire by
+//! wall-clock age and by how many slots the chain has advanced since the
+//! quote was made, and are dropped when the pool they quote changes.
+//! Entries are spread across independently locked shards. Lookups and
+//! inserts that find their shard busy skip the cache instead of waiting,
+//! so concurrent callers share one cache through `&self` without ever
+//! blocking on each other.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, TryLockError,
+    },
+    time::{Duration, Instant},
+};
+
+use super::Quote;
+
+/// Token in, token out, amount in and slippage tolerance of a quote
+pub type CacheKey = (Pubkey, Pubkey, u64, u16);
+
+/// Most shards a cache is split into
+const MAX_SHARDS: usize = 16;
+
+/// Fewest quotes a shard holds, which keeps small caches exactly LRU
+const MIN_SHARD_CAPACITY: usize = 64;
+
+/// When cached quotes may be reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Oldest quote served
+    pub max_age: Duration,
+    /// Most slots the chain may advance past a quote before it is stale
+    pub max_slot_lag: u64,
+    /// Most quotes kept; zero disables caching. Recency is tracked per
+    /// shard, so large caches evict approximately least recently used.
+    pub capacity: usize,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(5),
+            max_slot_lag: 10,
+            capacity: 1024,
+        }
+    }
+}
+
+/// Counters describing cache effectiveness
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that found no usable quote
+    pub misses: u64,
+    /// Quotes dropped for age or slot lag
+    pub expirations: u64,
+    /// Quotes dropped to stay within capacity
+    pub evictions: u64,
+    /// Quotes dropped because their pool changed
+    pub invalidations: u64,
+    /// Lookups and inserts that skipped the cache because their shard
+    /// was busy
+    pub contended: u64,
+    /// Quotes currently cached
+    pub entries: usize,
+}
+
+impl CacheStats {
+    /// Share of lookups answered from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+struct Entry {
+    quote: Quote,
+    inserted_at: Instant,
+    slot: u64,
+    /// Position in the recency order
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<CacheKey, Entry>,
+    /// Keys by last use, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+    next_tick: u64,
+    stats: CacheStats,
+}
+
+impl Inner {
+    fn remove(&mut self, key: &CacheKey) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.tick);
+        Some(entry)
+    }
+
+    fn touch(&mut self, key: CacheKey) -> u64 {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        self.recency.insert(tick, key);
+        tick
+    }
+}
+
+/// Thread-safe LRU cache of quotes
+pub struct QuoteCache {
+    policy: CachePolicy,
+    /// Most quotes kept in each shard
+    shard_capacity: usize,
+    shards: Box<[Mutex<Inner>]>,
+    contended: AtomicU64,
+}
+
+impl QuoteCache {
+    /// Create an empty cache
+    pub fn new(policy: CachePolicy) -> Self {
+        Self::with_stats(policy, CacheStats::default())
+    }
+
+    fn with_stats(policy: CachePolicy, mut stats: CacheStats) -> Self {
+        let count = (policy.capacity / MIN_SHARD_CAPACITY).clamp(1, MAX_SHARDS);
+        let contended = std::mem::take(&mut stats.contended);
+        let shards = (0..count)
+            .map(|i| {
+                // Counters carried over are kept by the first shard
+                let stats = if i == 0 { stats } else { CacheStats::default() };
+                Mutex::new(Inner { stats, ..Inner::default() })
+            })
+            .collect();
+        Self {
+            policy,
+            shard_capacity: policy.capacity.div_ceil(count),
+            shards,
+            contended: AtomicU64::new(contended),
+        }
+    }
+
+    /// Empty cache with the same policy, carrying over the counters
+    pub fn emptied(&self) -> Self {
+        let mut stats = self.stats();
+        stats.invalidations += stats.entries as u64;
+        stats.entries = 0;
+        Self::with_stats(self.policy, stats)
+    }
+
+    /// Fresh quote for `key` as of `slot`
+    pub fn get(&self, key: &CacheKey, slot: u64) -> Option<Quote> {
+        self.get_at(key, slot, Instant::now())
+    }
+
+    /// Cache `quote` as made at `slot`
+    pub fn insert(&self, key: CacheKey, quote: Quote, slot: u64) {
+        self.insert_at(key, quote, slot, Instant::now())
+    }
+
+    /// Drop every quote through `pool`
+    pub fn invalidate_pool(&self, pool: &Pubkey) {
+        for shard in self.shards.iter() {
+            let mut inner = lock(shard);
+            let stale: Vec<CacheKey> = inner
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.quote.pool == *pool)
+                .map(|(key, _)| *key)
+                .collect();
+            for key in &stale {
+                inner.remove(key);
+            }
+            inner.stats.invalidations += stale.len() as u64;
+            inner.stats.entries = inner.entries.len();
+        }
+    }
+
+    /// Drop every quote
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            let mut inner = lock(shard);
+            inner.stats.invalidations += inner.entries.len() as u64;
+            inner.entries.clear();
+            inner.recency.clear();
+            inner.stats.entries = 0;
+        }
+    }
+
+    /// Snapshot of the cache counters
+    pub fn stats(&self) -> CacheStats {
+        let mut total = CacheStats {
+            contended: self.contended.load(Ordering::Relaxed),
+            ..CacheStats::default()
+        };
+        for shard in self.shards.iter() {
+            let stats = lock(shard).stats;
+            total.hits += stats.hits;
+            total.misses += stats.misses;
+            total.expirations += stats.expirations;
+            total.evictions += stats.evictions;
+            total.invalidations += stats.invalidations;
+            total.entries += stats.entries;
+        }
+        total
+    }
+
+    fn get_at(&self, key: &CacheKey, slot: u64, now: Instant) -> Option<Quote> {
+        let mut inner = self.try_lock(key)?;
+        let Some(entry) = inner.remove(key) else {
+            inner.stats.misses += 1;
+            return None;
+        };
+
+        let expired = now.saturating_duration_since(entry.inserted_at) > self.policy.max_age
+            || slot.saturating_sub(entry.slot) > self.policy.max_slot_lag;
+        if expired {
+            inner.stats.expirations += 1;
+            inner.stats.misses += 1;
+            inner.stats.entries = inner.entries.len();
+            return None;
+        }
+
+        let quote = entry.quote.clone();
+        let tick = inner.touch(*key);
+        inner.entries.insert(*key, Entry { tick, ..entry });
+        inner.stats.hits += 1;
+        Some(quote)
+    }
+
+    fn insert_at(&self, key: CacheKey, quote: Quote, slot: u64, now: Instant) {
+        if self.policy.capacity == 0 {
+            return;
+        }
+        let Some(mut inner) = self.try_lock(&key) else {
+            return;
+        };
+        inner.remove(&key);
+        while inner.entries.len() >= self.shard_capacity {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&oldest);
+            inner.stats.evictions += 1;
+        }
+
+        let tick = inner.touch(key);
+        inner.entries.insert(key, Entry { quote, inserted_at: now, slot, tick });
+        inner.stats.entries = inner.entries.len();
+    }
+
+    /// Shard holding `key`, or `None` if another caller holds it
+    fn try_lock(&self, key: &CacheKey) -> Option<MutexGuard<'_, Inner>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard = &self.shards[hasher.finish() as usize % self.shards.len()];
+        match shard.try_lock() {
+            Ok(inner) => Some(inner),
+            // Every update leaves the maps consistent, so a panic elsewhere
+            // while holding the lock does not invalidate the shard
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+fn lock(shard: &Mutex<Inner>) -> MutexGuard<'_, Inner> {
+    shard.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::adapter::tests;
+
+    fn quote(pool: Pubkey) -> Quote {
+        Quote { pool, ..tests::quote(Pubkey::new_unique(), Pubkey::new_unique()) }
+    }
+
+    fn key(amount: u64) -> CacheKey {
+        (Pubkey::new_from_array([1; 32]), Pubkey::new_from_array([2; 32]), amount, 100)
+    }
+
+    #[test]
+    fn test_expiry() {
+        let cache = QuoteCache::new(CachePolicy {
+            max_age: Duration::from_secs(5),
+            max_slot_lag: 10,
+            capacity: 8,
+        });
+        let start = Instant::now();
+
+        cache.insert_at(key(1), quote(Pubkey::new_unique()), 100, start);
+        assert!(cache.get_at(&key(1), 110, start + Duration::from_secs(5)).is_some());
+        assert!(cache.get_at(&key(1), 111, start).is_none());
+
+        cache.insert_at(key(1), quote(Pubkey::new_unique()), 100, start);
+        assert!(cache.get_at(&key(1), 100, start + Duration::from_secs(6)).is_none());
+        assert!(cache.get_at(&key(2), 100, start).is_none());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 3);
+        assert_eq!(stats.expirations, 2);
+        assert_eq!(stats.entries, 0);
+        assert_eq!(stats.hit_rate(), 0.25);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = QuoteCache::new(CachePolicy { capacity: 2, ..CachePolicy::default() });
+        cache.insert(key(1), quote(Pubkey::new_unique()), 0);
+        cache.insert(key(2), quote(Pubkey::new_unique()), 0);
+
+        // Using 1 makes 2 the least recently used
+        assert!(cache.get(&key(1), 0).is_some());
+        cache.insert(key(3), quote(Pubkey::new_unique()), 0);
+        assert!(cache.get(&key(2), 0).is_none());
+        assert!(cache.get(&key(1), 0).is_some());
+        assert!(cache.get(&key(3), 0).is_some());
+
+        // Replacing an entry does not evict
+        cache.insert(key(3), quote(Pubkey::new_unique()), 0);
+        assert!(cache.get(&key(1), 0).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.entries, 2);
+
+        let disabled = QuoteCache::new(CachePolicy { capacity: 0, ..CachePolicy::default() });
+        disabled.insert(key(1), quote(Pubkey::new_unique()), 0);
+        assert!(disabled.get(&key(1), 0).is_none());
+    }
+
+    #[test]
+    fn test_invalidate_pool() {
+        let cache = QuoteCache::new(CachePolicy::default());
+        let (pool, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        cache.insert(key(1), quote(pool), 0);
+        cache.insert(key(2), quote(pool), 0);
+        cache.insert(key(3), quote(other), 0);
+
+        cache.invalidate_pool(&pool);
+        assert!(cache.get(&key(1), 0).is_none());
+        assert!(cache.get(&key(3), 0).is_some());
+        assert_eq!(cache.stats().invalidations, 2);
+
+        let emptied = cache.emptied();
+        assert!(emptied.get(&key(3), 0).is_none());
+        assert_eq!(emptied.stats().invalidations, 3);
+        assert_eq!(emptied.stats().hits, 1);
+
+        cache.clear();
+        assert!(cache.get(&key(3), 0).is_none());
+        assert_eq!(cache.stats().invalidations, 3);
+    }
+
+    #[test]
+    fn test_contended_shard() {
+        let cache = QuoteCache::new(CachePolicy { capacity: 8, ..CachePolicy::default() });
+        cache.insert(key(1), quote(Pubkey::new_unique()), 0);
+
+        // A busy shard is skipped rather than waited on
+        {
+            let _busy = cache.shards[0].lock().unwrap();
+            assert!(cache.get(&key(1), 0).is_none());
+            cache.insert(key(2), quote(Pubkey::new_unique()), 0);
+        }
+        assert!(cache.get(&key(1), 0).is_some());
+        assert!(cache.get(&key(2), 0).is_none());
+
+        let stats = cache.stats();
+        assert_eq!(stats.contended, 2);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(cache.emptied().stats().contended, 2);
+
+        let large = QuoteCache::new(CachePolicy { capacity: 4096, ..CachePolicy::default() });
+        assert_eq!(large.shards.len(), MAX_SHARDS);
+        for amount in 0..100 {
+            large.insert(key(amount), quote(Pubkey::new_unique()), 0);
+        }
+        assert_eq!(large.stats().entries, 100);
+        assert!((0..100).all(|amount| large.get(&key(amount), 0).is_some()));
+    }
+}
@@ -0,0 +1,154 @@
+//! Compute budget and priority fees
+//!
+//! Swap transactions can carry `ComputeBudget` instructions setting how
+//! many compute units they may use and how many micro-lamports they pay
+//! per unit. Limits may come from a simulation of the swap, and prices
+//! from the fees recently paid to write the accounts the swap touches.
+
+use anchor_client::solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey,
+};
+
+/// Most compute units a transaction may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Headroom added to simulated compute usage, 10%
+pub const DEFAULT_COMPUTE_UNIT_MARGIN_BPS: u16 = 1_000;
+
+/// Most accounts `getRecentPrioritizationFees` accepts
+pub const MAX_FEE_ACCOUNTS: usize = 128;
+
+/// How many compute units a swap requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ComputeUnitLimit {
+    /// No limit instruction; the runtime allows 200k units per instruction
+    #[default]
+    Default,
+    /// Request exactly this many units
+    Fixed(u32),
+    /// Units a simulation of the swap consumed plus `margin_bps`
+    Simulated { margin_bps: u16 },
+}
+
+impl ComputeUnitLimit {
+    /// Units to request given what a simulation consumed, `None` for the
+    /// runtime default. Unsimulated swaps request the maximum.
+    pub fn units(&self, units_consumed: Option<u64>) -> Option<u32> {
+        match *self {
+            Self::Default => None,
+            Self::Fixed(units) => Some(units.min(MAX_COMPUTE_UNIT_LIMIT)),
+            Self::Simulated { margin_bps } => Some(match units_consumed {
+                Some(units) => {
+                    let limit = units as u128 * (10_000 + margin_bps as u128) / 10_000;
+                    limit.min(MAX_COMPUTE_UNIT_LIMIT as u128) as u32
+                }
+                None => MAX_COMPUTE_UNIT_LIMIT,
+            }),
+        }
+    }
+}
+
+/// What a swap pays per compute unit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PriorityFee {
+    /// No price instruction
+    #[default]
+    None,
+    /// Pay this many micro-lamports per unit
+    Fixed(u64),
+    /// Pay the `percentile` of recent fees for the accounts the swap
+    /// writes, at most `max_micro_lamports`
+    Estimated { percentile: u8, max_micro_lamports: u64 },
+}
+
+/// Compute budget instructions the engine prepends to swaps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComputeBudget {
+    /// Compute units requested
+    pub unit_limit: ComputeUnitLimit,
+    /// Price paid per unit
+    pub priority_fee: PriorityFee,
+}
+
+/// Instructions requesting `unit_limit` units at `micro_lamports` each,
+/// leaving out unset ones
+pub fn instructions(unit_limit: Option<u32>, micro_lamports: Option<u64>) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    if let Some(units) = unit_limit {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+    }
+    if let Some(micro_lamports) = micro_lamports {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(micro_lamports));
+    }
+    instructions
+}
+
+/// Accounts `instructions` write, which fee markets are keyed by
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts: Vec<Pubkey> = Vec::new();
+    for meta in instructions.iter().flat_map(|ix| &ix.accounts) {
+        if meta.is_writable && !accounts.contains(&meta.pubkey) {
+            accounts.push(meta.pubkey);
+        }
+    }
+    accounts.truncate(MAX_FEE_ACCOUNTS);
+    accounts
+}
+
+/// `percentile` of `fees` by nearest rank, zero without fees
+pub fn fee_percentile(fees: &[u64], percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    let mut fees = fees.to_vec();
+    fees.sort_unstable();
+    let rank = (fees.len() * percentile.min(100) as usize).div_ceil(100);
+    fees[rank.saturating_sub(1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_client::solana_sdk::{compute_budget, instruction::AccountMeta};
+
+    #[test]
+    fn test_unit_limit() {
+        assert_eq!(ComputeUnitLimit::Default.units(Some(50_000)), None);
+        assert_eq!(ComputeUnitLimit::Fixed(300_000).units(None), Some(300_000));
+        assert_eq!(ComputeUnitLimit::Fixed(u32::MAX).units(None), Some(MAX_COMPUTE_UNIT_LIMIT));
+
+        let simulated = ComputeUnitLimit::Simulated { margin_bps: DEFAULT_COMPUTE_UNIT_MARGIN_BPS };
+        assert_eq!(simulated.units(Some(50_000)), Some(55_000));
+        assert_eq!(simulated.units(None), Some(MAX_COMPUTE_UNIT_LIMIT));
+        assert_eq!(simulated.units(Some(1_390_000)), Some(MAX_COMPUTE_UNIT_LIMIT));
+    }
+
+    #[test]
+    fn test_instructions() {
+        assert!(instructions(None, None).is_empty());
+
+        let ixs = instructions(Some(55_000), Some(1_000));
+        assert_eq!(ixs.len(), 2);
+        assert!(ixs.iter().all(|ix| ix.program_id == compute_budget::ID));
+        assert_eq!(ixs[0], ComputeBudgetInstruction::set_compute_unit_limit(55_000));
+        assert_eq!(ixs[1], ComputeBudgetInstruction::set_compute_unit_price(1_000));
+    }
+
+    #[test]
+    fn test_fee_estimate() {
+        let (pool, vault, user) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = |accounts| Instruction::new_with_bytes(Pubkey::new_unique(), &[], accounts);
+        let ixs = [
+            ix(vec![AccountMeta::new(pool, false), AccountMeta::new_readonly(user, true)]),
+            ix(vec![AccountMeta::new(vault, false), AccountMeta::new(pool, false)]),
+        ];
+        assert_eq!(writable_accounts(&ixs), vec![pool, vault]);
+
+        let fees = [0, 0, 500, 100, 10_000, 0, 2_000, 300, 0, 50];
+        assert_eq!(fee_percentile(&fees, 50), 50);
+        assert_eq!(fee_percentile(&fees, 75), 500);
+        assert_eq!(fee_percentile(&fees, 100), 10_000);
+        assert_eq!(fee_percentile(&fees, 0), 0);
+        assert_eq!(fee_percentile(&[], 75), 0);
+    }
+}
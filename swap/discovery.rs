@@ -0,0 +1,483 @@
+//! Pool discovery
+//!
+//! Finds Raydium and Orca pools with `getProgramAccounts` filters and
+//! decodes them so the DEX clients can index them by mint pair, along
+//! with the mints they trade.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::Result;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use std::collections::HashMap;
+use tracing::debug;
+
+use super::{
+    mint::MintInfo,
+    orca::{self, WhirlpoolState},
+    raydium::{self, AmmInfo, PoolAccounts, PoolState},
+};
+
+/// Maximum number of addresses per `getMultipleAccounts` request
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Source of on-chain account data.
+///
+/// Implemented for the nonblocking [`RpcClient`]; tests use a local
+/// stand-in serving fixture accounts.
+#[async_trait::async_trait]
+pub trait AccountProvider: Send + Sync {
+    /// Fetch all accounts owned by `program_id` that match `filters`
+    async fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<(Pubkey, Vec<u8>)>>;
+
+    /// Fetch account data for each address, `None` if it does not exist
+    async fn get_multiple_accounts(
+        &self,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<Option<Vec<u8>>>>;
+
+    /// Fetch the owning program and data of each address, `None` if it
+    /// does not exist
+    async fn get_multiple_owned_accounts(
+        &self,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<Option<(Pubkey, Vec<u8>)>>>;
+
+    /// Slot the node has processed up to
+    async fn get_slot(&self) -> Result<u64>;
+
+    /// Epoch the node is in
+    async fn get_epoch(&self) -> Result<u64>;
+}
+
+#[async_trait::async_trait]
+impl AccountProvider for RpcClient {
+    async fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let config = program_accounts_config(filters);
+        let accounts = self.get_program_accounts_with_config(program_id, config).await?;
+        Ok(accounts
+            .into_iter()
+            .map(|(address, account)| (address, account.data))
+            .collect())
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        let accounts = RpcClient::get_multiple_accounts(self, addresses).await?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| account.map(|account| account.data))
+            .collect())
+    }
+
+    async fn get_multiple_owned_accounts(
+        &self,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<Option<(Pubkey, Vec<u8>)>>> {
+        let accounts = RpcClient::get_multiple_accounts(self, addresses).await?;
+        Ok(accounts
+            .into_iter()
+            .map(|account| account.map(|account| (account.owner, account.data)))
+            .collect())
+    }
+
+    async fn get_slot(&self) -> Result<u64> {
+        Ok(RpcClient::get_slot(self).await?)
+    }
+
+    async fn get_epoch(&self) -> Result<u64> {
+        Ok(RpcClient::get_epoch_info(self).await?.epoch)
+    }
+}
+
+/// `getProgramAccounts` config for `filters`.
+///
+/// Requests base64 data: the node defaults to base58, which it refuses
+/// for accounts over 128 bytes such as `AmmInfo` and `Whirlpool`.
+fn program_accounts_config(filters: Vec<RpcFilterType>) -> RpcProgramAccountsConfig {
+    RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    }
+}
+
+/// Which pools to discover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolFilter {
+    /// Every pool owned by the program
+    All,
+    /// Pools trading the given mint pair, in either order
+    Pair(Pubkey, Pubkey),
+}
+
+/// Discover Raydium AMM v4 pools
+pub async fn discover_raydium_pools(
+    rpc: &dyn AccountProvider,
+    program_id: &Pubkey,
+    filter: &PoolFilter,
+) -> Result<Vec<PoolState>> {
+    let size = RpcFilterType::DataSize(raydium::AMM_INFO_LEN as u64);
+    let queries = match *filter {
+        PoolFilter::All => vec![vec![size]],
+        // AMM v4 does not order its mints, so query both orientations
+        PoolFilter::Pair(a, b) => vec![
+            vec![
+                size.clone(),
+                memcmp(raydium::AMM_COIN_MINT_OFFSET, &a),
+                memcmp(raydium::AMM_PC_MINT_OFFSET, &b),
+            ],
+            vec![
+                size,
+                memcmp(raydium::AMM_COIN_MINT_OFFSET, &b),
+                memcmp(raydium::AMM_PC_MINT_OFFSET, &a),
+            ],
+        ],
+    };
+
+    let mut amms = Vec::new();
+    for filters in queries {
+        for (address, data) in rpc.get_program_accounts(program_id, filters).await? {
+            match AmmInfo::decode(&data) {
+                Ok(info) => amms.push((address, data, info)),
+                Err(e) => debug!("Skipping Raydium pool {}: {}", address, e),
+            }
+        }
+    }
+
+    // Reserves and market accounts live outside the AMM account
+    let related: Vec<Pubkey> = amms
+        .iter()
+        .flat_map(|(_, _, info)| [info.coin_vault, info.pc_vault, info.market])
+        .collect();
+    let related = get_multiple_accounts_chunked(rpc, &related).await?;
+
+    let mut pools = Vec::with_capacity(amms.len());
+    for ((address, amm, _), related) in amms.iter().zip(related.chunks(3)) {
+        let (Some(coin_vault), Some(pc_vault), Some(market)) =
+            (&related[0], &related[1], &related[2])
+        else {
+            debug!("Skipping Raydium pool {}: missing vault or market account", address);
+            continue;
+        };
+
+        let accounts = PoolAccounts {
+            amm,
+            coin_vault,
+            pc_vault,
+            market,
+        };
+        match PoolState::decode(*address, program_id, &accounts) {
+            Ok(pool) => pools.push(pool),
+            Err(e) => debug!("Skipping Raydium pool {}: {}", address, e),
+        }
+    }
+
+    Ok(pools)
+}
+
+/// Discover Orca whirlpools
+pub async fn discover_whirlpools(
+    rpc: &dyn AccountProvider,
+    program_id: &Pubkey,
+    filter: &PoolFilter,
+) -> Result<Vec<WhirlpoolState>> {
+    let mut filters = vec![RpcFilterType::DataSize(orca::WHIRLPOOL_LEN as u64)];
+    if let PoolFilter::Pair(a, b) = *filter {
+        // Whirlpools always store their mints in sorted order
+        let (mint_a, mint_b) = if a < b { (a, b) } else { (b, a) };
+        filters.push(memcmp(orca::WHIRLPOOL_TOKEN_MINT_A_OFFSET, &mint_a));
+        filters.push(memcmp(orca::WHIRLPOOL_TOKEN_MINT_B_OFFSET, &mint_b));
+    }
+
+    let accounts = rpc.get_program_accounts(program_id, filters).await?;
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(address, data)| match WhirlpoolState::decode(address, &data) {
+            Ok(pool) => Some(pool),
+            Err(e) => {
+                debug!("Skipping whirlpool {}: {}", address, e);
+                None
+            }
+        })
+        .collect())
+}
+
+fn memcmp(offset: usize, key: &Pubkey) -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(offset, key.as_ref()))
+}
+
+/// Fetch accounts in batches the RPC accepts
+pub(crate) async fn get_multiple_accounts_chunked(
+    rpc: &dyn AccountProvider,
+    addresses: &[Pubkey],
+) -> Result<Vec<Option<Vec<u8>>>> {
+    let mut accounts = Vec::with_capacity(addresses.len());
+    for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        accounts.extend(rpc.get_multiple_accounts(chunk).await?);
+    }
+    Ok(accounts)
+}
+
+/// Decode `mints` with the transfer fees in effect this epoch, leaving
+/// out accounts that do not exist or are not mints
+pub async fn get_mints(rpc: &dyn AccountProvider, mints: &[Pubkey]) -> Result<HashMap<Pubkey, MintInfo>> {
+    let epoch = rpc.get_epoch().await?;
+    let mut decoded = HashMap::with_capacity(mints.len());
+    for chunk in mints.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc.get_multiple_owned_accounts(chunk).await?;
+        for (mint, account) in chunk.iter().zip(accounts) {
+            let Some((owner, data)) = account else {
+                debug!("Skipping mint {}: account not found", mint);
+                continue;
+            };
+            match MintInfo::decode(&owner, &data, epoch) {
+                Ok(info) => {
+                    decoded.insert(*mint, info);
+                }
+                Err(e) => debug!("Skipping mint {}: {}", mint, e),
+            }
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use crate::swap::{
+        layout::load_fixture, mint::tests::fee_mint, OrcaClient, RaydiumClient, TransferFee, DEFAULT_SLIPPAGE_BPS,
+        TOKEN_2022_PROGRAM_ID,
+    };
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const RAY: &str = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R";
+
+    /// Slots per epoch on mainnet
+    const SLOTS_PER_EPOCH: u64 = 432_000;
+
+    /// Local RPC stand-in serving fixture accounts
+    pub(crate) struct FixtureRpc {
+        /// (address, owner, data)
+        accounts: Vec<(Pubkey, Pubkey, Vec<u8>)>,
+        /// Slot reported to callers
+        slot: AtomicU64,
+    }
+
+    impl FixtureRpc {
+        pub(crate) fn new() -> Self {
+            let raydium = *RaydiumClient::new().unwrap().program_id();
+            let orca = *OrcaClient::new().unwrap().program_id();
+            let openbook: Pubkey = "srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX".parse().unwrap();
+            let token = anchor_spl::token::ID;
+
+            let fixtures = [
+                (raydium, include_str!("../tests/fixtures/raydium/sol_usdc_amm.json")),
+                (token, include_str!("../tests/fixtures/raydium/sol_usdc_coin_vault.json")),
+                (token, include_str!("../tests/fixtures/raydium/sol_usdc_pc_vault.json")),
+                (openbook, include_str!("../tests/fixtures/raydium/sol_usdc_market.json")),
+                (raydium, include_str!("../tests/fixtures/raydium/ray_usdc_amm.json")),
+                (token, include_str!("../tests/fixtures/raydium/ray_usdc_coin_vault.json")),
+                (token, include_str!("../tests/fixtures/raydium/ray_usdc_pc_vault.json")),
+                (openbook, include_str!("../tests/fixtures/raydium/ray_usdc_market.json")),
+                (orca, include_str!("../tests/fixtures/orca/sol_usdc_whirlpool.json")),
+                (orca, include_str!("../tests/fixtures/orca/sol_usdc_tick_array_m28160.json")),
+                (orca, include_str!("../tests/fixtures/orca/sol_usdc_tick_array_m22528.json")),
+                (orca, include_str!("../tests/fixtures/orca/sol_usdc_tick_array_m16896.json")),
+            ];
+
+            Self {
+                accounts: fixtures
+                    .iter()
+                    .map(|(owner, json)| {
+                        let (address, data) = load_fixture(json);
+                        (address, *owner, data)
+                    })
+                    .collect(),
+                slot: AtomicU64::new(1),
+            }
+        }
+
+        /// Serve `data` at `address`, owned by `owner`, replacing any
+        /// account already there
+        pub(crate) fn insert_account(&mut self, address: Pubkey, owner: Pubkey, data: Vec<u8>) {
+            self.accounts.retain(|(a, _, _)| *a != address);
+            self.accounts.push((address, owner, data));
+        }
+
+        /// Advance the reported slot to `slot`
+        pub(crate) fn set_slot(&self, slot: u64) {
+            self.slot.store(slot, Ordering::Relaxed);
+        }
+    }
+
+    fn matches(filter: &RpcFilterType, data: &[u8]) -> bool {
+        match filter {
+            RpcFilterType::DataSize(size) => data.len() as u64 == *size,
+            RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+            _ => true,
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AccountProvider for FixtureRpc {
+        async fn get_program_accounts(
+            &self,
+            program_id: &Pubkey,
+            filters: Vec<RpcFilterType>,
+        ) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+            Ok(self
+                .accounts
+                .iter()
+                .filter(|(_, owner, data)| {
+                    owner == program_id && filters.iter().all(|f| matches(f, data))
+                })
+                .map(|(address, _, data)| (*address, data.clone()))
+                .collect())
+        }
+
+        async fn get_multiple_accounts(
+            &self,
+            addresses: &[Pubkey],
+        ) -> Result<Vec<Option<Vec<u8>>>> {
+            Ok(addresses
+                .iter()
+                .map(|address| {
+                    self.accounts
+                        .iter()
+                        .find(|(a, _, _)| a == address)
+                        .map(|(_, _, data)| data.clone())
+                })
+                .collect())
+        }
+
+        async fn get_multiple_owned_accounts(
+            &self,
+            addresses: &[Pubkey],
+        ) -> Result<Vec<Option<(Pubkey, Vec<u8>)>>> {
+            Ok(addresses
+                .iter()
+                .map(|address| {
+                    self.accounts
+                        .iter()
+                        .find(|(a, _, _)| a == address)
+                        .map(|(_, owner, data)| (*owner, data.clone()))
+                })
+                .collect())
+        }
+
+        async fn get_slot(&self) -> Result<u64> {
+            Ok(self.slot.load(Ordering::Relaxed))
+        }
+
+        async fn get_epoch(&self) -> Result<u64> {
+            Ok(self.slot.load(Ordering::Relaxed) / SLOTS_PER_EPOCH)
+        }
+    }
+
+    #[test]
+    fn test_program_accounts_config() {
+        let filters = vec![RpcFilterType::DataSize(raydium::AMM_INFO_LEN as u64)];
+        let config = program_accounts_config(filters.clone());
+
+        assert_eq!(config.filters, Some(filters));
+        assert_eq!(config.account_config.encoding, Some(UiAccountEncoding::Base64));
+        assert_eq!(config.account_config.data_slice, None);
+    }
+
+    #[tokio::test]
+    async fn test_discover_all_raydium_pools() {
+        let rpc = FixtureRpc::new();
+        let program_id = *RaydiumClient::new().unwrap().program_id();
+
+        let pools = discover_raydium_pools(&rpc, &program_id, &PoolFilter::All)
+            .await
+            .unwrap();
+        assert_eq!(pools.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_discover_raydium_pair() {
+        let rpc = FixtureRpc::new();
+        let mut client = RaydiumClient::new().unwrap();
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+
+        // Mint order in the filter does not matter
+        let found = client
+            .discover_pools(&rpc, &PoolFilter::Pair(usdc, sol))
+            .await
+            .unwrap();
+        assert_eq!(found, 1);
+        assert!(client.get_quote(&sol, &usdc, 100_000, DEFAULT_SLIPPAGE_BPS).await.is_ok());
+
+        let ray: Pubkey = RAY.parse().unwrap();
+        assert!(client.get_quote(&ray, &usdc, 100_000, DEFAULT_SLIPPAGE_BPS).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_discover_whirlpools() {
+        let rpc = FixtureRpc::new();
+        let mut client = OrcaClient::new().unwrap();
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let ray: Pubkey = RAY.parse().unwrap();
+
+        let found = client
+            .discover_whirlpools(&rpc, &PoolFilter::Pair(usdc, sol))
+            .await
+            .unwrap();
+        assert_eq!(found, 1);
+        assert_eq!(client.whirlpools().count(), 1);
+
+        let found = client
+            .discover_whirlpools(&rpc, &PoolFilter::Pair(ray, usdc))
+            .await
+            .unwrap();
+        assert_eq!(found, 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_mints() {
+        let mut rpc = FixtureRpc::new();
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let older = TransferFee { epoch: 0, maximum_fee: 1_000, basis_points: 50 };
+        let newer = TransferFee { epoch: 2, maximum_fee: 1_000, basis_points: 100 };
+        rpc.insert_account(sol, anchor_spl::token::ID, vec![0; 82]);
+        rpc.insert_account(usdc, TOKEN_2022_PROGRAM_ID, fee_mint(older, newer));
+
+        let mints = get_mints(&rpc, &[sol, usdc, Pubkey::new_unique()]).await.unwrap();
+        assert_eq!(mints.len(), 2);
+        assert_eq!(mints[&sol], MintInfo::default());
+        assert_eq!(mints[&usdc].transfer_fee, Some(older));
+
+        rpc.set_slot(2 * SLOTS_PER_EPOCH);
+        let mints = get_mints(&rpc, &[usdc]).await.unwrap();
+        assert_eq!(mints[&usdc].transfer_fee, Some(newer));
+
+        // Discovery loads the mints of the pools it finds
+        let mut client = OrcaClient::new().unwrap();
+        client.discover_whirlpools(&rpc, &PoolFilter::Pair(sol, usdc)).await.unwrap();
+        assert!(client.mint(&usdc).is_token_2022());
+        assert!(!client.mint(&sol).is_token_2022());
+    }
+}
@@ -0,0 +1,505 @@
+//! Swap execution
+//!
+//! Signs prepared versioned swap transactions with a fresh blockhash,
+//! submits them and polls until they reach the commitment the RPC client
+//! is configured with, rebroadcasting them until they land or their
+//! blockhash expires.
+//! An expired transaction can never execute, so only then may the swap be
+//! signed and sent again. A transaction given up on before that may still
+//! land, and is reported as [`OutcomeUnknown`].
+
+use anchor_client::solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    transaction::{TransactionError, VersionedTransaction},
+};
+use anyhow::Result;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig},
+};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+
+/// Time within which an expired swap may be re-quoted and sent again,
+/// matching the default swap timeout
+pub const DEFAULT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Time a pending swap is watched for its blockhash to expire. The
+/// blockhash is valid for 150 blocks and the finalized chain proving it
+/// expired trails the tip by about 32 slots, some 73s at 400ms slots;
+/// the rest allows for skipped slots.
+pub const EXPIRY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Delay between confirmation checks, about one slot
+pub const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Delay between rebroadcasts of a pending transaction
+pub const REBROADCAST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Where a submitted transaction stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Not yet at the sender's commitment
+    Pending,
+    /// Executed successfully in `slot`
+    Confirmed { slot: u64 },
+    /// Executed in `slot` but failed
+    Failed { slot: u64, error: TransactionError },
+}
+
+/// Outcome of simulating a transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Simulation {
+    /// Why the transaction failed, `None` if it succeeded
+    pub error: Option<TransactionError>,
+    /// Program log messages
+    pub logs: Vec<String>,
+    /// Compute units the transaction consumed, if reported
+    pub units_consumed: Option<u64>,
+    /// Data of the requested accounts before the transaction
+    pub pre_accounts: Vec<Option<Vec<u8>>>,
+    /// Data of the requested accounts after the transaction, empty if it
+    /// failed
+    pub post_accounts: Vec<Option<Vec<u8>>>,
+}
+
+/// A submitted swap whose blockhash expired before it landed. It can no
+/// longer execute, so sending the swap again cannot land it twice.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Swap {signature} expired after block height {last_valid_block_height} without landing")]
+pub struct BlockhashExpired {
+    /// Signature of the expired transaction
+    pub signature: Signature,
+    /// Last block height its blockhash was valid at
+    pub last_valid_block_height: u64,
+}
+
+/// A submitted swap still pending when the wait for it ran out, before its
+/// blockhash was seen to expire. It may yet land, so the swap must not be
+/// sent again until `last_valid_block_height` is finalized without it.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Swap {signature} still pending; it may land until block height {last_valid_block_height} is finalized")]
+pub struct OutcomeUnknown {
+    /// Signature of the pending transaction
+    pub signature: Signature,
+    /// Last block height its blockhash is valid at
+    pub last_valid_block_height: u64,
+}
+
+/// Signature and slot of a confirmed swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapReceipt {
+    /// Transaction signature
+    pub signature: Signature,
+    /// Slot the transaction executed in
+    pub slot: u64,
+}
+
+/// Submits transactions and tracks their confirmation.
+///
+/// Implemented for the nonblocking [`RpcClient`]; tests use a local
+/// stand-in.
+#[async_trait::async_trait]
+pub trait TransactionSender: Send + Sync {
+    /// Commitment transactions must reach to count as confirmed
+    fn commitment(&self) -> CommitmentConfig;
+
+    /// Blockhash to sign new transactions with and the last block height
+    /// transactions signed with it can land at
+    async fn get_latest_blockhash(&self) -> Result<(Hash, u64)>;
+
+    /// Block height reached at `commitment`
+    async fn get_block_height(&self, commitment: CommitmentConfig) -> Result<u64>;
+
+    /// Simulate an unsigned transaction against the latest state,
+    /// returning the data of `accounts` around it
+    async fn simulate_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+        accounts: &[Pubkey],
+    ) -> Result<Simulation>;
+
+    /// Priority fees, in micro-lamports per compute unit, that landed
+    /// transactions writing `accounts` paid in recent slots
+    async fn get_recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>>;
+
+    /// Submit a signed transaction
+    async fn send_transaction(&self, transaction: &VersionedTransaction) -> Result<Signature>;
+
+    /// Status of a submitted transaction at [`Self::commitment`]
+    async fn get_signature_status(&self, signature: &Signature) -> Result<SignatureStatus>;
+}
+
+#[async_trait::async_trait]
+impl TransactionSender for RpcClient {
+    fn commitment(&self) -> CommitmentConfig {
+        RpcClient::commitment(self)
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<(Hash, u64)> {
+        Ok(self.get_latest_blockhash_with_commitment(RpcClient::commitment(self)).await?)
+    }
+
+    async fn get_block_height(&self, commitment: CommitmentConfig) -> Result<u64> {
+        Ok(self.get_block_height_with_commitment(commitment).await?)
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+        accounts: &[Pubkey],
+    ) -> Result<Simulation> {
+        // Simulation only reports state afterwards; the state before is
+        // read separately and may be a slot older
+        let pre_accounts = RpcClient::get_multiple_accounts(self, accounts)
+            .await?
+            .into_iter()
+            .map(|account| account.map(|account| account.data))
+            .collect();
+
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(RpcClient::commitment(self)),
+            accounts: Some(RpcSimulateTransactionAccountsConfig {
+                encoding: None,
+                addresses: accounts.iter().map(|a| a.to_string()).collect(),
+            }),
+            ..RpcSimulateTransactionConfig::default()
+        };
+        let result = self.simulate_transaction_with_config(transaction, config).await?.value;
+        Ok(Simulation {
+            error: result.err,
+            logs: result.logs.unwrap_or_default(),
+            units_consumed: result.units_consumed,
+            pre_accounts,
+            post_accounts: result
+                .accounts
+                .unwrap_or_default()
+                .into_iter()
+                .map(|account| account.and_then(|account| account.data.decode()))
+                .collect(),
+        })
+    }
+
+    async fn get_recent_prioritization_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>> {
+        let fees = RpcClient::get_recent_prioritization_fees(self, accounts).await?;
+        Ok(fees.into_iter().map(|fee| fee.prioritization_fee).collect())
+    }
+
+    async fn send_transaction(&self, transaction: &VersionedTransaction) -> Result<Signature> {
+        Ok(RpcClient::send_transaction(self, transaction).await?)
+    }
+
+    async fn get_signature_status(&self, signature: &Signature) -> Result<SignatureStatus> {
+        let statuses = self.get_signature_statuses(&[*signature]).await?.value;
+        let status = match statuses.into_iter().next().flatten() {
+            Some(status) if status.satisfies_commitment(RpcClient::commitment(self)) => status,
+            _ => return Ok(SignatureStatus::Pending),
+        };
+        Ok(match status.err {
+            Some(error) => SignatureStatus::Failed { slot: status.slot, error },
+            None => SignatureStatus::Confirmed { slot: status.slot },
+        })
+    }
+}
+
+/// Sign `transaction` as `wallet` with a fresh blockhash, submit it and
+/// wait up to `timeout` for it to confirm, resending it every
+/// `rebroadcast_interval`.
+///
+/// Fails with [`BlockhashExpired`] once the finalized chain passes the
+/// blockhash's last valid block height without the transaction landing,
+/// whatever commitment `rpc` confirms at, and with [`OutcomeUnknown`] if
+/// `timeout` runs out first. A `timeout` of at least [`EXPIRY_TIMEOUT`]
+/// leaves time for the expiry to show.
+pub async fn send_and_confirm(
+    rpc: &dyn TransactionSender,
+    transaction: VersionedTransaction,
+    wallet: &Keypair,
+    timeout: Duration,
+    poll_interval: Duration,
+    rebroadcast_interval: Duration,
+) -> Result<SwapReceipt> {
+    let deadline = Instant::now() + timeout;
+    let (blockhash, last_valid_block_height) = rpc.get_latest_blockhash().await?;
+    let mut message = transaction.message;
+    message.set_recent_blockhash(blockhash);
+    let transaction = VersionedTransaction::try_new(message, &[wallet])?;
+    let signature = rpc.send_transaction(&transaction).await?;
+    tracing::debug!("Sent swap {}", signature);
+
+    let mut sent_at = Instant::now();
+    loop {
+        // Only a finalized height past the limit rules out every fork the
+        // transaction could still land on; below `finalized`, another fork
+        // may pass the limit while the transaction sits in a lower block.
+        // Read the height first: a status read after it covers every block
+        // the transaction could have landed in.
+        let finalized = rpc.get_block_height(CommitmentConfig::finalized()).await?;
+        let expired = finalized > last_valid_block_height;
+        match rpc.get_signature_status(&signature).await? {
+            SignatureStatus::Confirmed { slot } => return Ok(SwapReceipt { signature, slot }),
+            SignatureStatus::Failed { slot, error } => {
+                anyhow::bail!("Swap {} failed in slot {}: {}", signature, slot, error)
+            }
+            SignatureStatus::Pending if expired => {
+                return Err(BlockhashExpired { signature, last_valid_block_height }.into());
+            }
+            SignatureStatus::Pending => {}
+        }
+        if Instant::now() + poll_interval > deadline {
+            return Err(OutcomeUnknown { signature, last_valid_block_height }.into());
+        }
+        tokio::time::sleep(poll_interval).await;
+
+        if sent_at.elapsed() >= rebroadcast_interval {
+            // The same signed bytes, so at most one copy executes
+            if let Err(e) = rpc.send_transaction(&transaction).await {
+                tracing::debug!("Rebroadcast of swap {} failed: {}", signature, e);
+            }
+            sent_at = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use anchor_client::solana_sdk::{
+        instruction::InstructionError, signature::Signer, system_instruction, transaction::Transaction,
+    };
+    use std::{collections::VecDeque, sync::Mutex};
+
+    /// Finalized block height the stand-in starts at
+    pub(crate) const BLOCK_HEIGHT: u64 = 1_000;
+
+    /// Blocks the tip a fresh blockhash comes from runs ahead of
+    /// `finalized`
+    pub(crate) const FINALIZATION_LAG: u64 = 32;
+
+    /// Blocks a fresh blockhash stays valid for
+    const BLOCKHASH_VALIDITY: u64 = 150;
+
+    /// Blocks the stand-in's fork below `finalized` runs ahead of it, past
+    /// the last valid height of every blockhash it hands out
+    const FORK_LEAD: u64 = 200;
+
+    /// Local RPC stand-in replaying a scripted simulation and
+    /// confirmation statuses
+    pub(crate) struct MockSender {
+        /// Commitment transactions confirm at
+        pub(crate) commitment: CommitmentConfig,
+        /// Blockhash handed out once `expired_blockhashes` run out, valid
+        /// throughout
+        pub(crate) blockhash: Hash,
+        /// Blockhashes handed out first, each already expired
+        pub(crate) expired_blockhashes: Mutex<VecDeque<Hash>>,
+        /// Time per finalized block, `None` for a chain that never advances
+        pub(crate) block_time: Option<Duration>,
+        /// When the chain was at `BLOCK_HEIGHT`
+        started: Instant,
+        /// Result of every simulation
+        pub(crate) simulation: Simulation,
+        /// Recent priority fees of every account set
+        pub(crate) prioritization_fees: Vec<u64>,
+        /// Statuses returned by successive polls, the last one repeating
+        statuses: Mutex<VecDeque<SignatureStatus>>,
+        /// Transactions submitted so far
+        pub(crate) sent: Mutex<Vec<VersionedTransaction>>,
+    }
+
+    impl MockSender {
+        pub(crate) fn new(statuses: Vec<SignatureStatus>) -> Self {
+            Self {
+                commitment: CommitmentConfig::confirmed(),
+                blockhash: Hash::new_unique(),
+                expired_blockhashes: Mutex::new(VecDeque::new()),
+                block_time: None,
+                started: Instant::now(),
+                simulation: Simulation::default(),
+                prioritization_fees: Vec::new(),
+                statuses: Mutex::new(statuses.into()),
+                sent: Mutex::new(Vec::new()),
+            }
+        }
+
+        /// Sender confirming every transaction in `slot` on the first poll
+        pub(crate) fn confirming(slot: u64) -> Self {
+            Self::new(vec![SignatureStatus::Confirmed { slot }])
+        }
+
+        fn finalized_height(&self) -> u64 {
+            let elapsed = self.started.elapsed().as_nanos();
+            BLOCK_HEIGHT + self.block_time.map_or(0, |t| (elapsed / t.as_nanos()) as u64)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TransactionSender for MockSender {
+        fn commitment(&self) -> CommitmentConfig {
+            self.commitment
+        }
+
+        async fn get_latest_blockhash(&self) -> Result<(Hash, u64)> {
+            let finalized = self.finalized_height();
+            match self.expired_blockhashes.lock().unwrap().pop_front() {
+                Some(expired) => Ok((expired, finalized - 1)),
+                None => Ok((self.blockhash, finalized + FINALIZATION_LAG + BLOCKHASH_VALIDITY)),
+            }
+        }
+
+        async fn get_block_height(&self, commitment: CommitmentConfig) -> Result<u64> {
+            if commitment.is_finalized() {
+                Ok(self.finalized_height())
+            } else {
+                Ok(self.finalized_height() + FORK_LEAD)
+            }
+        }
+
+        async fn simulate_transaction(
+            &self,
+            _transaction: &VersionedTransaction,
+            _accounts: &[Pubkey],
+        ) -> Result<Simulation> {
+            Ok(self.simulation.clone())
+        }
+
+        async fn get_recent_prioritization_fees(&self, _accounts: &[Pubkey]) -> Result<Vec<u64>> {
+            Ok(self.prioritization_fees.clone())
+        }
+
+        async fn send_transaction(&self, transaction: &VersionedTransaction) -> Result<Signature> {
+            transaction.verify_and_hash_message()?;
+            self.sent.lock().unwrap().push(transaction.clone());
+            Ok(transaction.signatures[0])
+        }
+
+        async fn get_signature_status(&self, _signature: &Signature) -> Result<SignatureStatus> {
+            let mut statuses = self.statuses.lock().unwrap();
+            match statuses.len() {
+                0 => Ok(SignatureStatus::Pending),
+                1 => Ok(statuses[0].clone()),
+                _ => Ok(statuses.pop_front().unwrap()),
+            }
+        }
+    }
+
+    fn transfer(wallet: &Keypair) -> VersionedTransaction {
+        let ix = system_instruction::transfer(&wallet.pubkey(), &Keypair::new().pubkey(), 1);
+        Transaction::new_with_payer(&[ix], Some(&wallet.pubkey())).into()
+    }
+
+    async fn execute(rpc: &MockSender, wallet: &Keypair) -> Result<SwapReceipt> {
+        let poll = Duration::from_millis(1);
+        send_and_confirm(rpc, transfer(wallet), wallet, Duration::from_millis(50), poll, REBROADCAST_INTERVAL).await
+    }
+
+    #[tokio::test]
+    async fn test_send_and_confirm() {
+        let wallet = Keypair::new();
+        let rpc = MockSender::new(vec![
+            SignatureStatus::Pending,
+            SignatureStatus::Pending,
+            SignatureStatus::Confirmed { slot: 42 },
+        ]);
+
+        let receipt = execute(&rpc, &wallet).await.unwrap();
+        assert_eq!(receipt.slot, 42);
+
+        let sent = rpc.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].signatures[0], receipt.signature);
+        assert_eq!(*sent[0].message.recent_blockhash(), rpc.blockhash);
+        assert_eq!(sent[0].message.static_account_keys()[0], wallet.pubkey());
+    }
+
+    #[tokio::test]
+    async fn test_confirmation_failures() {
+        let wallet = Keypair::new();
+
+        let error = TransactionError::InstructionError(0, InstructionError::Custom(30));
+        let rpc = MockSender::new(vec![SignatureStatus::Failed { slot: 7, error }]);
+        let err = execute(&rpc, &wallet).await.unwrap_err();
+        assert!(err.to_string().contains("failed in slot 7"));
+
+        // Still pending when time runs out, so it may yet land
+        let rpc = MockSender::new(Vec::new());
+        let err = execute(&rpc, &wallet).await.unwrap_err();
+        let err = err.downcast_ref::<OutcomeUnknown>().unwrap();
+        assert_eq!(err.signature, rpc.sent.lock().unwrap()[0].signatures[0]);
+        assert_eq!(err.last_valid_block_height, BLOCK_HEIGHT + FINALIZATION_LAG + BLOCKHASH_VALIDITY);
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast() {
+        let wallet = Keypair::new();
+        let mut statuses = vec![SignatureStatus::Pending; 20];
+        statuses.push(SignatureStatus::Confirmed { slot: 3 });
+        let rpc = MockSender::new(statuses);
+
+        let poll = Duration::from_millis(1);
+        let receipt = send_and_confirm(&rpc, transfer(&wallet), &wallet, Duration::from_secs(1), poll, poll * 5)
+            .await
+            .unwrap();
+        let sent = rpc.sent.lock().unwrap();
+        assert!(sent.len() > 1);
+        assert!(sent.iter().all(|tx| *tx == sent[0] && tx.signatures[0] == receipt.signature));
+    }
+
+    #[tokio::test]
+    async fn test_blockhash_expiry() {
+        let wallet = Keypair::new();
+        let expired = Hash::new_unique();
+
+        let rpc = MockSender::new(vec![SignatureStatus::Pending]);
+        rpc.expired_blockhashes.lock().unwrap().push_back(expired);
+        let err = execute(&rpc, &wallet).await.unwrap_err();
+        let err = err.downcast_ref::<BlockhashExpired>().unwrap();
+        assert_eq!(err.last_valid_block_height, BLOCK_HEIGHT - 1);
+        {
+            let sent = rpc.sent.lock().unwrap();
+            assert_eq!(err.signature, sent[0].signatures[0]);
+            assert_eq!(*sent[0].message.recent_blockhash(), expired);
+        }
+
+        // Landing before the blockhash expired still counts
+        let rpc = MockSender::confirming(5);
+        rpc.expired_blockhashes.lock().unwrap().push_back(expired);
+        assert_eq!(execute(&rpc, &wallet).await.unwrap().slot, 5);
+    }
+
+    #[tokio::test]
+    async fn test_expiry_on_unfinalized_fork() {
+        let wallet = Keypair::new();
+        let mut statuses = vec![SignatureStatus::Pending; 5];
+        statuses.push(SignatureStatus::Confirmed { slot: 9 });
+        let mut rpc = MockSender::new(statuses);
+        rpc.commitment = CommitmentConfig::processed();
+
+        // A processed fork past the blockhash's limit does not expire it
+        assert_eq!(execute(&rpc, &wallet).await.unwrap().slot, 9);
+        assert_eq!(rpc.sent.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expiry_at_slot_pace() {
+        let wallet = Keypair::new();
+        let mut rpc = MockSender::new(vec![SignatureStatus::Pending]);
+        rpc.block_time = Some(CONFIRM_POLL_INTERVAL);
+
+        // Proving expiry takes the blockhash's validity plus the
+        // finalization lag, well past a minute
+        let start = Instant::now();
+        let err = send_and_confirm(&rpc, transfer(&wallet), &wallet, EXPIRY_TIMEOUT, CONFIRM_POLL_INTERVAL, REBROADCAST_INTERVAL)
+            .await
+            .unwrap_err();
+        assert!(err.is::<BlockhashExpired>());
+        assert!(start.elapsed() > Duration::from_secs(70));
+        assert!(start.elapsed() < EXPIRY_TIMEOUT);
+    }
+}
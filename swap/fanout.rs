@@ -0,0 +1,192 @@
+//! Concurrent quote fan-out
+//!
+//! Asks every venue with a pool for the pair at once, each under its own
+//! timeout, and keeps whatever quotes arrive. Venues that fail or stall
+//! are reported alongside the quotes instead of aborting the request.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::Result;
+use futures::future::{self, BoxFuture};
+use std::{fmt, time::Duration};
+use thiserror::Error;
+
+use super::{adapter::DexAdapter, DexType, Quote};
+
+/// Time each venue gets to quote, a tenth of the default swap timeout
+pub const DEFAULT_QUOTE_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// Why a venue produced no quote
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VenueError {
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// A venue that produced no quote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VenueFailure {
+    /// Venue that failed
+    pub dex_type: DexType,
+    /// What went wrong
+    pub error: VenueError,
+}
+
+impl fmt::Display for VenueFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.dex_type, self.error)
+    }
+}
+
+/// Best quote across venues and the venues that could not quote
+#[derive(Debug, Clone)]
+pub struct QuoteReport {
+    /// Best quote any venue returned
+    pub best: Quote,
+    /// Venues with a pool for the pair that returned no quote
+    pub failures: Vec<VenueFailure>,
+}
+
+/// No venue returned a quote
+#[derive(Error, Debug, Clone)]
+#[error("No valid quotes found{}", describe(failures))]
+pub struct NoQuotes {
+    /// Venues with a pool for the pair that returned no quote
+    pub failures: Vec<VenueFailure>,
+}
+
+fn describe(failures: &[VenueFailure]) -> String {
+    if failures.is_empty() {
+        return String::new();
+    }
+    let reasons: Vec<String> = failures.iter().map(|f| f.to_string()).collect();
+    format!(": {}", reasons.join("; "))
+}
+
+/// Quote the pair on every adapter with a pool for it concurrently,
+/// giving each `timeout`
+pub async fn quote_all<'a>(
+    adapters: impl IntoIterator<Item = &'a dyn DexAdapter>,
+    token_in: &Pubkey,
+    token_out: &Pubkey,
+    timeout: Duration,
+    quote: impl Fn(&'a dyn DexAdapter) -> BoxFuture<'a, Result<Quote>>,
+) -> (Vec<Quote>, Vec<VenueFailure>) {
+    let venues: Vec<&dyn DexAdapter> = adapters
+        .into_iter()
+        .filter(|a| a.supports_pair(token_in, token_out))
+        .collect();
+    let results = future::join_all(
+        venues.iter().map(|venue| tokio::time::timeout(timeout, quote(*venue))),
+    )
+    .await;
+
+    let mut quotes = Vec::new();
+    let mut failures = Vec::new();
+    for (venue, result) in venues.iter().zip(results) {
+        let error = match result {
+            Ok(Ok(quote)) => {
+                quotes.push(quote);
+                continue;
+            }
+            Ok(Err(e)) => VenueError::Failed(format!("{:#}", e)),
+            Err(_) => VenueError::Timeout(timeout),
+        };
+        tracing::debug!("No quote from {:?}: {}", venue.dex_type(), error);
+        failures.push(VenueFailure { dex_type: venue.dex_type(), error });
+    }
+    (quotes, failures)
+}
+
+/// Report of the quote with the most output, or [`NoQuotes`]
+pub fn best_by_output(quotes: Vec<Quote>, failures: Vec<VenueFailure>) -> Result<QuoteReport> {
+    best_by(quotes, failures, |best, quote| quote.amount_out > best.amount_out)
+}
+
+/// Report of the quote with the least input, or [`NoQuotes`]
+pub fn best_by_input(quotes: Vec<Quote>, failures: Vec<VenueFailure>) -> Result<QuoteReport> {
+    best_by(quotes, failures, |best, quote| quote.amount_in < best.amount_in)
+}
+
+fn best_by(
+    quotes: Vec<Quote>,
+    failures: Vec<VenueFailure>,
+    better: impl Fn(&Quote, &Quote) -> bool,
+) -> Result<QuoteReport> {
+    // The first venue wins ties, matching registration order
+    let best = quotes.into_iter().reduce(|best, quote| if better(&best, &quote) { quote } else { best });
+    match best {
+        Some(best) => Ok(QuoteReport { best, failures }),
+        None => Err(NoQuotes { failures }.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::adapter::tests::{Behaviour, Stub};
+
+    fn scripted(name: &'static str, behaviour: Behaviour) -> Stub {
+        let pair = (Pubkey::new_from_array([1; 32]), Pubkey::new_from_array([2; 32]));
+        Stub { name, pair, behaviour }
+    }
+
+    async fn quote(venues: &[Stub], token_out: Pubkey) -> Result<QuoteReport> {
+        let token_in = Pubkey::new_from_array([1; 32]);
+        let adapters = venues.iter().map(|v| v as &dyn DexAdapter);
+        let (quotes, failures) = quote_all(adapters, &token_in, &token_out, Duration::from_millis(50), |venue| {
+            venue.quote(&token_in, &token_out, 1_000, 0)
+        })
+        .await;
+        best_by_output(quotes, failures)
+    }
+
+    #[tokio::test]
+    async fn test_partial_results() {
+        let token_out = Pubkey::new_from_array([2; 32]);
+        let venues = [
+            scripted("slow", Behaviour::Stall),
+            scripted("low", Behaviour::Quote(900)),
+            scripted("broken", Behaviour::Fail),
+            scripted("high", Behaviour::Quote(1_000)),
+        ];
+
+        let report = quote(&venues, token_out).await.unwrap();
+        assert_eq!(report.best.dex_type, DexType::Custom("high"));
+        assert_eq!(
+            report.failures,
+            vec![
+                VenueFailure {
+                    dex_type: DexType::Custom("slow"),
+                    error: VenueError::Timeout(Duration::from_millis(50)),
+                },
+                VenueFailure {
+                    dex_type: DexType::Custom("broken"),
+                    error: VenueError::Failed("pool closed".to_string()),
+                },
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_quotes() {
+        let token_out = Pubkey::new_from_array([2; 32]);
+        let venues = [
+            scripted("slow", Behaviour::Stall),
+            scripted("broken", Behaviour::Fail),
+        ];
+
+        let err = quote(&venues, token_out).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<NoQuotes>().unwrap().failures.len(), 2);
+        assert_eq!(
+            err.to_string(),
+            "No valid quotes found: Custom(\"slow\"): timed out after 50ms; Custom(\"broken\"): pool closed",
+        );
+
+        // Venues without a pool for the pair are not failures
+        let err = quote(&venues, Pubkey::new_unique()).await.unwrap_err();
+        assert_eq!(err.to_string(), "No valid quotes found");
+    }
+}
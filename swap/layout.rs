@@ -0,0 +1,109 @@
+//! Helpers for decoding raw on-chain account layouts
+//!
+//! All integers are little-endian, matching the Solana programs
+//! whose accounts we decode.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::Result;
+
+/// Size of an SPL token account
+pub const TOKEN_ACCOUNT_LEN: usize = 165;
+
+/// Offset of `amount` within an SPL token account
+const TOKEN_ACCOUNT_AMOUNT_OFFSET: usize = 64;
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!(
+            "Account data too short: need {} bytes at offset {}, have {}",
+            N,
+            offset,
+            data.len(),
+        ))
+}
+
+/// Read a `u8` at `offset`
+pub fn read_u8(data: &[u8], offset: usize) -> Result<u8> {
+    Ok(read_bytes::<1>(data, offset)?[0])
+}
+
+/// Read a `u16` at `offset`
+pub fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(read_bytes(data, offset)?))
+}
+
+/// Read an `i32` at `offset`
+pub fn read_i32(data: &[u8], offset: usize) -> Result<i32> {
+    Ok(i32::from_le_bytes(read_bytes(data, offset)?))
+}
+
+/// Read a `u64` at `offset`
+pub fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(data, offset)?))
+}
+
+/// Read a `u128` at `offset`
+pub fn read_u128(data: &[u8], offset: usize) -> Result<u128> {
+    Ok(u128::from_le_bytes(read_bytes(data, offset)?))
+}
+
+/// Read an `i128` at `offset`
+pub fn read_i128(data: &[u8], offset: usize) -> Result<i128> {
+    Ok(i128::from_le_bytes(read_bytes(data, offset)?))
+}
+
+/// Read a `Pubkey` at `offset`
+pub fn read_pubkey(data: &[u8], offset: usize) -> Result<Pubkey> {
+    Ok(Pubkey::new_from_array(read_bytes(data, offset)?))
+}
+
+/// Read the token balance from an SPL token account
+pub fn read_token_amount(data: &[u8]) -> Result<u64> {
+    if data.len() < TOKEN_ACCOUNT_LEN {
+        anyhow::bail!("Invalid token account: expected {} bytes, got {}", TOKEN_ACCOUNT_LEN, data.len());
+    }
+    read_u64(data, TOKEN_ACCOUNT_AMOUNT_OFFSET)
+}
+
+/// SPL token account holding `amount`
+#[cfg(test)]
+pub(crate) fn token_account(amount: u64) -> Vec<u8> {
+    let mut data = vec![0; TOKEN_ACCOUNT_LEN];
+    data[TOKEN_ACCOUNT_AMOUNT_OFFSET..TOKEN_ACCOUNT_AMOUNT_OFFSET + 8].copy_from_slice(&amount.to_le_bytes());
+    data
+}
+
+/// Load an account dump written by `solana account <address> --output json`
+#[cfg(test)]
+pub(crate) fn load_fixture(json: &str) -> (Pubkey, Vec<u8>) {
+    use base64::Engine;
+
+    let value: serde_json::Value = serde_json::from_str(json).unwrap();
+    let address = value["pubkey"].as_str().unwrap().parse().unwrap();
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(value["account"]["data"][0].as_str().unwrap())
+        .unwrap();
+    (address, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_integers() {
+        let data = [1u8, 2, 3, 4, 5, 6, 7, 8, 9];
+        assert_eq!(read_u16(&data, 0).unwrap(), 0x0201);
+        assert_eq!(read_u64(&data, 1).unwrap(), 0x0908070605040302);
+        assert_eq!(read_i32(&[0xff; 4], 0).unwrap(), -1);
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let data = [0u8; 8];
+        assert!(read_u64(&data, 1).is_err());
+        assert!(read_pubkey(&data, 0).is_err());
+        assert!(read_token_amount(&data).is_err());
+    }
+}
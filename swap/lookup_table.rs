@@ -0,0 +1,180 @@
+//! Address lookup tables
+//!
+//! Versioned (v0) swap transactions reference pool and vault accounts
+//! through address lookup tables, one byte each instead of 32, which keeps
+//! multi-hop and split swaps under the packet size limit. Tables are
+//! created and extended on chain with the helpers here, then loaded into
+//! the engine.
+
+use anchor_client::solana_sdk::{
+    address_lookup_table::{instruction, state::AddressLookupTable, AddressLookupTableAccount},
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::VersionedTransaction,
+};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Addresses one extend instruction adds, few enough for its transaction
+/// to fit a packet
+pub const MAX_EXTEND_ADDRESSES: usize = 20;
+
+/// Instruction creating a table owned by `authority`, and the table's
+/// address. `recent_slot` must be a slot the cluster still remembers.
+pub fn create_lookup_table(authority: &Pubkey, payer: &Pubkey, recent_slot: u64) -> (Instruction, Pubkey) {
+    instruction::create_lookup_table(*authority, *payer, recent_slot)
+}
+
+/// Instructions adding `addresses` to `table`, one per transaction
+pub fn extend_lookup_table(table: &Pubkey, authority: &Pubkey, payer: &Pubkey, addresses: &[Pubkey]) -> Vec<Instruction> {
+    addresses
+        .chunks(MAX_EXTEND_ADDRESSES)
+        .map(|chunk| instruction::extend_lookup_table(*table, *authority, Some(*payer), chunk.to_vec()))
+        .collect()
+}
+
+/// Decode the account data of the table at `key`
+pub fn decode_lookup_table(key: Pubkey, data: &[u8]) -> Result<AddressLookupTableAccount> {
+    let table = AddressLookupTable::deserialize(data)
+        .map_err(|e| anyhow::anyhow!("Invalid lookup table {}: {}", key, e))?;
+    Ok(AddressLookupTableAccount {
+        key,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+/// Accounts of `instructions` worth adding to a table, most used first,
+/// leaving out signers and accounts `tables` already hold
+pub fn candidates(instructions: &[Instruction], tables: &[AddressLookupTableAccount]) -> Vec<Pubkey> {
+    let mut uses: HashMap<Pubkey, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for meta in instructions.iter().flat_map(|ix| &ix.accounts) {
+        if meta.is_signer || tables.iter().any(|t| t.addresses.contains(&meta.pubkey)) {
+            continue;
+        }
+        let count = uses.entry(meta.pubkey).or_insert(0);
+        if *count == 0 {
+            order.push(meta.pubkey);
+        }
+        *count += 1;
+    }
+    // Stable, so equally used accounts keep their first-use order
+    order.sort_by_key(|address| std::cmp::Reverse(uses[address]));
+    order
+}
+
+/// Unsigned v0 transaction paid by `payer` running `instructions`, with
+/// accounts resolved through `tables` where possible. The blockhash is
+/// set when signing.
+pub fn compile(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    tables: &[AddressLookupTableAccount],
+) -> Result<VersionedTransaction> {
+    let message = v0::Message::try_compile(payer, instructions, tables, Default::default())?;
+    let signers = message.header.num_required_signatures as usize;
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default(); signers],
+        message: VersionedMessage::V0(message),
+    };
+
+    let size = bincode::serialized_size(&transaction)? as usize;
+    if size > PACKET_DATA_SIZE {
+        anyhow::bail!(
+            "Transaction is {} bytes, over the {} byte limit; add its accounts to a lookup table",
+            size,
+            PACKET_DATA_SIZE,
+        );
+    }
+    Ok(transaction)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use anchor_client::solana_sdk::{
+        address_lookup_table::{program, state::LookupTableMeta},
+        instruction::AccountMeta,
+    };
+    use std::borrow::Cow;
+
+    /// Account data of a table holding `addresses`
+    pub(crate) fn table_data(addresses: &[Pubkey]) -> Vec<u8> {
+        AddressLookupTable {
+            meta: LookupTableMeta::new(Pubkey::new_unique()),
+            addresses: Cow::Borrowed(addresses),
+        }
+        .serialize_for_tests()
+        .unwrap()
+    }
+
+    /// Instruction touching `count` fresh writable accounts
+    fn wide_instruction(payer: &Pubkey, count: usize) -> Instruction {
+        let mut accounts = vec![AccountMeta::new(*payer, true)];
+        accounts.extend((0..count).map(|_| AccountMeta::new(Pubkey::new_unique(), false)));
+        Instruction::new_with_bytes(Pubkey::new_unique(), &[0; 16], accounts)
+    }
+
+    #[test]
+    fn test_create_and_extend() {
+        let (authority, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (ix, table) = create_lookup_table(&authority, &payer, 1_000);
+        assert_eq!(ix.program_id, program::ID);
+        assert_eq!(ix.accounts[0].pubkey, table);
+
+        let addresses: Vec<Pubkey> = (0..45).map(|_| Pubkey::new_unique()).collect();
+        let ixs = extend_lookup_table(&table, &authority, &payer, &addresses);
+        assert_eq!(ixs.len(), 3);
+        assert!(ixs.iter().all(|ix| ix.accounts[0].pubkey == table));
+
+        let decoded = decode_lookup_table(table, &table_data(&addresses)).unwrap();
+        assert_eq!(decoded.key, table);
+        assert_eq!(decoded.addresses, addresses);
+        assert!(decode_lookup_table(table, &[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let (payer, pool, vault) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let program = Pubkey::new_unique();
+        let ixs = [
+            Instruction::new_with_bytes(program, &[], vec![
+                AccountMeta::new(vault, false),
+                AccountMeta::new(pool, false),
+                AccountMeta::new(payer, true),
+            ]),
+            Instruction::new_with_bytes(program, &[], vec![AccountMeta::new_readonly(pool, false)]),
+        ];
+        assert_eq!(candidates(&ixs, &[]), vec![pool, vault]);
+
+        let table = AddressLookupTableAccount { key: Pubkey::new_unique(), addresses: vec![pool] };
+        assert_eq!(candidates(&ixs, &[table]), vec![vault]);
+    }
+
+    #[test]
+    fn test_compile() {
+        let payer = Pubkey::new_unique();
+        let ixs = [wide_instruction(&payer, 30), wide_instruction(&payer, 30)];
+
+        // 63 static keys do not fit a packet
+        let err = compile(&payer, &ixs, &[]).unwrap_err();
+        assert!(err.to_string().contains("over the 1232 byte limit"));
+
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: candidates(&ixs, &[]),
+        };
+        let tx = compile(&payer, &ixs, std::slice::from_ref(&table)).unwrap();
+        assert_eq!(tx.signatures.len(), 1);
+        assert_eq!(tx.message.static_account_keys()[0], payer);
+        let VersionedMessage::V0(message) = &tx.message else {
+            panic!("expected a v0 message");
+        };
+        assert_eq!(message.address_table_lookups.len(), 1);
+        assert_eq!(message.address_table_lookups[0].account_key, table.key);
+        assert_eq!(message.address_table_lookups[0].writable_indexes.len(), 60);
+    }
+}
@@ -0,0 +1,299 @@
+//! Checked fixed-point math
+//!
+//! Overflow-safe arithmetic shared by every quote path. Intermediate
+//! products are widened to u128 or [`U256`], every division states its
+//! rounding direction, and failures surface as a [`MathError`] instead
+//! of panicking or silently wrapping.
+
+use thiserror::Error;
+
+mod u256 {
+    // Lints fire on code generated by `construct_uint!`
+    #![allow(clippy::all)]
+
+    uint::construct_uint! {
+        /// 256-bit unsigned integer for intermediate products
+        pub struct U256(4);
+    }
+}
+
+pub use u256::U256;
+
+/// Fractional bits of a Q64.64 number
+pub const Q64_RESOLUTION: u32 = 64;
+
+/// 1.0 as Q64.64
+pub const Q64: u128 = 1 << Q64_RESOLUTION;
+
+/// Basis points in 100%
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Arithmetic failures in quote calculations
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathError {
+    #[error("Multiplication overflow")]
+    MultiplicationOverflow,
+
+    #[error("Subtraction underflow")]
+    SubtractionUnderflow,
+
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("Token amount exceeds u64")]
+    AmountExceedsU64,
+
+    #[error("Value exceeds u128")]
+    ValueExceedsU128,
+
+    #[error("Fee of {0} bps exceeds 100%")]
+    InvalidFee(u64),
+}
+
+/// Rounding direction of a division
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Toward zero
+    Down,
+    /// Away from zero
+    Up,
+}
+
+impl Rounding {
+    /// `Up` when `round_up` is set, `Down` otherwise
+    pub fn up_if(round_up: bool) -> Self {
+        if round_up {
+            Self::Up
+        } else {
+            Self::Down
+        }
+    }
+}
+
+/// Narrow to a token amount
+pub fn to_u64(value: U256) -> Result<u64, MathError> {
+    if value > U256::from(u64::MAX) {
+        return Err(MathError::AmountExceedsU64);
+    }
+    Ok(value.as_u64())
+}
+
+/// Narrow to u128
+pub fn to_u128(value: U256) -> Result<u128, MathError> {
+    if value > U256::from(u128::MAX) {
+        return Err(MathError::ValueExceedsU128);
+    }
+    Ok(value.as_u128())
+}
+
+/// Shift left by [`Q64_RESOLUTION`] bits, failing instead of dropping
+/// high bits
+pub fn shl_q64(value: U256) -> Result<U256, MathError> {
+    if !(value >> (256 - Q64_RESOLUTION)).is_zero() {
+        return Err(MathError::MultiplicationOverflow);
+    }
+    Ok(value << Q64_RESOLUTION)
+}
+
+/// `numerator / denominator`
+pub fn div(numerator: U256, denominator: U256, rounding: Rounding) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+    let (quotient, remainder) = numerator.div_mod(denominator);
+    Ok(if rounding == Rounding::Up && !remainder.is_zero() {
+        quotient + 1
+    } else {
+        quotient
+    })
+}
+
+/// `a * b / denominator` with a 256-bit intermediate product
+pub fn mul_div(a: u128, b: u128, denominator: u128, rounding: Rounding) -> Result<u128, MathError> {
+    to_u128(div(U256::from(a) * U256::from(b), U256::from(denominator), rounding)?)
+}
+
+/// `a * b / denominator` for token amounts
+pub fn mul_div_u64(a: u64, b: u64, denominator: u64, rounding: Rounding) -> Result<u64, MathError> {
+    let result = mul_div(a as u128, b as u128, denominator as u128, rounding)?;
+    u64::try_from(result).map_err(|_| MathError::AmountExceedsU64)
+}
+
+/// Multiply by a Q64.64 factor
+pub fn mul_q64(value: u128, factor_x64: u128, rounding: Rounding) -> Result<u128, MathError> {
+    mul_div(value, factor_x64, Q64, rounding)
+}
+
+/// `numerator / denominator` as Q64.64
+pub fn div_q64(numerator: u128, denominator: u128, rounding: Rounding) -> Result<u128, MathError> {
+    to_u128(div(shl_q64(U256::from(numerator))?, U256::from(denominator), rounding)?)
+}
+
+/// `part / whole` in basis points, rounded down and saturating at
+/// `u16::MAX`
+pub fn bps(part: u128, whole: u128) -> Result<u16, MathError> {
+    let ratio = div(U256::from(part) * U256::from(BPS_DENOMINATOR), U256::from(whole), Rounding::Down)?;
+    Ok(if ratio > U256::from(u16::MAX) {
+        u16::MAX
+    } else {
+        ratio.as_u32() as u16
+    })
+}
+
+/// Shortfall of `amount_out` from `expected_out`, the output at the
+/// pre-trade mid price, in basis points
+pub fn price_impact_bps(expected_out: u128, amount_out: u64) -> Result<u16, MathError> {
+    if expected_out == 0 {
+        return Ok(0);
+    }
+    bps(expected_out.saturating_sub(amount_out as u128), expected_out)
+}
+
+/// `amount` left after a fee of `fee_bps`, rounded down
+pub fn deduct_fee_bps(amount: u64, fee_bps: u16) -> Result<u64, MathError> {
+    let fee_complement = BPS_DENOMINATOR
+        .checked_sub(fee_bps as u64)
+        .ok_or(MathError::InvalidFee(fee_bps as u64))?;
+    mul_div_u64(amount, fee_complement, BPS_DENOMINATOR, Rounding::Down)
+}
+
+/// Constant product output for `amount_in`, fees taken from the input.
+///
+/// Rounds down, in favor of the pool.
+pub fn constant_product_output(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+) -> Result<u64, MathError> {
+    let amount_with_fees = deduct_fee_bps(amount_in, fee_bps)?;
+
+    let denominator = (reserve_in as u128) + amount_with_fees as u128;
+    // Always below `reserve_out`
+    let amount_out = mul_div(amount_with_fees as u128, reserve_out as u128, denominator, Rounding::Down)?;
+    Ok(amount_out as u64)
+}
+
+/// Constant product input needed to receive `amount_out`, fees taken
+/// from the input.
+///
+/// Rounds up, in favor of the pool.
+pub fn constant_product_input(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+) -> Result<u64, MathError> {
+    let fee_complement = BPS_DENOMINATOR
+        .checked_sub(fee_bps as u64)
+        .ok_or(MathError::InvalidFee(fee_bps as u64))?;
+    let remaining_out = reserve_out
+        .checked_sub(amount_out)
+        .ok_or(MathError::SubtractionUnderflow)?;
+
+    let amount_with_fees = mul_div(amount_out as u128, reserve_in as u128, remaining_out as u128, Rounding::Up)?;
+    let amount_in = mul_div(amount_with_fees, BPS_DENOMINATOR as u128, fee_complement as u128, Rounding::Up)?;
+    u64::try_from(amount_in).map_err(|_| MathError::AmountExceedsU64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_mul_div_rounding() {
+        assert_eq!(mul_div(7, 3, 2, Rounding::Down), Ok(10));
+        assert_eq!(mul_div(7, 3, 2, Rounding::Up), Ok(11));
+        assert_eq!(mul_div(8, 3, 2, Rounding::Up), Ok(12));
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, Rounding::Down), Ok(u128::MAX));
+
+        assert_eq!(mul_div(1, 1, 0, Rounding::Down), Err(MathError::DivisionByZero));
+        assert_eq!(mul_div(u128::MAX, 2, 1, Rounding::Down), Err(MathError::ValueExceedsU128));
+        assert_eq!(mul_div_u64(u64::MAX, 2, 1, Rounding::Down), Err(MathError::AmountExceedsU64));
+    }
+
+    #[test]
+    fn test_q64() {
+        assert_eq!(div_q64(1, 2, Rounding::Down), Ok(Q64 / 2));
+        assert_eq!(mul_q64(10, Q64 / 2, Rounding::Down), Ok(5));
+        assert_eq!(mul_q64(3, Q64 / 2, Rounding::Up), Ok(2));
+        assert_eq!(div_q64(u128::MAX, 1, Rounding::Down), Err(MathError::ValueExceedsU128));
+        assert_eq!(shl_q64(U256::MAX), Err(MathError::MultiplicationOverflow));
+    }
+
+    #[test]
+    fn test_bps() {
+        assert_eq!(bps(1, 100), Ok(100));
+        assert_eq!(bps(1, 3), Ok(3333));
+        assert_eq!(bps(u128::MAX, 1), Ok(u16::MAX));
+        assert_eq!(bps(1, 0), Err(MathError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_price_impact_bps() {
+        assert_eq!(price_impact_bps(1_000, 1_000), Ok(0));
+        assert_eq!(price_impact_bps(1_000, 990), Ok(100));
+        // Rounding in the trader's favor is not negative impact
+        assert_eq!(price_impact_bps(1_000, 1_001), Ok(0));
+        assert_eq!(price_impact_bps(0, 0), Ok(0));
+        assert_eq!(price_impact_bps(u128::MAX, 0), Ok(10_000));
+    }
+
+    #[test]
+    fn test_constant_product() {
+        // 0.3% fee on a 1:1 pool
+        assert_eq!(constant_product_output(1_000, 1_000_000, 1_000_000, 30), Ok(996));
+        assert_eq!(constant_product_input(996, 1_000_000, 1_000_000, 30), Ok(1_000));
+
+        // Realistic reserves overflow a u64 product
+        let out = constant_product_output(u64::MAX, u64::MAX, u64::MAX, 25).unwrap();
+        assert!(out < u64::MAX / 2);
+
+        assert_eq!(constant_product_output(1, 1, 1, 10_001), Err(MathError::InvalidFee(10_001)));
+        assert_eq!(constant_product_input(10, 100, 10, 25), Err(MathError::DivisionByZero));
+        assert_eq!(constant_product_input(11, 100, 10, 25), Err(MathError::SubtractionUnderflow));
+        assert_eq!(constant_product_input(1, 100, 1_000, 10_000), Err(MathError::DivisionByZero));
+        assert_eq!(constant_product_input(u64::MAX - 1, u64::MAX, u64::MAX, 0), Err(MathError::AmountExceedsU64));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_mul_div_brackets_exact(a: u128, b: u128, denominator in 1u128..) {
+            let exact = U256::from(a) * U256::from(b);
+            if let (Ok(down), Ok(up)) = (
+                mul_div(a, b, denominator, Rounding::Down),
+                mul_div(a, b, denominator, Rounding::Up),
+            ) {
+                prop_assert!(U256::from(down) * U256::from(denominator) <= exact);
+                prop_assert!(U256::from(up) * U256::from(denominator) >= exact);
+                prop_assert!(up - down <= 1);
+            }
+        }
+
+        #[test]
+        fn prop_output_below_reserve(
+            amount_in: u64,
+            reserve_in in 1u64..,
+            reserve_out: u64,
+            fee_bps in 0u16..=10_000,
+        ) {
+            let amount_out = constant_product_output(amount_in, reserve_in, reserve_out, fee_bps).unwrap();
+            prop_assert!(amount_out < reserve_out || reserve_out == 0);
+        }
+
+        #[test]
+        fn prop_input_covers_output(
+            amount_out in 1u64..,
+            reserve_in in 1u64..,
+            reserve_out in 2u64..,
+            fee_bps in 0u16..10_000,
+        ) {
+            let amount_out = amount_out % (reserve_out - 1) + 1;
+            if let Ok(amount_in) = constant_product_input(amount_out, reserve_in, reserve_out, fee_bps) {
+                let received = constant_product_output(amount_in, reserve_in, reserve_out, fee_bps).unwrap();
+                prop_assert!(received >= amount_out);
+            }
+        }
+    }
+}
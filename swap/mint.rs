@@ -0,0 +1,230 @@
+//! Token mints
+//!
+//! Mints belong to either the SPL Token program or Token-2022, which
+//! derive different associated token accounts and must be passed to
+//! swap programs. Token-2022 mints may carry a transfer fee extension
+//! withholding part of every transfer, so a swap pays it on its input on
+//! the way into the pool and on its output on the way out.
+
+use anchor_client::solana_sdk::{pubkey, pubkey::Pubkey};
+use anchor_spl::token;
+use anyhow::Result;
+
+use super::{
+    layout::{read_u16, read_u64, read_u8, TOKEN_ACCOUNT_LEN},
+    math::MathError,
+};
+
+/// Token-2022 program
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+/// Size of a mint without extensions
+const MINT_LEN: usize = 82;
+
+/// Account type byte of a mint with extensions, which follows the mint
+/// padded to the size of a token account
+const ACCOUNT_TYPE_OFFSET: usize = TOKEN_ACCOUNT_LEN;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+
+/// Extension types, the first field of each TLV entry
+const EXTENSION_UNINITIALIZED: u16 = 0;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+
+/// Offsets of the older and newer `TransferFee` within `TransferFeeConfig`
+const OLDER_TRANSFER_FEE_OFFSET: usize = 72;
+const NEWER_TRANSFER_FEE_OFFSET: usize = 90;
+
+/// Fee rate withholding the whole transfer
+const MAX_FEE_BASIS_POINTS: u16 = 10_000;
+
+/// Transfer fee of a Token-2022 mint from `epoch` on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferFee {
+    /// First epoch the fee applies in
+    pub epoch: u64,
+    /// Most withheld from one transfer
+    pub maximum_fee: u64,
+    /// Share of each transfer withheld, in basis points
+    pub basis_points: u16,
+}
+
+impl TransferFee {
+    fn decode(data: &[u8], offset: usize) -> Result<Self> {
+        Ok(Self {
+            epoch: read_u64(data, offset)?,
+            maximum_fee: read_u64(data, offset + 8)?,
+            basis_points: read_u16(data, offset + 16)?,
+        })
+    }
+
+    /// Amount withheld from a transfer of `amount`, rounded up
+    pub fn fee(&self, amount: u64) -> u64 {
+        if self.basis_points == 0 || amount == 0 {
+            return 0;
+        }
+        let fee = (amount as u128 * self.basis_points as u128).div_ceil(MAX_FEE_BASIS_POINTS as u128);
+        fee.min(self.maximum_fee as u128) as u64
+    }
+
+    /// Smallest transfer delivering `amount` after the fee
+    pub fn pre_fee_amount(&self, amount: u64) -> Result<u64, MathError> {
+        let max = MAX_FEE_BASIS_POINTS as u128;
+        match (self.basis_points, amount) {
+            (0, _) => Ok(amount),
+            (_, 0) => Ok(0),
+            (bps, _) if bps >= MAX_FEE_BASIS_POINTS => {
+                amount.checked_add(self.maximum_fee).ok_or(MathError::AmountExceedsU64)
+            }
+            (bps, _) => {
+                let transfer = (amount as u128 * max).div_ceil(max - bps as u128);
+                if transfer - amount as u128 >= self.maximum_fee as u128 {
+                    amount.checked_add(self.maximum_fee).ok_or(MathError::AmountExceedsU64)
+                } else {
+                    u64::try_from(transfer).map_err(|_| MathError::AmountExceedsU64)
+                }
+            }
+        }
+    }
+}
+
+/// Token program and transfer fee of a mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MintInfo {
+    /// Program owning the mint and its token accounts
+    pub token_program: Pubkey,
+    /// Transfer fee in effect, if any
+    pub transfer_fee: Option<TransferFee>,
+}
+
+impl Default for MintInfo {
+    /// An SPL Token mint
+    fn default() -> Self {
+        Self {
+            token_program: token::ID,
+            transfer_fee: None,
+        }
+    }
+}
+
+impl MintInfo {
+    /// Decode a mint owned by `owner`, with the transfer fee in effect at
+    /// `epoch`
+    pub fn decode(owner: &Pubkey, data: &[u8], epoch: u64) -> Result<Self> {
+        if *owner != token::ID && *owner != TOKEN_2022_PROGRAM_ID {
+            anyhow::bail!("Not a mint: owned by {}", owner);
+        }
+        if data.len() < MINT_LEN {
+            anyhow::bail!("Invalid mint: expected at least {} bytes, got {}", MINT_LEN, data.len());
+        }
+        let mut info = Self {
+            token_program: *owner,
+            transfer_fee: None,
+        };
+        if *owner == token::ID || data.len() == MINT_LEN {
+            return Ok(info);
+        }
+
+        if read_u8(data, ACCOUNT_TYPE_OFFSET)? != ACCOUNT_TYPE_MINT {
+            anyhow::bail!("Invalid mint: extensions do not belong to a mint");
+        }
+        let mut offset = ACCOUNT_TYPE_OFFSET + 1;
+        while offset < data.len() {
+            let extension = read_u16(data, offset)?;
+            let len = read_u16(data, offset + 2)? as usize;
+            let value = offset + 4;
+            match extension {
+                EXTENSION_UNINITIALIZED => break,
+                EXTENSION_TRANSFER_FEE_CONFIG => {
+                    let older = TransferFee::decode(data, value + OLDER_TRANSFER_FEE_OFFSET)?;
+                    let newer = TransferFee::decode(data, value + NEWER_TRANSFER_FEE_OFFSET)?;
+                    // A scheduled fee change takes effect at its epoch
+                    info.transfer_fee = Some(if epoch >= newer.epoch { newer } else { older });
+                }
+                _ => {}
+            }
+            offset = value + len;
+        }
+        Ok(info)
+    }
+
+    /// Whether the mint belongs to Token-2022
+    pub fn is_token_2022(&self) -> bool {
+        self.token_program == TOKEN_2022_PROGRAM_ID
+    }
+
+    /// Amount withheld from a transfer of `amount`
+    pub fn transfer_fee(&self, amount: u64) -> u64 {
+        self.transfer_fee.map_or(0, |fee| fee.fee(amount))
+    }
+
+    /// Smallest transfer delivering `amount` after the fee
+    pub fn pre_fee_amount(&self, amount: u64) -> Result<u64, MathError> {
+        self.transfer_fee.map_or(Ok(amount), |fee| fee.pre_fee_amount(amount))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Token-2022 mint data with a transfer fee changing from `older` to
+    /// `newer`
+    pub(crate) fn fee_mint(older: TransferFee, newer: TransferFee) -> Vec<u8> {
+        let mut data = vec![0; ACCOUNT_TYPE_OFFSET];
+        data.push(ACCOUNT_TYPE_MINT);
+        // An extension to skip ahead of the fee
+        data.extend_from_slice(&9u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&EXTENSION_TRANSFER_FEE_CONFIG.to_le_bytes());
+        data.extend_from_slice(&108u16.to_le_bytes());
+        data.extend_from_slice(&[0; OLDER_TRANSFER_FEE_OFFSET]);
+        for fee in [older, newer] {
+            data.extend_from_slice(&fee.epoch.to_le_bytes());
+            data.extend_from_slice(&fee.maximum_fee.to_le_bytes());
+            data.extend_from_slice(&fee.basis_points.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        let fee = TransferFee { epoch: 0, maximum_fee: 5_000, basis_points: 100 };
+        assert_eq!(fee.fee(0), 0);
+        assert_eq!(fee.fee(1), 1);
+        assert_eq!(fee.fee(10_000), 100);
+        assert_eq!(fee.fee(10_001), 101);
+        assert_eq!(fee.fee(10_000_000), 5_000);
+
+        for amount in [1, 99, 9_900, 9_901, 494_999, 495_000, 10_000_000] {
+            let transfer = fee.pre_fee_amount(amount).unwrap();
+            assert_eq!(transfer - fee.fee(transfer), amount);
+            assert!(transfer - 1 - fee.fee(transfer - 1) < amount);
+        }
+
+        let all = TransferFee { epoch: 0, maximum_fee: 7, basis_points: MAX_FEE_BASIS_POINTS };
+        assert_eq!(all.pre_fee_amount(100).unwrap(), 107);
+        assert!(fee.pre_fee_amount(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_decode_mint() {
+        let spl = MintInfo::decode(&token::ID, &[0; MINT_LEN], 0).unwrap();
+        assert_eq!(spl, MintInfo::default());
+        assert!(!spl.is_token_2022());
+
+        let plain = MintInfo::decode(&TOKEN_2022_PROGRAM_ID, &[0; MINT_LEN], 0).unwrap();
+        assert!(plain.is_token_2022());
+        assert_eq!(plain.transfer_fee(1_000), 0);
+
+        let older = TransferFee { epoch: 0, maximum_fee: 1_000, basis_points: 50 };
+        let newer = TransferFee { epoch: 600, maximum_fee: 1_000, basis_points: 200 };
+        let data = fee_mint(older, newer);
+        assert_eq!(MintInfo::decode(&TOKEN_2022_PROGRAM_ID, &data, 599).unwrap().transfer_fee, Some(older));
+        let info = MintInfo::decode(&TOKEN_2022_PROGRAM_ID, &data, 600).unwrap();
+        assert_eq!(info.transfer_fee, Some(newer));
+        assert_eq!(info.transfer_fee(10_000), 200);
+
+        assert!(MintInfo::decode(&Pubkey::new_unique(), &data, 0).is_err());
+        assert!(MintInfo::decode(&TOKEN_2022_PROGRAM_ID, &data[..200], 0).is_err());
+    }
+}
@@ -4,18 +4,67 @@
 //! Solana DEXes and finding optimal swap routes.
 
 use anchor_client::solana_sdk::{
+    address_lookup_table::AddressLookupTableAccount,
+    instruction::Instruction,
     pubkey::Pubkey,
-    signature::Keypair,
-    transaction::Transaction,
+    signature::{Keypair, Signer},
+    transaction::VersionedTransaction,
 };
 use anyhow::Result;
-use std::collections::HashMap;
+use arc_swap::ArcSwap;
+use futures::future::BoxFuture;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
 
+mod adapter;
+mod cache;
+mod compute_budget;
+mod discovery;
+mod execution;
+mod fanout;
+mod layout;
+mod lookup_table;
+mod math;
+mod mint;
 mod raydium;
 mod orca;
+mod router;
+mod simulation;
+mod slippage;
+mod split;
+mod token_accounts;
+mod whirlpool_math;
+
+use cache::QuoteCache;
+use token_accounts::AccountSetup;
 
-pub use raydium::Client as RaydiumClient;
+pub use adapter::DexAdapter;
+pub use cache::{CachePolicy, CacheStats};
+pub use compute_budget::{
+    ComputeBudget, ComputeUnitLimit, PriorityFee, DEFAULT_COMPUTE_UNIT_MARGIN_BPS, MAX_COMPUTE_UNIT_LIMIT,
+};
+pub use discovery::{AccountProvider, PoolFilter};
+pub use adapter::SwapFailure;
+pub use execution::{
+    BlockhashExpired, OutcomeUnknown, SignatureStatus, Simulation, SwapReceipt, TransactionSender,
+    DEFAULT_CONFIRM_TIMEOUT, EXPIRY_TIMEOUT,
+};
+pub use fanout::{NoQuotes, QuoteReport, VenueError, VenueFailure, DEFAULT_QUOTE_TIMEOUT};
+pub use lookup_table::{create_lookup_table, decode_lookup_table, extend_lookup_table, MAX_EXTEND_ADDRESSES};
+pub use math::{MathError, Rounding};
+pub use mint::{MintInfo, TransferFee, TOKEN_2022_PROGRAM_ID};
+pub use raydium::{Client as RaydiumClient, PoolAccounts as RaydiumPoolAccounts};
 pub use orca::Client as OrcaClient;
+pub use router::{Route, TokenGraph, MAX_HOPS};
+pub use slippage::{Slippage, VolatilitySource, DEFAULT_SLIPPAGE_BPS};
+pub use split::{SplitQuote, DEFAULT_SPLIT_CHUNKS};
+pub use token_accounts::{create_associated_token_account_idempotent, NATIVE_MINT, TOKEN_ACCOUNT_RENT};
 
 /// Supported DEX types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -24,6 +73,18 @@ pub enum DexType {
     Raydium,
     /// Orca Whirlpools
     Orca,
+    /// Venue provided by an adapter outside this crate
+    Custom(&'static str),
+}
+
+/// Which side of a swap is fixed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SwapMode {
+    /// Spend exactly `amount_in`, receive at least `minimum_out`
+    #[default]
+    ExactIn,
+    /// Receive exactly `amount_out`, spend at most `maximum_in`
+    ExactOut,
 }
 
 /// Unified quote information
@@ -31,137 +92,1443 @@ pub enum DexType {
 pub struct Quote {
     /// DEX providing the quote
     pub dex_type: DexType,
-    /// Input amount
+    /// Which side of the swap is fixed
+    pub mode: SwapMode,
+    /// Source token mint
+    pub token_in: Pubkey,
+    /// Destination token mint
+    pub token_out: Pubkey,
+    /// Token program of the source mint
+    pub token_program_in: Pubkey,
+    /// Token program of the destination mint
+    pub token_program_out: Pubkey,
+    /// Pool being used
+    pub pool: Pubkey,
+    /// Input amount, including any transfer fee
     pub amount_in: u64,
-    /// Expected output amount
+    /// Expected output amount, net of any transfer fee
     pub amount_out: u64,
-    /// Price impact (in basis points)
+    /// Shortfall of the execution price from the pool's mid price before
+    /// the swap, fees excluded (in basis points)
     pub price_impact_bps: u16,
     /// Minimum output amount (with slippage)
     pub minimum_out: u64,
-    /// Prepared transaction
-    pub transaction: Transaction,
+    /// Maximum input amount (with slippage)
+    pub maximum_in: u64,
+    /// Venue-specific accounts the swap needs, such as Orca tick arrays
+    pub extra_accounts: Vec<Pubkey>,
 }
 
-/// Core swap engine
+/// Core swap engine.
+///
+/// Cloning is cheap: clones share adapters, pool state and the quote
+/// cache, so one engine can serve many tasks. Quoting reads an immutable
+/// snapshot and never waits on a lock, skipping the quote cache when
+/// another task is using the same shard of it; updates publish a new
+/// snapshot.
+#[derive(Clone)]
 pub struct SwapEngine {
-    /// Raydium client
-    raydium: RaydiumClient,
-    /// Orca client
-    orca: OrcaClient,
-    /// Quote cache
-    quote_cache: HashMap<(Pubkey, Pubkey, u64), Quote>,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// Snapshot quoting currently reads
+    state: ArcSwap<EngineState>,
+    /// Latest slot observed on chain
+    current_slot: AtomicU64,
+}
+
+/// Everything a quote depends on, replaced as a whole on update
+#[derive(Clone)]
+struct EngineState {
+    /// Registered DEX adapters
+    adapters: Vec<Arc<dyn DexAdapter>>,
+    /// Observed volatility for [`Slippage::Dynamic`]
+    volatility: Option<Arc<dyn VolatilitySource>>,
+    /// Time each venue gets to quote
+    quote_timeout: Duration,
+    /// Time a submitted swap gets to confirm
+    confirm_timeout: Duration,
+    /// Compute budget instructions prepended to swaps
+    compute_budget: ComputeBudget,
+    /// Lookup tables swap transactions resolve accounts through
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    /// Best quotes from these adapters, keyed by pair, amount and
+    /// slippage tolerance
+    quote_cache: Arc<QuoteCache>,
 }
 
 impl SwapEngine {
-    /// Create a new swap engine
+    /// Create a new swap engine with the Raydium and Orca adapters
     pub fn new() -> Result<Self> {
-        Ok(Self {
-            raydium: RaydiumClient::new()?,
-            orca: OrcaClient::new()?,
-            quote_cache: HashMap::new(),
+        Self::with_adapters(vec![
+            Box::new(RaydiumClient::new()?),
+            Box::new(OrcaClient::new()?),
+        ])
+    }
+
+    /// Create a swap engine routing through the given adapters only
+    pub fn with_adapters(adapters: Vec<Box<dyn DexAdapter>>) -> Result<Self> {
+        let state = EngineState {
+            adapters: Vec::with_capacity(adapters.len()),
+            volatility: None,
+            quote_timeout: DEFAULT_QUOTE_TIMEOUT,
+            confirm_timeout: DEFAULT_CONFIRM_TIMEOUT,
+            compute_budget: ComputeBudget::default(),
+            lookup_tables: Vec::new(),
+            quote_cache: Arc::new(QuoteCache::new(CachePolicy::default())),
+        };
+        let engine = Self {
+            shared: Arc::new(Shared {
+                state: ArcSwap::from_pointee(state),
+                current_slot: AtomicU64::new(0),
+            }),
+        };
+        for adapter in adapters {
+            engine.register_adapter(adapter)?;
+        }
+        Ok(engine)
+    }
+
+    /// Register an additional venue
+    pub fn register_adapter(&self, adapter: Box<dyn DexAdapter>) -> Result<()> {
+        let adapter: Arc<dyn DexAdapter> = adapter.into();
+        self.update(|state| {
+            let dex_type = adapter.dex_type();
+            if state.adapters.iter().any(|a| a.dex_type() == dex_type) {
+                anyhow::bail!("Adapter for {:?} already registered", dex_type);
+            }
+            state.adapters.push(Arc::clone(&adapter));
+            Ok(())
+        })
+    }
+
+    /// Swap the registered adapter of the same venue for `adapter`, e.g.
+    /// one with more pools loaded
+    pub fn replace_adapter(&self, adapter: Box<dyn DexAdapter>) -> Result<()> {
+        let adapter: Arc<dyn DexAdapter> = adapter.into();
+        self.update(|state| {
+            let dex_type = adapter.dex_type();
+            match state.adapters.iter_mut().find(|a| a.dex_type() == dex_type) {
+                Some(registered) => *registered = Arc::clone(&adapter),
+                None => anyhow::bail!("No adapter registered for {:?}", dex_type),
+            }
+            Ok(())
         })
     }
 
+    /// Use `source` to scale [`Slippage::Dynamic`] tolerances
+    pub fn set_volatility_source(&self, source: Arc<dyn VolatilitySource>) {
+        self.apply(|state| {
+            state.volatility = Some(Arc::clone(&source));
+        });
+    }
+
+    /// Give each venue `timeout` to quote before it is reported as failed,
+    /// e.g. [`SwapConfig::quote_timeout`](crate::SwapConfig::quote_timeout)
+    pub fn set_quote_timeout(&self, timeout: Duration) {
+        self.apply(|state| {
+            state.quote_timeout = timeout;
+        });
+    }
+
+    /// Re-quote and resend swaps that expire within `timeout` of being
+    /// executed, e.g.
+    /// [`SwapConfig::confirm_timeout`](crate::SwapConfig::confirm_timeout)
+    pub fn set_confirm_timeout(&self, timeout: Duration) {
+        self.apply(|state| {
+            state.confirm_timeout = timeout;
+        });
+    }
+
+    /// Request compute units and pay priority fees on swaps as `budget`
+    /// describes
+    pub fn set_compute_budget(&self, budget: ComputeBudget) {
+        self.apply(|state| {
+            state.compute_budget = budget;
+        });
+    }
+
+    /// Replace the quote cache with an empty one enforcing `policy`
+    pub fn set_cache_policy(&self, policy: CachePolicy) {
+        self.apply(|state| {
+            state.quote_cache = Arc::new(QuoteCache::new(policy));
+        });
+    }
+
+    /// Quote cache counters
+    pub fn cache_stats(&self) -> CacheStats {
+        self.state().quote_cache.stats()
+    }
+
+    /// Record that the chain has reached `slot`, ageing cached quotes
+    pub fn observe_slot(&self, slot: u64) {
+        self.shared.current_slot.fetch_max(slot, Ordering::Relaxed);
+    }
+
+    /// Latest slot observed on chain
+    pub fn current_slot(&self) -> u64 {
+        self.shared.current_slot.load(Ordering::Relaxed)
+    }
+
+    /// Drop cached quotes through `pool`, e.g. when its account changes
+    pub fn invalidate_pool(&self, pool: &Pubkey) {
+        self.state().quote_cache.invalidate_pool(pool);
+    }
+
+    /// Drop every cached quote
+    pub fn clear_cache(&self) {
+        self.state().quote_cache.clear();
+    }
+
+    /// Resolve swap transaction accounts through `table`, replacing an
+    /// earlier copy of it
+    pub fn register_lookup_table(&self, table: AddressLookupTableAccount) {
+        self.apply(|state| {
+            state.lookup_tables.retain(|t| t.key != table.key);
+            state.lookup_tables.push(table.clone());
+        });
+    }
+
+    /// Fetch the lookup tables at `addresses` and register them, e.g.
+    /// after extending them
+    pub async fn load_lookup_tables(&self, rpc: &dyn AccountProvider, addresses: &[Pubkey]) -> Result<()> {
+        let accounts = discovery::get_multiple_accounts_chunked(rpc, addresses).await?;
+        for (address, data) in addresses.iter().zip(accounts) {
+            let data = data.ok_or_else(|| anyhow::anyhow!("Lookup table {} not found", address))?;
+            self.register_lookup_table(decode_lookup_table(*address, &data)?);
+        }
+        Ok(())
+    }
+
+    /// Registered lookup tables
+    pub fn lookup_tables(&self) -> Vec<AddressLookupTableAccount> {
+        self.state().lookup_tables.clone()
+    }
+
+    /// Accounts the swaps of `quotes` for `user` touch that no registered
+    /// table holds, most used first; candidates for
+    /// [`extend_lookup_table`]
+    pub fn lookup_table_candidates(&self, quotes: &[Quote], user: &Pubkey) -> Result<Vec<Pubkey>> {
+        let mut instructions = Vec::new();
+        for quote in quotes {
+            instructions.extend(self.build_swap_instructions(quote, user)?);
+        }
+        Ok(lookup_table::candidates(&instructions, &self.state().lookup_tables))
+    }
+
+    /// Registered venues
+    pub fn dex_types(&self) -> Vec<DexType> {
+        self.state().adapters.iter().map(|a| a.dex_type()).collect()
+    }
+
+    /// Adapter registered for `dex_type`
+    pub fn adapter(&self, dex_type: DexType) -> Result<Arc<dyn DexAdapter>> {
+        self.state().adapter(dex_type).map(Arc::clone)
+    }
+
+    /// Reload pool state of every adapter from chain.
+    ///
+    /// Copies of the adapters are reloaded, so quoting continues against
+    /// the previous pool state until the refresh completes.
+    pub async fn refresh(&self, rpc: &dyn AccountProvider) -> Result<()> {
+        let slot = rpc.get_slot().await?;
+        let mut refreshed = Vec::new();
+        for adapter in &self.state().adapters {
+            let mut fresh = adapter.clone_adapter();
+            fresh.refresh(rpc).await?;
+            refreshed.push((Arc::clone(adapter), Arc::<dyn DexAdapter>::from(fresh)));
+        }
+
+        self.update(|state| {
+            // Adapters replaced meanwhile are newer than our copies
+            for adapter in &mut state.adapters {
+                let current = Arc::as_ptr(adapter);
+                let fresh = refreshed
+                    .iter()
+                    .find(|(original, _)| std::ptr::addr_eq(Arc::as_ptr(original), current));
+                if let Some((_, fresh)) = fresh {
+                    *adapter = Arc::clone(fresh);
+                }
+            }
+            Ok(())
+        })?;
+        self.observe_slot(slot);
+        Ok(())
+    }
+
+    /// Get a quote from a single DEX
+    pub async fn get_quote(
+        &self,
+        dex_type: DexType,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage: Slippage,
+    ) -> Result<Quote> {
+        let state = self.state();
+        let slippage_bps = state.slippage_bps(&slippage, token_in, token_out)?;
+        state.adapter(dex_type)?.quote(token_in, token_out, amount, slippage_bps).await
+    }
+
+    /// Get a quote from a single DEX for receiving exactly `amount_out`
+    pub async fn get_quote_exact_out(
+        &self,
+        dex_type: DexType,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_out: u64,
+        slippage: Slippage,
+    ) -> Result<Quote> {
+        let state = self.state();
+        let slippage_bps = state.slippage_bps(&slippage, token_in, token_out)?;
+        state.adapter(dex_type)?.quote_exact_out(token_in, token_out, amount_out, slippage_bps).await
+    }
+
+    /// Get the quote needing the least input to receive exactly
+    /// `amount_out`, across all DEXes
+    pub async fn get_best_quote_exact_out(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_out: u64,
+        slippage: Slippage,
+    ) -> Result<Quote> {
+        let state = self.state();
+        let slippage_bps = state.slippage_bps(&slippage, token_in, token_out)?;
+        let (quotes, failures) = state.quote_all(token_in, token_out, |adapter| {
+            adapter.quote_exact_out(token_in, token_out, amount_out, slippage_bps)
+        }).await;
+        Ok(fanout::best_by_input(quotes, failures)?.best)
+    }
+
     /// Get best quote across all DEXes
     pub async fn get_best_quote(
-        &mut self,
+        &self,
         token_in: &Pubkey,
         token_out: &Pubkey,
         amount: u64,
+        slippage: Slippage,
     ) -> Result<Quote> {
+        let state = self.state();
+        let slippage_bps = state.slippage_bps(&slippage, token_in, token_out)?;
+
         // Check cache first
-        let cache_key = (*token_in, *token_out, amount);
-        if let Some(quote) = self.quote_cache.get(&cache_key) {
-            return Ok(quote.clone());
+        let cache_key = (*token_in, *token_out, amount, slippage_bps);
+        let slot = self.current_slot();
+        if let Some(quote) = state.quote_cache.get(&cache_key, slot) {
+            return Ok(quote);
         }
 
-        // Get quotes from all DEXes
-        let raydium_quote = self.raydium.get_quote(token_in, token_out, amount).await?;
-        let orca_quote = self.orca.get_quote(token_in, token_out, amount).await?;
+        let report = state.best_direct_quote(token_in, token_out, amount, slippage_bps).await?;
 
-        // Convert to unified quote format
-        let quotes = vec![
-            self.convert_raydium_quote(raydium_quote)?,
-            self.convert_orca_quote(orca_quote)?,
-        ];
+        // Cache the result
+        state.quote_cache.insert(cache_key, report.best.clone(), slot);
 
-        // Find best quote
-        let best_quote = quotes.into_iter()
-            .max_by_key(|q| q.amount_out)
-            .ok_or_else(|| anyhow::anyhow!("No valid quotes found"))?;
+        Ok(report.best)
+    }
 
-        // Cache the result
-        self.quote_cache.insert(cache_key, best_quote.clone());
+    /// Get a fresh best quote across all DEXes, along with the venues that
+    /// failed or timed out.
+    ///
+    /// Fails with [`NoQuotes`] only if no venue returned a quote.
+    pub async fn get_best_quote_report(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage: Slippage,
+    ) -> Result<QuoteReport> {
+        let state = self.state();
+        let slippage_bps = state.slippage_bps(&slippage, token_in, token_out)?;
+        let report = state.best_direct_quote(token_in, token_out, amount, slippage_bps).await?;
+
+        let cache_key = (*token_in, *token_out, amount, slippage_bps);
+        state.quote_cache.insert(cache_key, report.best.clone(), self.current_slot());
+        Ok(report)
+    }
+
+    /// Get the best route across all DEXes, through up to [`MAX_HOPS`] pools.
+    ///
+    /// Routes are compared by output net of the rent of the token accounts
+    /// their intermediate tokens need, valued in `token_out`.
+    ///
+    /// Fails with [`AgentSwapError::RouteNotFound`](crate::AgentSwapError::RouteNotFound)
+    /// only if no path of known pools connects the tokens.
+    pub async fn get_best_route(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage: Slippage,
+    ) -> Result<Route> {
+        let state = self.state();
+        let graph = TokenGraph::build(state.adapters.iter().map(|a| a.as_ref()));
+        let paths = graph.paths(token_in, token_out, MAX_HOPS);
+        if paths.is_empty() {
+            return Err(crate::AgentSwapError::RouteNotFound {
+                from: *token_in,
+                to: *token_out,
+            }.into());
+        }
+
+        let mut best: Option<(Route, u64)> = None;
+        'paths: for path in paths.iter().take(router::MAX_CANDIDATE_PATHS) {
+            let mut legs = Vec::with_capacity(path.len() - 1);
+            let mut leg_amount = amount;
+            for hop in path.windows(2) {
+                let quote = match state.slippage_bps(&slippage, &hop[0], &hop[1]) {
+                    Ok(slippage_bps) => state
+                        .best_direct_quote(&hop[0], &hop[1], leg_amount, slippage_bps)
+                        .await
+                        .map(|report| report.best),
+                    Err(e) => Err(e),
+                };
+                match quote {
+                    Ok(quote) => {
+                        leg_amount = quote.amount_out;
+                        legs.push(quote);
+                    }
+                    Err(e) => {
+                        tracing::debug!("Skipping path {:?}: {}", path, e);
+                        continue 'paths;
+                    }
+                }
+            }
+
+            let route = Route { legs };
+            let rent = match token_accounts::intermediate_rent(path) {
+                0 => 0,
+                lamports => state.rent_in(token_out, lamports).await,
+            };
+            let net_out = route.amount_out().saturating_sub(rent);
+            let better = match &best {
+                Some((_, best_out)) => net_out > *best_out,
+                None => true,
+            };
+            if better {
+                best = Some((route, net_out));
+            }
+        }
+
+        best.map(|(route, _)| route).ok_or_else(|| anyhow::anyhow!(
+            "No valid quotes for any of {} paths from {} to {}",
+            paths.len(),
+            token_in,
+            token_out,
+        ))
+    }
+
+    /// Split a swap across every DEX with a pool for the pair.
+    ///
+    /// Each DEX contributes one leg from the deepest pool it caches for
+    /// the pair, so a swap is never divided between two pools of the same
+    /// DEX. DEXes that fail or exceed the quote timeout take no further
+    /// share of the input.
+    pub async fn get_best_split(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage: Slippage,
+    ) -> Result<SplitQuote> {
+        let state = self.state();
+        let slippage_bps = state.slippage_bps(&slippage, token_in, token_out)?;
+        let adapters: Vec<&dyn DexAdapter> = state.adapters.iter().map(|a| a.as_ref()).collect();
+        split::optimize(
+            &adapters,
+            token_in,
+            token_out,
+            amount,
+            DEFAULT_SPLIT_CHUNKS,
+            slippage_bps,
+            state.quote_timeout,
+        )
+        .await
+    }
+
+    /// Prepare an unsigned v0 transaction executing every leg of `split`,
+    /// wrapping and unwrapping native SOL as needed
+    pub fn prepare_split_swap(&self, split: &SplitQuote, user: &Pubkey) -> Result<VersionedTransaction> {
+        let accounts = token_accounts::account_setup(&split.legs, user, &[]);
+        let instructions = self.swap_instructions(&split.legs, user, accounts)?;
+        lookup_table::compile(user, &instructions, &self.state().lookup_tables)
+    }
+
+    /// Prepare an unsigned v0 transaction executing the legs of `route`
+    /// in order, wrapping and unwrapping native SOL as needed. Each leg
+    /// after the first spends only what the leg before it guarantees, see
+    /// [`Route::bounded_legs`].
+    pub fn prepare_route(&self, route: &Route, user: &Pubkey) -> Result<VersionedTransaction> {
+        let legs = route.bounded_legs()?;
+        let accounts = token_accounts::account_setup(&legs, user, &[]);
+        let instructions = self.swap_instructions(&legs, user, accounts)?;
+        lookup_table::compile(user, &instructions, &self.state().lookup_tables)
+    }
+
+    /// Instructions executing `quote` on behalf of `user`
+    pub fn build_swap_instructions(&self, quote: &Quote, user: &Pubkey) -> Result<Vec<Instruction>> {
+        self.adapter(quote.dex_type)?.build_swap_instructions(quote, user)
+    }
+
+    /// Prepare an unsigned v0 transaction executing `quote` for `user`,
+    /// resolving accounts through the registered lookup tables.
+    ///
+    /// The destination token account is created if missing, native SOL
+    /// input is wrapped before the swap and native SOL output unwrapped
+    /// after it. Fixed compute budget settings are applied as
+    /// set. Simulated limits request the maximum and estimated fees are
+    /// left out until [`Self::execute_swap`] resolves them through RPC.
+    pub fn prepare_swap(&self, quote: &Quote, user: &Pubkey) -> Result<VersionedTransaction> {
+        self.prepare(quote, user, true)
+    }
+
+    /// Priority fee, in micro-lamports per compute unit, at `percentile` of
+    /// those recently paid to write the accounts `quote` touches
+    pub async fn estimate_priority_fee(
+        &self,
+        rpc: &dyn TransactionSender,
+        quote: &Quote,
+        user: &Pubkey,
+        percentile: u8,
+    ) -> Result<u64> {
+        let instructions = self.build_swap_instructions(quote, user)?;
+        let accounts = compute_budget::writable_accounts(&instructions);
+        let fees = rpc.get_recent_prioritization_fees(&accounts).await?;
+        Ok(compute_budget::fee_percentile(&fees, percentile))
+    }
 
-        Ok(best_quote)
+    /// Simulate `quote` for `user` through `rpc`, returning the output it
+    /// would deliver.
+    ///
+    /// Program errors come back as typed
+    /// [`AgentSwapError`](crate::AgentSwapError)s, and outputs below
+    /// `quote.minimum_out` as
+    /// [`SlippageExceeded`](crate::AgentSwapError::SlippageExceeded).
+    pub async fn simulate_swap(&self, rpc: &dyn TransactionSender, quote: &Quote, user: &Pubkey) -> Result<u64> {
+        let result = self.simulate(rpc, quote, user).await?;
+        simulation::simulated_output(&result)
     }
 
-    /// Execute a swap
+    /// Execute `quote` as `wallet`: simulate it, then sign with a fresh
+    /// blockhash, submit through `rpc` and wait for confirmation at its
+    /// commitment.
+    ///
+    /// Pending transactions are rebroadcast and watched until they land or
+    /// their blockhash expires, even past the confirm timeout. If one
+    /// expires without landing within the confirm timeout, the swap is
+    /// re-quoted within the bounds of `quote`, then simulated, signed and
+    /// sent again. One whose expiry does not show within
+    /// [`EXPIRY_TIMEOUT`] fails with [`OutcomeUnknown`], as it may still
+    /// land.
     pub async fn execute_swap(
         &self,
+        rpc: &dyn TransactionSender,
         quote: &Quote,
         wallet: &Keypair,
-    ) -> Result<String> {
-        let signature = match quote.dex_type {
-            DexType::Raydium => {
-                // Execute on Raydium
-                "raydium_signature".to_string()
+    ) -> Result<SwapReceipt> {
+        let deadline = Instant::now() + self.state().confirm_timeout;
+        let mut quote = quote.clone();
+        loop {
+            let transaction = self.executable_swap(rpc, &quote, &wallet.pubkey()).await?;
+            // Giving up before the expiry shows would leave the caller
+            // unsure whether the swap may still land
+            let timeout = deadline.saturating_duration_since(Instant::now()).max(EXPIRY_TIMEOUT);
+            let poll = execution::CONFIRM_POLL_INTERVAL;
+            let result = execution::send_and_confirm(rpc, transaction, wallet, timeout, poll, execution::REBROADCAST_INTERVAL).await;
+            match result {
+                // Expired transactions can no longer land, so a resend
+                // cannot execute the swap twice
+                Err(e) if e.is::<BlockhashExpired>() && Instant::now() < deadline => {
+                    tracing::debug!("{}; re-quoting", e);
+                    quote = self.requote(&quote).await?;
+                }
+                result => return result,
             }
-            DexType::Orca => {
-                // Execute on Orca
-                "orca_signature".to_string()
+        }
+    }
+
+    // Private helper methods
+    /// Simulated, budgeted transaction executing `quote` for `user`
+    async fn executable_swap(
+        &self,
+        rpc: &dyn TransactionSender,
+        quote: &Quote,
+        user: &Pubkey,
+    ) -> Result<VersionedTransaction> {
+        let simulation = self.simulate(rpc, quote, user).await?;
+
+        let state = self.state();
+        let unit_limit = state.compute_budget.unit_limit.units(simulation.units_consumed);
+        let micro_lamports = match state.compute_budget.priority_fee {
+            PriorityFee::None => None,
+            PriorityFee::Fixed(micro_lamports) => Some(micro_lamports),
+            PriorityFee::Estimated { percentile, max_micro_lamports } => {
+                let estimate = self.estimate_priority_fee(rpc, quote, user, percentile).await?;
+                Some(estimate.min(max_micro_lamports))
             }
         };
+        // Leave out creating accounts the simulation found
+        let existing: Vec<Pubkey> = simulation::swap_token_accounts(quote, user)
+            .into_iter()
+            .zip(&simulation.pre_accounts)
+            .filter(|(_, data)| data.is_some())
+            .map(|(account, _)| account)
+            .collect();
+        let accounts = token_accounts::account_setup(std::slice::from_ref(quote), user, &existing);
+        self.budgeted_swap(quote, user, accounts, unit_limit, micro_lamports)
+    }
 
-        Ok(signature)
+    /// Fresh quote from the same venue for the same swap, keeping the
+    /// price bounds the caller accepted
+    async fn requote(&self, quote: &Quote) -> Result<Quote> {
+        let adapter = self.adapter(quote.dex_type)?;
+        let mut fresh = match quote.mode {
+            SwapMode::ExactIn => adapter.quote(&quote.token_in, &quote.token_out, quote.amount_in, 0).await?,
+            SwapMode::ExactOut => {
+                adapter.quote_exact_out(&quote.token_in, &quote.token_out, quote.amount_out, 0).await?
+            }
+        };
+        fresh.minimum_out = quote.minimum_out;
+        fresh.maximum_in = quote.maximum_in;
+        Ok(fresh)
     }
 
-    // Private helper methods
-    fn convert_raydium_quote(&self, quote: raydium::RaydiumQuote) -> Result<Quote> {
-        Ok(Quote {
-            dex_type: DexType::Raydium,
-            amount_in: quote.amount_in,
-            amount_out: quote.amount_out,
-            price_impact_bps: quote.price_impact_bps,
-            minimum_out: quote.minimum_out,
-            transaction: Transaction::default(), // Replace with actual transaction
-        })
+    fn prepare(&self, quote: &Quote, user: &Pubkey, cleanup: bool) -> Result<VersionedTransaction> {
+        let budget = self.state().compute_budget;
+        let micro_lamports = match budget.priority_fee {
+            PriorityFee::Fixed(micro_lamports) => Some(micro_lamports),
+            PriorityFee::None | PriorityFee::Estimated { .. } => None,
+        };
+        let mut accounts = token_accounts::account_setup(std::slice::from_ref(quote), user, &[]);
+        if !cleanup {
+            accounts.cleanup.clear();
+        }
+        self.budgeted_swap(quote, user, accounts, budget.unit_limit.units(None), micro_lamports)
     }
 
-    fn convert_orca_quote(&self, quote: orca::OrcaQuote) -> Result<Quote> {
-        Ok(Quote {
-            dex_type: DexType::Orca,
-            amount_in: quote.amount_in,
-            amount_out: quote.amount_out,
-            price_impact_bps: quote.price_impact_bps,
-            minimum_out: quote.minimum_out,
-            transaction: Transaction::default(), // Replace with actual transaction
-        })
+    /// Transaction running `quote` within `accounts` behind compute budget
+    /// instructions
+    fn budgeted_swap(
+        &self,
+        quote: &Quote,
+        user: &Pubkey,
+        accounts: AccountSetup,
+        unit_limit: Option<u32>,
+        micro_lamports: Option<u64>,
+    ) -> Result<VersionedTransaction> {
+        let mut instructions = compute_budget::instructions(unit_limit, micro_lamports);
+        instructions.extend(self.swap_instructions(std::slice::from_ref(quote), user, accounts)?);
+        lookup_table::compile(user, &instructions, &self.state().lookup_tables)
+    }
+
+    /// Instructions running `legs` for `user` between the setup and
+    /// cleanup of `accounts`
+    fn swap_instructions(&self, legs: &[Quote], user: &Pubkey, accounts: AccountSetup) -> Result<Vec<Instruction>> {
+        let mut instructions = accounts.setup;
+        for leg in legs {
+            instructions.extend(self.build_swap_instructions(leg, user)?);
+        }
+        instructions.extend(accounts.cleanup);
+        Ok(instructions)
+    }
+
+    /// Simulate the prepared swap, failing if it errors or delivers less
+    /// than `quote.minimum_out`
+    async fn simulate(&self, rpc: &dyn TransactionSender, quote: &Quote, user: &Pubkey) -> Result<Simulation> {
+        // Unwrapping SOL output would close the account showing what the
+        // swap delivered; closing only returns its lamports to the user
+        let transaction = self.prepare(quote, user, false)?;
+        let accounts = simulation::swap_token_accounts(quote, user);
+        let result = rpc.simulate_transaction(&transaction, &accounts).await?;
+        if let Some(error) = result.error.clone() {
+            let adapters = &self.state().adapters;
+            return Err(simulation::decode_failure(error, &result, quote, &transaction, adapters).into());
+        }
+
+        let output = simulation::simulated_output(&result)?;
+        if output < quote.minimum_out {
+            return Err(crate::AgentSwapError::SlippageExceeded {
+                expected: quote.minimum_out as f64,
+                actual: output as f64,
+            }
+            .into());
+        }
+        Ok(result)
+    }
+
+    fn state(&self) -> Arc<EngineState> {
+        // A full load keeps the snapshot alive across awaits
+        self.shared.state.load_full()
+    }
+
+    /// Publish a copy of the current state with `update` applied and an
+    /// emptied quote cache
+    fn apply(&self, mut update: impl FnMut(&mut EngineState)) {
+        // Concurrent updates rerun `update` against the state that won
+        self.shared.state.rcu(|current| {
+            let mut next = EngineState::clone(current);
+            next.quote_cache = Arc::new(current.quote_cache.emptied());
+            update(&mut next);
+            next
+        });
+    }
+
+    /// Like [`apply`](Self::apply), but publishes nothing if `update`
+    /// fails
+    fn update(&self, mut update: impl FnMut(&mut EngineState) -> Result<()>) -> Result<()> {
+        let mut result = Ok(());
+        // Concurrent updates rerun `update` against the state that won
+        self.shared.state.rcu(|current| {
+            let mut next = EngineState::clone(current);
+            next.quote_cache = Arc::new(current.quote_cache.emptied());
+            result = update(&mut next);
+            match result {
+                Ok(()) => Arc::new(next),
+                Err(_) => Arc::clone(current),
+            }
+        });
+        result
+    }
+}
+
+impl EngineState {
+    fn adapter(&self, dex_type: DexType) -> Result<&Arc<dyn DexAdapter>> {
+        self.adapters
+            .iter()
+            .find(|a| a.dex_type() == dex_type)
+            .ok_or_else(|| anyhow::anyhow!("No adapter registered for {:?}", dex_type))
+    }
+
+    fn slippage_bps(&self, slippage: &Slippage, token_in: &Pubkey, token_out: &Pubkey) -> Result<u16> {
+        slippage.resolve(token_in, token_out, self.volatility.as_deref())
+    }
+
+    async fn best_direct_quote(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage_bps: u16,
+    ) -> Result<QuoteReport> {
+        let (quotes, failures) = self.quote_all(token_in, token_out, |adapter| {
+            adapter.quote(token_in, token_out, amount, slippage_bps)
+        }).await;
+        fanout::best_by_output(quotes, failures)
+    }
+
+    /// `lamports` valued in `token`, zero if no pool prices it
+    async fn rent_in(&self, token: &Pubkey, lamports: u64) -> u64 {
+        if *token == token_accounts::NATIVE_MINT {
+            return lamports;
+        }
+        match self.best_direct_quote(&token_accounts::NATIVE_MINT, token, lamports, 0).await {
+            Ok(report) => report.best.amount_out,
+            Err(e) => {
+                tracing::debug!("Cannot value rent in {}: {}", token, e);
+                0
+            }
+        }
+    }
+
+    /// Run `quote` on every adapter with a pool for the pair at once
+    async fn quote_all<'a>(
+        &'a self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        quote: impl Fn(&'a dyn DexAdapter) -> BoxFuture<'a, Result<Quote>>,
+    ) -> (Vec<Quote>, Vec<VenueFailure>) {
+        let adapters = self.adapters.iter().map(|a| a.as_ref());
+        fanout::quote_all(adapters, token_in, token_out, self.quote_timeout, quote).await
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
+    use discovery::tests::FixtureRpc;
+    use anchor_client::solana_sdk::{instruction::InstructionError, system_program, transaction::TransactionError};
+    use execution::tests::MockSender;
+    use layout::token_account;
+    use adapter::tests::{Behaviour, Stub};
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    /// Engine with the fixture pools loaded: SOL/USDC on both DEXes and
+    /// RAY/USDC on Raydium
+    async fn fixture_engine(rpc: &FixtureRpc) -> SwapEngine {
+        let mut raydium = RaydiumClient::new().unwrap();
+        raydium.discover_pools(rpc, &PoolFilter::All).await.unwrap();
+        let mut orca = OrcaClient::new().unwrap();
+        orca.discover_whirlpools(rpc, &PoolFilter::All).await.unwrap();
+
+        SwapEngine::with_adapters(vec![Box::new(raydium), Box::new(orca)]).unwrap()
+    }
+
+    /// Venue quoting `rate` units of USDC per unit of SOL
+    fn fixed_rate(rate: u64) -> Box<dyn DexAdapter> {
+        let pair = (SOL.parse().unwrap(), USDC.parse().unwrap());
+        Box::new(Stub { name: "fixed", pair, behaviour: Behaviour::Rate(rate) })
+    }
 
     #[tokio::test]
     async fn test_best_quote() {
-        let mut engine = SwapEngine::new().unwrap();
-        let token_in = Keypair::new().pubkey();
-        let token_out = Keypair::new().pubkey();
-        
-        let quote = engine.get_best_quote(&token_in, &token_out, 1000000)
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let token_in: Pubkey = SOL.parse().unwrap();
+        let token_out: Pubkey = USDC.parse().unwrap();
+
+        let quote = engine.get_best_quote(&token_in, &token_out, 100_000, Slippage::default())
             .await
             .unwrap();
-            
+
         assert!(quote.amount_out > 0);
         assert!(quote.price_impact_bps < 1000); // Less than 10%
+        for dex_type in [DexType::Raydium, DexType::Orca] {
+            let single = engine.get_quote(dex_type, &token_in, &token_out, 100_000, Slippage::default()).await.unwrap();
+            assert!(quote.amount_out >= single.amount_out);
+        }
+
+        let user = Pubkey::new_unique();
+        let instructions = engine.build_swap_instructions(&quote, &user).unwrap();
+        assert_eq!(instructions.len(), 1);
+
+        // Pairs without pools on any venue have no quote
+        let unknown = Pubkey::new_unique();
+        assert!(engine.get_best_quote(&token_in, &unknown, 1_000_000, Slippage::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_best_quote_exact_out() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let token_in: Pubkey = SOL.parse().unwrap();
+        let token_out: Pubkey = USDC.parse().unwrap();
+
+        // Exactly 100 USDC
+        let quote = engine.get_best_quote_exact_out(&token_in, &token_out, 100_000_000, Slippage::default())
+            .await
+            .unwrap();
+
+        assert_eq!(quote.mode, SwapMode::ExactOut);
+        assert_eq!(quote.amount_out, 100_000_000);
+        assert!(quote.maximum_in >= quote.amount_in);
+        for dex_type in [DexType::Raydium, DexType::Orca] {
+            let single = engine.get_quote_exact_out(dex_type, &token_in, &token_out, 100_000_000, Slippage::default()).await.unwrap();
+            assert!(quote.amount_in <= single.amount_in);
+        }
+
+        let user = Pubkey::new_unique();
+        let instructions = engine.build_swap_instructions(&quote, &user).unwrap();
+        assert_eq!(instructions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_custom_adapter() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+
+        engine.register_adapter(fixed_rate(1_000)).unwrap();
+        assert!(engine.register_adapter(fixed_rate(1)).is_err());
+        assert_eq!(
+            engine.dex_types(),
+            vec![DexType::Raydium, DexType::Orca, DexType::Custom("fixed")],
+        );
+
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(quote.dex_type, DexType::Custom("fixed"));
+        assert_eq!(quote.amount_out, 100_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+
+        // Without tick arrays the whirlpool quotes as if liquidity never ends
+        assert!(engine.get_quote(DexType::Orca, &sol, &usdc, 100_000_000_000_000, Slippage::default()).await.is_ok());
+
+        engine.refresh(&rpc).await.unwrap();
+        assert!(engine.get_quote(DexType::Orca, &sol, &usdc, 100_000_000_000_000, Slippage::default()).await.is_err());
+        assert!(engine.get_quote(DexType::Raydium, &sol, &usdc, 100_000, Slippage::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_multi_hop_route() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let ray: Pubkey = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R".parse().unwrap();
+
+        // No direct RAY/SOL pool
+        assert!(engine.get_best_quote(&ray, &sol, 1_000, Slippage::default()).await.is_err());
+
+        let route = engine.get_best_route(&ray, &sol, 1_000, Slippage::default()).await.unwrap();
+        assert_eq!(route.path(), vec![ray, usdc, sol]);
+        assert_eq!(route.legs[1].amount_in, route.legs[0].amount_out);
+        assert!(route.amount_out() > 0);
+
+        // Direct pools are still routed directly
+        let route = engine.get_best_route(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(route.hops(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_route() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let ray: Pubkey = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R".parse().unwrap();
+        let route = engine.get_best_route(&ray, &sol, 1_000_000, Slippage::default()).await.unwrap();
+
+        let user = Pubkey::new_unique();
+        engine.register_lookup_table(AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: engine.lookup_table_candidates(&route.legs, &user).unwrap(),
+        });
+        let tx = engine.prepare_route(&route, &user).unwrap();
+        // Creating the USDC and wrapped SOL accounts, the legs, unwrapping
+        assert_eq!(tx.message.instructions().len(), 2 + 2 + 1);
+        assert_eq!(tx.message.static_account_keys()[0], user);
+
+        // The second leg spends only the first leg's minimum output
+        let legs = route.bounded_legs().unwrap();
+        assert_eq!(legs[1].amount_in, route.legs[0].minimum_out);
+        assert!(legs[1].amount_in < route.legs[1].amount_in);
+        let second = engine.build_swap_instructions(&legs[1], &user).unwrap();
+        assert!(tx.message.instructions().iter().any(|ix| ix.data == second[0].data));
+    }
+
+    #[tokio::test]
+    async fn test_split_across_dexes() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+
+        for amount in [1_000_000, 1_000_000_000, 5_000_000_000_000] {
+            let split = engine.get_best_split(&sol, &usdc, amount, Slippage::default()).await.unwrap();
+            let single = engine.state().best_direct_quote(&sol, &usdc, amount, DEFAULT_SLIPPAGE_BPS).await.unwrap().best;
+            assert_eq!(split.amount_in(), amount);
+            assert!(split.amount_out() >= single.amount_out);
+        }
+
+        // 5k SOL moves both pools enough to be worth splitting
+        let split = engine.get_best_split(&sol, &usdc, 5_000_000_000_000, Slippage::default()).await.unwrap();
+        assert_eq!(split.legs.len(), 2);
+        let single = engine.state().best_direct_quote(&sol, &usdc, 5_000_000_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap().best;
+        assert!(split.amount_out() > single.amount_out);
+
+        let user = Pubkey::new_unique();
+        engine.register_lookup_table(AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: engine.lookup_table_candidates(&split.legs, &user).unwrap(),
+        });
+        let tx = engine.prepare_split_swap(&split, &user).unwrap();
+        // Wrapping SOL for both legs, creating the USDC account, the legs,
+        // unwrapping
+        assert_eq!(tx.message.instructions().len(), 3 + 1 + 2 + 1);
+        assert_eq!(tx.message.static_account_keys()[0], user);
+    }
+
+    #[tokio::test]
+    async fn test_route_rent() {
+        let sol = NATIVE_MINT;
+        let (usdc, bonk) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let venue = |name, pair, amount_out| -> Box<dyn DexAdapter> {
+            Box::new(Stub { name, pair, behaviour: Behaviour::Quote(amount_out) })
+        };
+        let engine = SwapEngine::with_adapters(vec![
+            venue("direct", (sol, bonk), 1_000_000),
+            venue("first", (sol, usdc), 500),
+            venue("second", (usdc, bonk), 1_000_500),
+        ])
+        .unwrap();
+
+        // Going through USDC pays more BONK but needs a USDC account, whose
+        // rent is worth more BONK than it gains
+        let route = engine.get_best_route(&sol, &bonk, 1_000, Slippage::default()).await.unwrap();
+        assert_eq!(route.path(), vec![sol, bonk]);
+    }
+
+    #[tokio::test]
+    async fn test_route_not_found() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let orphan = Pubkey::new_unique();
+
+        let err = engine.get_best_route(&sol, &orphan, 1_000, Slippage::default()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AgentSwapError>(),
+            Some(crate::AgentSwapError::RouteNotFound { .. }),
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_partial_quotes() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let pair = (sol, usdc);
+        engine.register_adapter(Box::new(Stub { name: "stalled", pair, behaviour: Behaviour::Stall })).unwrap();
+        engine.register_adapter(Box::new(Stub { name: "broken", pair, behaviour: Behaviour::Fail })).unwrap();
+        engine.set_quote_timeout(Duration::from_millis(50));
+
+        // The healthy venues still quote
+        let report = engine.get_best_quote_report(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert!(matches!(report.best.dex_type, DexType::Raydium | DexType::Orca));
+        let failed: Vec<_> = report.failures.iter().map(|f| f.dex_type).collect();
+        assert_eq!(failed, vec![DexType::Custom("stalled"), DexType::Custom("broken")]);
+        assert_eq!(report.failures[0].error, VenueError::Timeout(Duration::from_millis(50)));
+
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(quote.amount_out, report.best.amount_out);
+        let route = engine.get_best_route(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(route.amount_out(), report.best.amount_out);
+        assert!(engine.get_best_quote_exact_out(&sol, &usdc, 100_000_000, Slippage::default()).await.is_ok());
+
+        // Venues alone on a pair fail with their reasons
+        let ray: Pubkey = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R".parse().unwrap();
+        let lone = SwapEngine::with_adapters(vec![
+            Box::new(Stub { name: "broken", pair: (ray, sol), behaviour: Behaviour::Fail }),
+        ]).unwrap();
+        let err = lone.get_best_quote(&ray, &sol, 1_000, Slippage::default()).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<NoQuotes>().unwrap().failures[0].dex_type, DexType::Custom("broken"));
+    }
+
+    #[tokio::test]
+    async fn test_execute_swap() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let wallet = Keypair::new();
+
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        let mut sender = MockSender::confirming(1_234);
+        sender.simulation = Simulation {
+            pre_accounts: vec![Some(token_account(100_000)), None],
+            post_accounts: vec![Some(token_account(0)), Some(token_account(quote.amount_out))],
+            ..Simulation::default()
+        };
+        let receipt = engine.execute_swap(&sender, &quote, &wallet).await.unwrap();
+        assert_eq!(receipt.slot, 1_234);
+
+        let mut expected = engine.prepare_swap(&quote, &wallet.pubkey()).unwrap().message;
+        expected.set_recent_blockhash(sender.blockhash);
+        {
+            let sent = sender.sent.lock().unwrap();
+            assert_eq!(sent[0].signatures[0], receipt.signature);
+            assert_eq!(sent[0].message, expected);
+        }
+
+        // The swap creates the destination account only if the simulation
+        // did not find it
+        let usdc_account = anchor_spl::associated_token::get_associated_token_address(&wallet.pubkey(), &usdc);
+        let creates = |tx: &VersionedTransaction| {
+            let keys = tx.message.static_account_keys();
+            tx.message
+                .instructions()
+                .iter()
+                .filter(|ix| *ix.program_id(keys) == anchor_spl::associated_token::ID)
+                .filter(|ix| keys.get(ix.accounts[1] as usize) == Some(&usdc_account))
+                .count()
+        };
+        sender.simulation.pre_accounts[1] = Some(token_account(5));
+        sender.simulation.post_accounts[1] = Some(token_account(quote.amount_out + 5));
+        engine.execute_swap(&sender, &quote, &wallet).await.unwrap();
+        let sent = sender.sent.lock().unwrap();
+        assert_eq!(creates(&sent[0]), 1);
+        assert_eq!(creates(&sent[1]), 0);
+        assert_eq!(sent[1].message.instructions().len(), sent[0].message.instructions().len() - 1);
+    }
+
+    #[tokio::test]
+    async fn test_resend_expired_swap() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let wallet = Keypair::new();
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::Fixed(50)).await.unwrap();
+        let simulation = Simulation {
+            pre_accounts: vec![Some(token_account(100_000)), Some(token_account(0))],
+            post_accounts: vec![Some(token_account(0)), Some(token_account(quote.amount_out))],
+            ..Simulation::default()
+        };
+
+        // The first transaction expires unseen, the re-quoted one lands
+        let expired = anchor_client::solana_sdk::hash::Hash::new_unique();
+        let mut sender = MockSender::new(vec![SignatureStatus::Pending, SignatureStatus::Confirmed { slot: 9 }]);
+        sender.simulation = simulation.clone();
+        sender.expired_blockhashes.lock().unwrap().push_back(expired);
+        let receipt = engine.execute_swap(&sender, &quote, &wallet).await.unwrap();
+        assert_eq!(receipt.slot, 9);
+
+        {
+            let sent = sender.sent.lock().unwrap();
+            assert_eq!(sent.len(), 2);
+            assert_eq!(*sent[0].message.recent_blockhash(), expired);
+            assert_eq!(*sent[1].message.recent_blockhash(), sender.blockhash);
+            assert_eq!(sent[1].signatures[0], receipt.signature);
+            // Same swap and bounds, fresh signature
+            assert_ne!(sent[0].signatures[0], sent[1].signatures[0]);
+            assert_eq!(sent[0].message.instructions(), sent[1].message.instructions());
+        }
+
+        // Out of time, the expiry is reported instead of sending again
+        engine.set_confirm_timeout(Duration::ZERO);
+        let mut sender = MockSender::new(vec![SignatureStatus::Pending, SignatureStatus::Confirmed { slot: 9 }]);
+        sender.simulation = simulation;
+        sender.expired_blockhashes.lock().unwrap().push_back(expired);
+        let err = engine.execute_swap(&sender, &quote, &wallet).await.unwrap_err();
+        assert!(err.is::<BlockhashExpired>());
+        assert_eq!(sender.sent.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expiry_outlasts_confirm_timeout() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let wallet = Keypair::new();
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::Fixed(50)).await.unwrap();
+        let never_lands = |block_time| {
+            let mut sender = MockSender::new(vec![SignatureStatus::Pending]);
+            sender.simulation = Simulation {
+                pre_accounts: vec![Some(token_account(100_000)), Some(token_account(0))],
+                post_accounts: vec![Some(token_account(0)), Some(token_account(quote.amount_out))],
+                ..Simulation::default()
+            };
+            sender.block_time = block_time;
+            sender
+        };
+        let signatures = |sender: &MockSender| {
+            let mut signatures: Vec<_> = sender.sent.lock().unwrap().iter().map(|tx| tx.signatures[0]).collect();
+            signatures.dedup();
+            signatures
+        };
+        let slot = Some(execution::CONFIRM_POLL_INTERVAL);
+
+        // Expiry shows only after the default timeout, and is still
+        // reported rather than a bare timeout
+        engine.set_confirm_timeout(DEFAULT_CONFIRM_TIMEOUT);
+        let sender = never_lands(slot);
+        let start = Instant::now();
+        let err = engine.execute_swap(&sender, &quote, &wallet).await.unwrap_err();
+        let expired = err.downcast_ref::<BlockhashExpired>().unwrap();
+        assert!(start.elapsed() > DEFAULT_CONFIRM_TIMEOUT);
+        assert_eq!(signatures(&sender), [expired.signature]);
+
+        // Expiring within a longer timeout re-quotes once, and the resend
+        // is only given up on once it expires in turn
+        let first_expiry = start.elapsed();
+        engine.set_confirm_timeout(DEFAULT_CONFIRM_TIMEOUT * 2);
+        let sender = never_lands(slot);
+        let start = Instant::now();
+        let err = engine.execute_swap(&sender, &quote, &wallet).await.unwrap_err();
+        assert!(err.is::<BlockhashExpired>());
+        assert!(start.elapsed() >= first_expiry * 2);
+        assert!(start.elapsed() < first_expiry * 3);
+
+        // Without a finalized chain to show expiry, the swap may still land
+        engine.set_confirm_timeout(DEFAULT_CONFIRM_TIMEOUT);
+        let sender = never_lands(None);
+        let start = Instant::now();
+        let err = engine.execute_swap(&sender, &quote, &wallet).await.unwrap_err();
+        let unknown = err.downcast_ref::<OutcomeUnknown>().unwrap();
+        assert!(start.elapsed() >= EXPIRY_TIMEOUT - execution::CONFIRM_POLL_INTERVAL);
+        assert_eq!(signatures(&sender), [unknown.signature]);
+    }
+
+    #[tokio::test]
+    async fn test_simulation_rejects_swap() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let wallet = Keypair::new();
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+
+        // Output below the minimum
+        let mut sender = MockSender::confirming(1);
+        sender.simulation = Simulation {
+            pre_accounts: vec![Some(token_account(100_000)), Some(token_account(5))],
+            post_accounts: vec![Some(token_account(0)), Some(token_account(quote.minimum_out + 4))],
+            ..Simulation::default()
+        };
+        let err = engine.execute_swap(&sender, &quote, &wallet).await.unwrap_err();
+        match err.downcast_ref::<crate::AgentSwapError>() {
+            Some(crate::AgentSwapError::SlippageExceeded { expected, actual }) => {
+                assert_eq!(*expected, quote.minimum_out as f64);
+                assert_eq!(*actual, (quote.minimum_out - 1) as f64);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(sender.sent.lock().unwrap().is_empty());
+
+        // Raydium's `ExceededSlippage`, reported by the failing program
+        let program = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+        sender.simulation = Simulation {
+            error: Some(TransactionError::InstructionError(0, InstructionError::Custom(30))),
+            logs: vec![format!("Program {} failed: custom program error: 0x1e", program)],
+            ..Simulation::default()
+        };
+        let err = engine.execute_swap(&sender, &quote, &wallet).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AgentSwapError>(),
+            Some(crate::AgentSwapError::SlippageToleranceExceeded { dex_type: DexType::Raydium }),
+        ));
+        assert!(sender.sent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_native_sol() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let wallet = Keypair::new();
+        let user = wallet.pubkey();
+        let wsol = anchor_spl::associated_token::get_associated_token_address(&user, &NATIVE_MINT);
+        let program_ids = |tx: &VersionedTransaction| -> Vec<Pubkey> {
+            let keys = tx.message.static_account_keys();
+            tx.message.instructions().iter().map(|ix| *ix.program_id(keys)).collect()
+        };
+        assert_eq!(sol, NATIVE_MINT);
+
+        // SOL in: fund and sync the wrapped account, then close it
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        let tx = engine.prepare_swap(&quote, &user).unwrap();
+        let ids = program_ids(&tx);
+        assert_eq!(ids[..3], [anchor_spl::associated_token::ID, system_program::ID, anchor_spl::token::ID]);
+        assert_eq!(ids.last(), Some(&anchor_spl::token::ID));
+        assert_eq!(tx.message.instructions()[1].data[4..], 100_000u64.to_le_bytes());
+
+        // SOL out: create the wrapped account, then unwrap the output. The
+        // simulation stops short of closing it to read the output.
+        let quote = engine.get_best_quote(&usdc, &sol, 10_000_000, Slippage::default()).await.unwrap();
+        let mut sender = MockSender::confirming(1);
+        sender.simulation = Simulation {
+            pre_accounts: vec![Some(token_account(10_000_000)), None],
+            post_accounts: vec![Some(token_account(0)), Some(token_account(quote.amount_out))],
+            ..Simulation::default()
+        };
+        assert_eq!(engine.simulate_swap(&sender, &quote, &user).await.unwrap(), quote.amount_out);
+        engine.execute_swap(&sender, &quote, &wallet).await.unwrap();
+
+        let sent = sender.sent.lock().unwrap();
+        let ids = program_ids(&sent[0]);
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[0], anchor_spl::associated_token::ID);
+        let close = sent[0].message.instructions().last().unwrap();
+        assert_eq!(sent[0].message.static_account_keys()[close.accounts[0] as usize], wsol);
+    }
+
+    #[tokio::test]
+    async fn test_compute_budget() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let wallet = Keypair::new();
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        let legs = std::slice::from_ref(&quote);
+        let accounts = token_accounts::account_setup(legs, &wallet.pubkey(), &[]);
+        let swap = engine.swap_instructions(legs, &wallet.pubkey(), accounts).unwrap();
+
+        // Fixed settings apply when preparing
+        engine.set_compute_budget(ComputeBudget {
+            unit_limit: ComputeUnitLimit::Fixed(300_000),
+            priority_fee: PriorityFee::Fixed(5_000),
+        });
+        let tx = engine.prepare_swap(&quote, &wallet.pubkey()).unwrap();
+        let ixs = [compute_budget::instructions(Some(300_000), Some(5_000)), swap.clone()].concat();
+        let expected = lookup_table::compile(&wallet.pubkey(), &ixs, &[]).unwrap();
+        assert_eq!(tx.message, expected.message);
+
+        // Simulated limits and estimated fees resolve when executing
+        engine.set_compute_budget(ComputeBudget {
+            unit_limit: ComputeUnitLimit::Simulated { margin_bps: DEFAULT_COMPUTE_UNIT_MARGIN_BPS },
+            priority_fee: PriorityFee::Estimated { percentile: 75, max_micro_lamports: 20_000 },
+        });
+        let tx = engine.prepare_swap(&quote, &wallet.pubkey()).unwrap();
+        assert_eq!(tx.message.instructions().len(), swap.len() + 1);
+
+        let mut sender = MockSender::confirming(1);
+        sender.simulation = Simulation {
+            units_consumed: Some(60_000),
+            pre_accounts: vec![Some(token_account(100_000)), None],
+            post_accounts: vec![Some(token_account(0)), Some(token_account(quote.amount_out))],
+            ..Simulation::default()
+        };
+        sender.prioritization_fees = vec![0, 1_000, 4_000, 8_000];
+        engine.execute_swap(&sender, &quote, &wallet).await.unwrap();
+        let ixs = [compute_budget::instructions(Some(66_000), Some(4_000)), swap.clone()].concat();
+        let mut expected = lookup_table::compile(&wallet.pubkey(), &ixs, &[]).unwrap().message;
+        expected.set_recent_blockhash(sender.blockhash);
+        assert_eq!(sender.sent.lock().unwrap()[0].message, expected);
+
+        // Estimates are capped
+        sender.prioritization_fees = vec![50_000];
+        engine.execute_swap(&sender, &quote, &wallet).await.unwrap();
+        let sent = sender.sent.lock().unwrap();
+        assert_eq!(sent[1].message.instructions()[1].data, compute_budget::instructions(None, Some(20_000))[0].data);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_tables() {
+        let mut rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+        let user = Pubkey::new_unique();
+
+        let split = engine.get_best_split(&sol, &usdc, 5_000_000_000_000, Slippage::default()).await.unwrap();
+        let err = engine.prepare_split_swap(&split, &user).unwrap_err();
+        assert!(err.to_string().contains("add its accounts to a lookup table"));
+        let candidates = engine.lookup_table_candidates(&split.legs, &user).unwrap();
+        assert!(!candidates.is_empty());
+        assert!(!candidates.contains(&user));
+
+        let table = Pubkey::new_unique();
+        let program = anchor_client::solana_sdk::address_lookup_table::program::ID;
+        rpc.insert_account(table, program, lookup_table::tests::table_data(&candidates));
+        engine.load_lookup_tables(&rpc, &[table]).await.unwrap();
+        assert_eq!(engine.lookup_tables()[0].addresses, candidates);
+        assert!(engine.lookup_table_candidates(&split.legs, &user).unwrap().is_empty());
+
+        // Tabled accounts shrink the transaction to their one-byte indexes
+        let tabled = engine.prepare_split_swap(&split, &user).unwrap();
+        assert_eq!(tabled.message.address_table_lookups().unwrap()[0].account_key, table);
+
+        assert!(engine.load_lookup_tables(&rpc, &[Pubkey::new_unique()]).await.is_err());
+    }
+
+    /// Volatility observed for every pair
+    struct Observed(u16);
+
+    impl VolatilitySource for Observed {
+        fn volatility_bps(&self, _token_in: &Pubkey, _token_out: &Pubkey) -> Option<u16> {
+            Some(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slippage() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        engine.register_adapter(fixed_rate(1_000)).unwrap();
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::Fixed(50)).await.unwrap();
+        assert_eq!(quote.minimum_out, 99_500_000);
+        // Tolerance is part of the cache key
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::Fixed(300)).await.unwrap();
+        assert_eq!(quote.minimum_out, 97_000_000);
+
+        let quote = engine.get_best_quote_exact_out(&sol, &usdc, 100_000_000, Slippage::Fixed(200)).await.unwrap();
+        assert_eq!(quote.maximum_in, slippage::maximum_in(quote.amount_in, 200).unwrap());
+
+        // Dynamic tolerance follows the observed volatility
+        let dynamic = Slippage::Dynamic { base_bps: 100, min_bps: 10, max_bps: 500 };
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, dynamic).await.unwrap();
+        assert_eq!(quote.minimum_out, 99_000_000);
+        engine.set_volatility_source(Arc::new(Observed(150)));
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, dynamic).await.unwrap();
+        assert_eq!(quote.minimum_out, 97_000_000);
+
+        assert!(engine.get_best_quote(&sol, &usdc, 100_000, Slippage::Fixed(10_001)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_quote_caching() {
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        let cached = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(cached.amount_out, quote.amount_out);
+        assert_eq!((engine.cache_stats().hits, engine.cache_stats().misses), (1, 1));
+
+        // Quotes go stale once the chain moves past the slot lag
+        let max_slot_lag = CachePolicy::default().max_slot_lag;
+        engine.observe_slot(max_slot_lag + 1);
+        engine.observe_slot(0);
+        assert_eq!(engine.current_slot(), max_slot_lag + 1);
+        engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(engine.cache_stats().expirations, 1);
+
+        // And when their pool changes
+        engine.invalidate_pool(&quote.pool);
+        engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(engine.cache_stats().invalidations, 1);
+        assert_eq!(engine.cache_stats().misses, 3);
+
+        // Refreshing reloads every pool at the node's slot
+        rpc.set_slot(500);
+        engine.refresh(&rpc).await.unwrap();
+        assert_eq!(engine.current_slot(), 500);
+        assert_eq!(engine.cache_stats().entries, 0);
+
+        engine.set_cache_policy(CachePolicy { capacity: 0, ..CachePolicy::default() });
+        engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(engine.cache_stats().hits, 0);
+        assert_eq!(engine.cache_stats().entries, 0);
     }
 
-    #[test]
-    fn test_quote_caching() {
-        // Add cache test implementation
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_shared_engine() {
+        fn assert_shared<T: Clone + Send + Sync + 'static>() {}
+        assert_shared::<SwapEngine>();
+
+        let rpc = FixtureRpc::new();
+        let engine = fixture_engine(&rpc).await;
+        let sol: Pubkey = SOL.parse().unwrap();
+        let usdc: Pubkey = USDC.parse().unwrap();
+
+        // Quotes keep flowing while pools are refreshed
+        let handles: Vec<_> = (0..8u64)
+            .map(|i| {
+                let engine = engine.clone();
+                tokio::spawn(async move {
+                    engine.get_best_quote(&sol, &usdc, 100_000 * (i % 2 + 1), Slippage::default()).await
+                })
+            })
+            .collect();
+        engine.refresh(&rpc).await.unwrap();
+        for handle in handles {
+            assert!(handle.await.unwrap().unwrap().amount_out > 0);
+        }
+
+        // Clones share adapters and the quote cache
+        let clone = engine.clone();
+        clone.register_adapter(fixed_rate(1_000)).unwrap();
+        assert_eq!(engine.dex_types().len(), 3);
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(quote.dex_type, DexType::Custom("fixed"));
+        let hits = engine.cache_stats().hits;
+        clone.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(engine.cache_stats().hits, hits + 1);
+
+        clone.replace_adapter(fixed_rate(2_000)).unwrap();
+        let quote = engine.get_best_quote(&sol, &usdc, 100_000, Slippage::default()).await.unwrap();
+        assert_eq!(quote.amount_out, 200_000_000);
+
+        let empty = SwapEngine::with_adapters(Vec::new()).unwrap();
+        assert!(empty.replace_adapter(fixed_rate(1)).is_err());
     }
-}
\ No newline at end of file
+}
@@ -4,15 +4,181 @@
 //! and provides quote calculation and swap execution.
 
 use anchor_client::solana_sdk::{
-    instruction::Instruction,
+    instruction::{AccountMeta, Instruction},
+    pubkey,
     pubkey::Pubkey,
-    system_program,
-    sysvar,
-    transaction::Transaction,
 };
-use anchor_spl::token::{self, Token};
+use anchor_spl::{
+    associated_token::{get_associated_token_address, get_associated_token_address_with_program_id},
+    token,
+};
 use anyhow::Result;
 use std::collections::HashMap;
+use tracing::debug;
+
+use super::{
+    adapter::{DexAdapter, SwapFailure},
+    discovery::{self, AccountProvider, PoolFilter},
+    layout::{read_i128, read_i32, read_pubkey, read_u128, read_u16, read_u64, read_u8},
+    math,
+    mint::MintInfo,
+    slippage,
+    whirlpool_math::{self, PoolSnapshot, SwapResult, Tick, TickSequence},
+    DexType, Quote, SwapMode,
+};
+
+/// Anchor discriminator of the `Whirlpool` account
+const WHIRLPOOL_DISCRIMINATOR: [u8; 8] = [63, 149, 209, 12, 225, 128, 99, 9];
+
+/// Anchor discriminator of the `swap` instruction
+const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+/// Anchor discriminator of the `swap_v2` instruction
+const SWAP_V2_DISCRIMINATOR: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+
+/// SPL Memo program, required by `swap_v2` for Token-2022 transfer memos
+const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TyNNNH8uPavpgETxCEuXrG");
+
+/// Anchor discriminator of the `TickArray` account
+const TICK_ARRAY_DISCRIMINATOR: [u8; 8] = [69, 97, 189, 190, 110, 7, 66, 187];
+
+/// Number of ticks in a tick array
+pub const TICK_ARRAY_SIZE: i32 = 88;
+
+/// Number of tick arrays a swap instruction can traverse
+pub const MAX_SWAP_TICK_ARRAYS: usize = 3;
+
+/// Anchor `ErrorCode` codes of the Whirlpool program
+const ERROR_SQRT_PRICE_OUT_OF_BOUNDS: u32 = 6011;
+const ERROR_LIQUIDITY_ZERO: u32 = 6012;
+const ERROR_INVALID_TICK_ARRAY_SEQUENCE: u32 = 6023;
+const ERROR_ZERO_TRADABLE_AMOUNT: u32 = 6035;
+const ERROR_AMOUNT_OUT_BELOW_MINIMUM: u32 = 6036;
+const ERROR_AMOUNT_IN_ABOVE_MAXIMUM: u32 = 6037;
+
+/// Seed of the `TickArray` PDA
+const TICK_ARRAY_SEED: &[u8] = b"tick_array";
+
+/// Seed of the `Oracle` PDA
+const ORACLE_SEED: &[u8] = b"oracle";
+
+/// Size of a `TickArray` account
+pub const TICK_ARRAY_LEN: usize = 9988;
+
+/// Number of reward slots in a whirlpool
+pub const NUM_REWARDS: usize = 3;
+
+/// Size of a `Whirlpool` account
+pub const WHIRLPOOL_LEN: usize = 653;
+
+// `Whirlpool` field offsets (including the 8-byte discriminator)
+const WHIRLPOOL_TICK_SPACING_OFFSET: usize = 41;
+const WHIRLPOOL_FEE_RATE_OFFSET: usize = 45;
+const WHIRLPOOL_PROTOCOL_FEE_RATE_OFFSET: usize = 47;
+const WHIRLPOOL_LIQUIDITY_OFFSET: usize = 49;
+const WHIRLPOOL_SQRT_PRICE_OFFSET: usize = 65;
+const WHIRLPOOL_TICK_CURRENT_INDEX_OFFSET: usize = 81;
+const WHIRLPOOL_PROTOCOL_FEE_OWED_A_OFFSET: usize = 85;
+const WHIRLPOOL_PROTOCOL_FEE_OWED_B_OFFSET: usize = 93;
+pub(crate) const WHIRLPOOL_TOKEN_MINT_A_OFFSET: usize = 101;
+const WHIRLPOOL_TOKEN_VAULT_A_OFFSET: usize = 133;
+const WHIRLPOOL_FEE_GROWTH_GLOBAL_A_OFFSET: usize = 165;
+pub(crate) const WHIRLPOOL_TOKEN_MINT_B_OFFSET: usize = 181;
+const WHIRLPOOL_TOKEN_VAULT_B_OFFSET: usize = 213;
+const WHIRLPOOL_FEE_GROWTH_GLOBAL_B_OFFSET: usize = 245;
+const WHIRLPOOL_REWARD_LAST_UPDATED_OFFSET: usize = 261;
+const WHIRLPOOL_REWARD_INFOS_OFFSET: usize = 269;
+const WHIRLPOOL_REWARD_INFO_LEN: usize = 128;
+
+// `TickArray` field offsets (including the 8-byte discriminator)
+const TICK_ARRAY_START_TICK_INDEX_OFFSET: usize = 8;
+const TICK_ARRAY_TICKS_OFFSET: usize = 12;
+const TICK_ARRAY_WHIRLPOOL_OFFSET: usize = 9956;
+const TICK_LEN: usize = 113;
+
+/// Whirlpool reward emission information
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WhirlpoolRewardInfo {
+    /// Reward token mint, default if the slot is unused
+    pub mint: Pubkey,
+    /// Reward token vault
+    pub vault: Pubkey,
+    /// Authority allowed to configure the reward
+    pub authority: Pubkey,
+    /// Reward tokens emitted per second (Q64.64)
+    pub emissions_per_second_x64: u128,
+    /// Reward growth per unit of liquidity (Q64.64)
+    pub growth_global_x64: u128,
+}
+
+impl WhirlpoolRewardInfo {
+    /// Whether this reward slot is in use
+    pub fn initialized(&self) -> bool {
+        self.mint != Pubkey::default()
+    }
+}
+
+/// Decoded `TickArray` account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickArray {
+    /// Tick array address
+    pub address: Pubkey,
+    /// Whirlpool the array belongs to
+    pub whirlpool: Pubkey,
+    /// Index of the first tick in the array
+    pub start_tick_index: i32,
+    /// Initialized ticks in the array, sorted by index
+    pub ticks: Vec<Tick>,
+}
+
+impl TickArray {
+    /// Decode a `TickArray` account for a whirlpool with the given tick spacing
+    pub fn decode(address: Pubkey, data: &[u8], tick_spacing: u16) -> Result<Self> {
+        if data.len() != TICK_ARRAY_LEN {
+            anyhow::bail!("Invalid TickArray account: expected {} bytes, got {}", TICK_ARRAY_LEN, data.len());
+        }
+        if data[..8] != TICK_ARRAY_DISCRIMINATOR {
+            anyhow::bail!("Invalid TickArray account: discriminator mismatch");
+        }
+
+        let start_tick_index = read_i32(data, TICK_ARRAY_START_TICK_INDEX_OFFSET)?;
+        let mut ticks = Vec::new();
+        for i in 0..TICK_ARRAY_SIZE {
+            let offset = TICK_ARRAY_TICKS_OFFSET + i as usize * TICK_LEN;
+            if read_u8(data, offset)? != 0 {
+                ticks.push(Tick {
+                    index: start_tick_index + i * tick_spacing as i32,
+                    liquidity_net: read_i128(data, offset + 1)?,
+                });
+            }
+        }
+
+        Ok(Self {
+            address,
+            whirlpool: read_pubkey(data, TICK_ARRAY_WHIRLPOOL_OFFSET)?,
+            start_tick_index,
+            ticks,
+        })
+    }
+
+    /// Whirlpool a `TickArray` account belongs to, without decoding ticks
+    pub fn whirlpool_of(data: &[u8]) -> Result<Pubkey> {
+        if data.len() != TICK_ARRAY_LEN || data[..8] != TICK_ARRAY_DISCRIMINATOR {
+            anyhow::bail!("Invalid TickArray account");
+        }
+        read_pubkey(data, TICK_ARRAY_WHIRLPOOL_OFFSET)
+    }
+}
+
+/// Start index of the tick array containing `tick_index`.
+///
+/// Arrays are aligned to multiples of `88 * tick_spacing`, rounding
+/// towards negative infinity so negative ticks land in the array below
+/// zero rather than the one at zero.
+pub fn tick_array_start_index(tick_index: i32, tick_spacing: u16) -> i32 {
+    let ticks_per_array = TICK_ARRAY_SIZE * tick_spacing as i32;
+    tick_index.div_euclid(ticks_per_array) * ticks_per_array
+}
 
 /// Whirlpool state information
 #[derive(Debug, Clone)]
@@ -27,20 +193,242 @@ pub struct WhirlpoolState {
     pub tick_current_index: i32,
     /// Tick spacing
     pub tick_spacing: u16,
-    /// Fee rate (in basis points)
+    /// Fee rate (in hundredths of a basis point)
     pub fee_rate: u16,
-    /// Protocol fee rate (in basis points)
+    /// Protocol share of the fee (in basis points)
     pub protocol_fee_rate: u16,
     /// Liquidity
     pub liquidity: u128,
+    /// Current sqrt price (Q64.64)
+    pub sqrt_price: u128,
+    /// Token A vault
+    pub token_vault_a: Pubkey,
+    /// Token B vault
+    pub token_vault_b: Pubkey,
+    /// Protocol fees owed in token A
+    pub protocol_fee_owed_a: u64,
+    /// Protocol fees owed in token B
+    pub protocol_fee_owed_b: u64,
+    /// Fee growth per unit of liquidity in token A (Q64.64)
+    pub fee_growth_global_a: u128,
+    /// Fee growth per unit of liquidity in token B (Q64.64)
+    pub fee_growth_global_b: u128,
+    /// Last time rewards were updated (unix timestamp)
+    pub reward_last_updated_timestamp: u64,
+    /// Reward emissions
+    pub reward_infos: [WhirlpoolRewardInfo; NUM_REWARDS],
+    /// Loaded tick arrays, sorted by start tick index
+    pub tick_arrays: Vec<TickArray>,
+}
+
+impl WhirlpoolState {
+    /// Decode a `Whirlpool` account
+    pub fn decode(address: Pubkey, data: &[u8]) -> Result<Self> {
+        if data.len() != WHIRLPOOL_LEN {
+            anyhow::bail!("Invalid Whirlpool account: expected {} bytes, got {}", WHIRLPOOL_LEN, data.len());
+        }
+        if data[..8] != WHIRLPOOL_DISCRIMINATOR {
+            anyhow::bail!("Invalid Whirlpool account: discriminator mismatch");
+        }
+
+        Ok(Self {
+            address,
+            token_a: read_pubkey(data, WHIRLPOOL_TOKEN_MINT_A_OFFSET)?,
+            token_b: read_pubkey(data, WHIRLPOOL_TOKEN_MINT_B_OFFSET)?,
+            tick_current_index: read_i32(data, WHIRLPOOL_TICK_CURRENT_INDEX_OFFSET)?,
+            tick_spacing: read_u16(data, WHIRLPOOL_TICK_SPACING_OFFSET)?,
+            fee_rate: read_u16(data, WHIRLPOOL_FEE_RATE_OFFSET)?,
+            protocol_fee_rate: read_u16(data, WHIRLPOOL_PROTOCOL_FEE_RATE_OFFSET)?,
+            liquidity: read_u128(data, WHIRLPOOL_LIQUIDITY_OFFSET)?,
+            sqrt_price: read_u128(data, WHIRLPOOL_SQRT_PRICE_OFFSET)?,
+            token_vault_a: read_pubkey(data, WHIRLPOOL_TOKEN_VAULT_A_OFFSET)?,
+            token_vault_b: read_pubkey(data, WHIRLPOOL_TOKEN_VAULT_B_OFFSET)?,
+            protocol_fee_owed_a: read_u64(data, WHIRLPOOL_PROTOCOL_FEE_OWED_A_OFFSET)?,
+            protocol_fee_owed_b: read_u64(data, WHIRLPOOL_PROTOCOL_FEE_OWED_B_OFFSET)?,
+            fee_growth_global_a: read_u128(data, WHIRLPOOL_FEE_GROWTH_GLOBAL_A_OFFSET)?,
+            fee_growth_global_b: read_u128(data, WHIRLPOOL_FEE_GROWTH_GLOBAL_B_OFFSET)?,
+            reward_last_updated_timestamp: read_u64(data, WHIRLPOOL_REWARD_LAST_UPDATED_OFFSET)?,
+            reward_infos: [
+                Self::decode_reward_info(data, 0)?,
+                Self::decode_reward_info(data, 1)?,
+                Self::decode_reward_info(data, 2)?,
+            ],
+            tick_arrays: Vec::new(),
+        })
+    }
+
+    fn decode_reward_info(data: &[u8], index: usize) -> Result<WhirlpoolRewardInfo> {
+        let offset = WHIRLPOOL_REWARD_INFOS_OFFSET + index * WHIRLPOOL_REWARD_INFO_LEN;
+        Ok(WhirlpoolRewardInfo {
+            mint: read_pubkey(data, offset)?,
+            vault: read_pubkey(data, offset + 32)?,
+            authority: read_pubkey(data, offset + 64)?,
+            emissions_per_second_x64: read_u128(data, offset + 96)?,
+            growth_global_x64: read_u128(data, offset + 112)?,
+        })
+    }
+
+    /// Number of ticks covered by one tick array
+    pub fn ticks_per_array(&self) -> i32 {
+        TICK_ARRAY_SIZE * self.tick_spacing as i32
+    }
+
+    /// Add or replace a loaded tick array
+    pub fn insert_tick_array(&mut self, tick_array: TickArray) -> Result<()> {
+        if tick_array.whirlpool != self.address {
+            anyhow::bail!(
+                "Tick array {} belongs to whirlpool {}, not {}",
+                tick_array.address,
+                tick_array.whirlpool,
+                self.address,
+            );
+        }
+
+        match self
+            .tick_arrays
+            .binary_search_by_key(&tick_array.start_tick_index, |a| a.start_tick_index)
+        {
+            Ok(i) => self.tick_arrays[i] = tick_array,
+            Err(i) => self.tick_arrays.insert(i, tick_array),
+        }
+        Ok(())
+    }
+
+    /// Start indexes of the tick arrays a swap in the given direction
+    /// traverses, nearest first.
+    ///
+    /// Mirrors the Whirlpool program: a `b_to_a` swap shifts the current
+    /// tick up by one spacing, since a price sitting in the last tick of
+    /// an array starts its search in the next one. Arrays past the tick
+    /// range are left out, so fewer than three may be returned.
+    pub fn tick_array_start_indexes(&self, a_to_b: bool) -> Vec<i32> {
+        let span = self.ticks_per_array();
+        let shift = if a_to_b { 0 } else { self.tick_spacing as i32 };
+        let first = tick_array_start_index(self.tick_current_index + shift, self.tick_spacing);
+        let min_start = tick_array_start_index(whirlpool_math::MIN_TICK_INDEX, self.tick_spacing);
+        let max_start = tick_array_start_index(whirlpool_math::MAX_TICK_INDEX, self.tick_spacing);
+
+        (0..MAX_SWAP_TICK_ARRAYS as i32)
+            .map(|i| if a_to_b { first - i * span } else { first + i * span })
+            .take_while(|start| (min_start..=max_start).contains(start))
+            .collect()
+    }
+
+    /// Initialized ticks a swap in the given direction can reach.
+    ///
+    /// Only the loaded tick arrays among those passed to the swap
+    /// instruction are used, stopping at the first one missing. Without
+    /// any tick data the active liquidity is assumed to extend across
+    /// the whole price range.
+    pub fn tick_sequence(&self, a_to_b: bool) -> TickSequence {
+        let arrays: Vec<&TickArray> = self
+            .tick_array_start_indexes(a_to_b)
+            .into_iter()
+            .map_while(|start| {
+                self.tick_arrays
+                    .binary_search_by_key(&start, |a| a.start_tick_index)
+                    .ok()
+                    .map(|i| &self.tick_arrays[i])
+            })
+            .collect();
+        let (Some(nearest), Some(farthest)) = (arrays.first(), arrays.last()) else {
+            return TickSequence::unbounded();
+        };
+
+        let (lower, upper) = if a_to_b { (farthest, nearest) } else { (nearest, farthest) };
+        TickSequence::new(
+            arrays.iter().flat_map(|a| a.ticks.iter().copied()).collect(),
+            lower.start_tick_index,
+            upper.start_tick_index + self.ticks_per_array() - 1,
+        )
+    }
+}
+
+/// Arguments of the Whirlpool `swap` and `swap_v2` instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapArgs {
+    /// Exact input, or exact output if `amount_specified_is_input` is false
+    pub amount: u64,
+    /// Minimum output for exact input, maximum input for exact output
+    pub other_amount_threshold: u64,
+    /// Price the swap may not move past (Q64.64)
+    pub sqrt_price_limit: u128,
+    /// Whether `amount` is the input amount
+    pub amount_specified_is_input: bool,
+    /// Swap direction, token A to token B if true
+    pub a_to_b: bool,
+}
+
+impl SwapArgs {
+    fn encode(&self, discriminator: &[u8; 8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(43);
+        data.extend_from_slice(discriminator);
+        data.extend_from_slice(&self.amount.to_le_bytes());
+        data.extend_from_slice(&self.other_amount_threshold.to_le_bytes());
+        data.extend_from_slice(&self.sqrt_price_limit.to_le_bytes());
+        data.push(self.amount_specified_is_input as u8);
+        data.push(self.a_to_b as u8);
+        data
+    }
+}
+
+/// User token accounts for a swap and the token programs owning the mints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapTokenAccounts {
+    /// User token account for token A
+    pub owner_account_a: Pubkey,
+    /// User token account for token B
+    pub owner_account_b: Pubkey,
+    /// Token program of token A
+    pub token_program_a: Pubkey,
+    /// Token program of token B
+    pub token_program_b: Pubkey,
+}
+
+impl SwapTokenAccounts {
+    /// Associated token accounts of `owner` for a whirlpool of SPL Token mints
+    pub fn associated(owner: &Pubkey, pool: &WhirlpoolState) -> Self {
+        Self {
+            owner_account_a: get_associated_token_address(owner, &pool.token_a),
+            owner_account_b: get_associated_token_address(owner, &pool.token_b),
+            token_program_a: token::ID,
+            token_program_b: token::ID,
+        }
+    }
+
+    /// Associated token accounts of `owner` for a whirlpool whose mints
+    /// belong to `token_program_a` and `token_program_b`
+    pub fn associated_with_programs(
+        owner: &Pubkey,
+        pool: &WhirlpoolState,
+        token_program_a: Pubkey,
+        token_program_b: Pubkey,
+    ) -> Self {
+        Self {
+            owner_account_a: get_associated_token_address_with_program_id(owner, &pool.token_a, &token_program_a),
+            owner_account_b: get_associated_token_address_with_program_id(owner, &pool.token_b, &token_program_b),
+            token_program_a,
+            token_program_b,
+        }
+    }
 }
 
 /// Quote information from Orca
 #[derive(Debug, Clone)]
 pub struct OrcaQuote {
-    /// Input amount
+    /// Which side of the swap is fixed
+    pub mode: SwapMode,
+    /// Source token mint
+    pub token_in: Pubkey,
+    /// Destination token mint
+    pub token_out: Pubkey,
+    /// Token program of the source mint
+    pub token_program_in: Pubkey,
+    /// Token program of the destination mint
+    pub token_program_out: Pubkey,
+    /// Input amount, transfer fee included
     pub amount_in: u64,
-    /// Expected output amount
+    /// Expected output amount, transfer fee deducted
     pub amount_out: u64,
     /// Price impact (in basis points)
     pub price_impact_bps: u16,
@@ -48,14 +436,39 @@ pub struct OrcaQuote {
     pub pool: Pubkey,
     /// Minimum output amount (with slippage)
     pub minimum_out: u64,
+    /// Maximum input amount (with slippage)
+    pub maximum_in: u64,
     /// Tick array addresses needed for swap
     pub tick_arrays: Vec<Pubkey>,
 }
 
+impl From<OrcaQuote> for Quote {
+    fn from(quote: OrcaQuote) -> Self {
+        Self {
+            dex_type: DexType::Orca,
+            mode: quote.mode,
+            token_in: quote.token_in,
+            token_out: quote.token_out,
+            token_program_in: quote.token_program_in,
+            token_program_out: quote.token_program_out,
+            pool: quote.pool,
+            amount_in: quote.amount_in,
+            amount_out: quote.amount_out,
+            price_impact_bps: quote.price_impact_bps,
+            minimum_out: quote.minimum_out,
+            maximum_in: quote.maximum_in,
+            extra_accounts: quote.tick_arrays,
+        }
+    }
+}
+
 /// Orca DEX client
+#[derive(Clone)]
 pub struct Client {
     /// Pool cache
     whirlpools: HashMap<(Pubkey, Pubkey), WhirlpoolState>,
+    /// Token program and transfer fee of the mints pools trade
+    mints: HashMap<Pubkey, MintInfo>,
     /// Program ID
     program_id: Pubkey,
     /// Config account
@@ -67,6 +480,7 @@ impl Client {
     pub fn new() -> Result<Self> {
         Ok(Self {
             whirlpools: HashMap::new(),
+            mints: HashMap::new(),
             program_id: "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc"
                 .parse()
                 .unwrap(),
@@ -82,57 +496,253 @@ impl Client {
         token_in: &Pubkey,
         token_out: &Pubkey,
         amount: u64,
+        slippage_bps: u16,
     ) -> Result<OrcaQuote> {
         // Find whirlpool for token pair
         let pool = self.get_whirlpool(token_in, token_out)?;
-        
-        // Calculate output using CL formula
-        let (amount_out, price_impact, tick_arrays) = self.calculate_output(
-            amount,
-            &pool,
+        let (mint_in, mint_out) = (self.mint(token_in), self.mint(token_out));
+
+        // Calculate output using CL formula on what reaches the pool
+        let (swapped_out, price_impact, tick_arrays) = self.calculate_output(
+            amount.saturating_sub(mint_in.transfer_fee(amount)),
+            pool,
             token_in == &pool.token_a,
         )?;
+        let amount_out = swapped_out.saturating_sub(mint_out.transfer_fee(swapped_out));
 
-        // Calculate minimum output with 1% slippage
-        let minimum_out = amount_out * 99 / 100;
+        // Calculate minimum output with slippage
+        let minimum_out = slippage::minimum_out(amount_out, slippage_bps)?;
 
         Ok(OrcaQuote {
+            mode: SwapMode::ExactIn,
+            token_in: *token_in,
+            token_out: *token_out,
+            token_program_in: mint_in.token_program,
+            token_program_out: mint_out.token_program,
             amount_in: amount,
             amount_out,
             price_impact_bps: price_impact,
             pool: pool.address,
             minimum_out,
+            maximum_in: amount,
             tick_arrays,
         })
     }
 
-    /// Prepare swap transaction
-    pub fn prepare_swap(
+    /// Get quote for receiving exactly `amount_out`
+    pub async fn get_quote_exact_out(
         &self,
-        quote: &OrcaQuote,
-        user: &Pubkey,
-    ) -> Result<Transaction> {
-        let pool = self.whirlpools.values()
-            .find(|p| p.address == quote.pool)
-            .ok_or_else(|| anyhow::anyhow!("Pool not found"))?;
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_out: u64,
+        slippage_bps: u16,
+    ) -> Result<OrcaQuote> {
+        // Find whirlpool for token pair
+        let pool = self.get_whirlpool(token_in, token_out)?;
+        let (mint_in, mint_out) = (self.mint(token_in), self.mint(token_out));
 
-        // Create swap instruction
-        let swap_ix = self.create_swap_instruction(
-            user,
+        // Calculate required input using CL formula for an output that
+        // still covers its transfer fee
+        let (swapped_in, price_impact, tick_arrays) = self.calculate_input(
+            mint_out.pre_fee_amount(amount_out)?,
             pool,
-            quote.amount_in,
-            quote.minimum_out,
-            &quote.tick_arrays,
+            token_in == &pool.token_a,
         )?;
+        let amount_in = mint_in.pre_fee_amount(swapped_in)?;
+
+        // Calculate maximum input with slippage
+        let maximum_in = slippage::maximum_in(amount_in, slippage_bps)?;
+
+        Ok(OrcaQuote {
+            mode: SwapMode::ExactOut,
+            token_in: *token_in,
+            token_out: *token_out,
+            token_program_in: mint_in.token_program,
+            token_program_out: mint_out.token_program,
+            amount_in,
+            amount_out,
+            price_impact_bps: price_impact,
+            pool: pool.address,
+            minimum_out: amount_out,
+            maximum_in,
+            tick_arrays,
+        })
+    }
 
-        // Create transaction
-        Ok(Transaction::new_with_payer(
-            &[swap_ix],
-            Some(user),
-        ))
+    /// Program ID
+    pub fn program_id(&self) -> &Pubkey {
+        &self.program_id
+    }
+
+    /// Decode a whirlpool from raw account data and add it to the pool
+    /// cache with `insert_whirlpool`, returning the cached whirlpool for
+    /// its mint pair
+    pub fn load_whirlpool(
+        &mut self,
+        address: Pubkey,
+        data: &[u8],
+    ) -> Result<&WhirlpoolState> {
+        let pool = WhirlpoolState::decode(address, data)?;
+        let key = (pool.token_a, pool.token_b);
+        self.insert_whirlpool(pool);
+        Ok(&self.whirlpools[&key])
+    }
+
+    /// Decode a tick array from raw account data and attach it to its
+    /// whirlpool, which must already be loaded
+    pub fn load_tick_array(&mut self, address: Pubkey, data: &[u8]) -> Result<()> {
+        let whirlpool = TickArray::whirlpool_of(data)?;
+        let pool = self.whirlpools.values_mut()
+            .find(|p| p.address == whirlpool)
+            .ok_or_else(|| anyhow::anyhow!("Whirlpool {} not loaded", whirlpool))?;
+
+        let tick_array = TickArray::decode(address, data, pool.tick_spacing)?;
+        pool.insert_tick_array(tick_array)
+    }
+
+    /// Add a whirlpool to the pool cache.
+    ///
+    /// Only the deepest whirlpool per mint pair is kept, since quotes
+    /// are taken from a single pool. Refreshing a cached whirlpool keeps
+    /// its loaded tick arrays.
+    pub fn insert_whirlpool(&mut self, mut pool: WhirlpoolState) {
+        let key = (pool.token_a, pool.token_b);
+        match self.whirlpools.get_mut(&key) {
+            Some(existing) if existing.address == pool.address => {
+                pool.tick_arrays = std::mem::take(&mut existing.tick_arrays);
+                *existing = pool;
+            }
+            Some(existing) if existing.liquidity >= pool.liquidity => {}
+            _ => {
+                self.whirlpools.insert(key, pool);
+            }
+        }
+    }
+
+    /// Discover whirlpools on-chain and add them to the pool cache.
+    ///
+    /// Returns the number of whirlpools found.
+    pub async fn discover_whirlpools(
+        &mut self,
+        rpc: &dyn AccountProvider,
+        filter: &PoolFilter,
+    ) -> Result<usize> {
+        let pools = discovery::discover_whirlpools(rpc, &self.program_id, filter).await?;
+        let found = pools.len();
+        for pool in pools {
+            self.insert_whirlpool(pool);
+        }
+        self.load_mints(rpc).await?;
+        Ok(found)
+    }
+
+    /// Cached whirlpools
+    pub fn whirlpools(&self) -> impl Iterator<Item = &WhirlpoolState> {
+        self.whirlpools.values()
+    }
+
+    /// Record the token program and transfer fee of `mint`
+    pub fn insert_mint(&mut self, mint: Pubkey, info: MintInfo) {
+        self.mints.insert(mint, info);
+    }
+
+    /// Token program and transfer fee of `mint`. Mints not loaded are
+    /// taken to be SPL Token mints.
+    pub fn mint(&self, mint: &Pubkey) -> MintInfo {
+        self.mints.get(mint).copied().unwrap_or_default()
+    }
+
+    /// Load the mints of cached whirlpools, with the transfer fees in
+    /// effect this epoch
+    pub async fn load_mints(&mut self, rpc: &dyn AccountProvider) -> Result<()> {
+        let mut mints: Vec<Pubkey> = self.whirlpools.keys().flat_map(|&(a, b)| [a, b]).collect();
+        mints.sort_unstable();
+        mints.dedup();
+        self.mints = discovery::get_mints(rpc, &mints).await?;
+        Ok(())
+    }
+
+    /// Reload cached whirlpools, the tick arrays swaps in either
+    /// direction traverse and the mints they trade, dropping whirlpools
+    /// that no longer exist or whose accounts no longer decode
+    pub async fn refresh_whirlpools(&mut self, rpc: &dyn AccountProvider) -> Result<()> {
+        let addresses: Vec<Pubkey> = self.whirlpools.values().map(|p| p.address).collect();
+        let accounts = discovery::get_multiple_accounts_chunked(rpc, &addresses).await?;
+        for (address, data) in addresses.iter().zip(accounts) {
+            let Some(data) = data else {
+                debug!("Dropping whirlpool {}: account not found", address);
+                self.whirlpools.retain(|_, p| p.address != *address);
+                continue;
+            };
+            if let Err(e) = self.load_whirlpool(*address, &data) {
+                debug!("Dropping whirlpool {}: {}", address, e);
+                self.whirlpools.retain(|_, p| p.address != *address);
+            }
+        }
+
+        // Which tick arrays are needed depends on the refreshed current tick
+        let mut tick_arrays: Vec<(Pubkey, Pubkey)> = Vec::new();
+        for pool in self.whirlpools.values() {
+            for a_to_b in [true, false] {
+                for address in self.get_tick_arrays(pool, a_to_b) {
+                    if !tick_arrays.iter().any(|(a, _)| *a == address) {
+                        tick_arrays.push((address, pool.address));
+                    }
+                }
+            }
+        }
+        let addresses: Vec<Pubkey> = tick_arrays.iter().map(|(a, _)| *a).collect();
+        let accounts = discovery::get_multiple_accounts_chunked(rpc, &addresses).await?;
+        for (&(address, whirlpool), data) in tick_arrays.iter().zip(accounts) {
+            // Arrays without initialized ticks may never have been created
+            let Some(data) = data else { continue };
+            if !self.whirlpools.values().any(|p| p.address == whirlpool) {
+                continue;
+            }
+            // A pool missing a tick array would quote through ticks it
+            // cannot see
+            if let Err(e) = self.load_tick_array(address, &data) {
+                debug!("Dropping whirlpool {}: tick array {}: {}", whirlpool, address, e);
+                self.whirlpools.retain(|_, p| p.address != whirlpool);
+            }
+        }
+        self.load_mints(rpc).await
     }
 
     // Private helper methods
+    fn swap_instruction(&self, quote: &Quote, user: &Pubkey) -> Result<Instruction> {
+        let pool = self.whirlpools.values()
+            .find(|p| p.address == quote.pool)
+            .ok_or_else(|| anyhow::anyhow!("Pool not found"))?;
+
+        let a_to_b = quote.token_in == pool.token_a;
+        // The threshold bounds whichever side is not specified
+        let (amount, other_amount_threshold) = match quote.mode {
+            SwapMode::ExactIn => (quote.amount_in, quote.minimum_out),
+            SwapMode::ExactOut => (quote.amount_out, quote.maximum_in),
+        };
+        let args = SwapArgs {
+            amount,
+            other_amount_threshold,
+            sqrt_price_limit: whirlpool_math::default_sqrt_price_limit(a_to_b),
+            amount_specified_is_input: quote.mode == SwapMode::ExactIn,
+            a_to_b,
+        };
+
+        let (token_program_a, token_program_b) = if a_to_b {
+            (quote.token_program_in, quote.token_program_out)
+        } else {
+            (quote.token_program_out, quote.token_program_in)
+        };
+        let accounts = SwapTokenAccounts::associated_with_programs(user, pool, token_program_a, token_program_b);
+        // Only swap_v2 handles Token-2022 mints
+        if token_program_a == token::ID && token_program_b == token::ID {
+            self.create_swap_instruction(pool, user, &accounts, &args, &quote.extra_accounts)
+        } else {
+            self.create_swap_v2_instruction(pool, user, &accounts, &args, &quote.extra_accounts)
+        }
+    }
+
     fn get_whirlpool(&self, token_a: &Pubkey, token_b: &Pubkey) -> Result<&WhirlpoolState> {
         self.whirlpools
             .get(&(*token_a, *token_b))
@@ -146,121 +756,665 @@ impl Client {
         pool: &WhirlpoolState,
         a_to_b: bool,
     ) -> Result<(u64, u16, Vec<Pubkey>)> {
-        // This is a simplified version of Orca's CL math
-        let amount_with_fees = amount_in as u128 * 
-            (10000 - pool.fee_rate - pool.protocol_fee_rate) as u128 / 10000;
-
-        // Calculate required tick arrays for swap
-        let tick_arrays = self.get_tick_arrays(
-            pool.tick_current_index,
-            pool.tick_spacing,
-            a_to_b,
-        )?;
+        // Tick arrays the swap instruction will traverse
+        let tick_arrays = self.get_tick_arrays(pool, a_to_b);
 
         // Simulate swap across ticks
-        let (amount_out, sqrt_price_limit) = self.simulate_swap(
-            amount_with_fees,
-            pool.liquidity,
-            pool.tick_current_index,
-            a_to_b,
-        )?;
+        let result = self.simulate_swap(amount_in, pool, a_to_b, true)?;
+        let (consumed, amount_out) = result.amounts(a_to_b);
+        if consumed < amount_in {
+            anyhow::bail!(
+                "Insufficient liquidity: only {} of {} could be swapped",
+                consumed,
+                amount_in,
+            );
+        }
 
         // Calculate price impact
-        let price_impact = ((amount_in as f64 / pool.liquidity as f64) * 10000.0) as u16;
+        let price_impact = self.price_impact(pool, &result, a_to_b)?;
 
-        Ok((amount_out as u64, price_impact, tick_arrays))
+        Ok((amount_out, price_impact, tick_arrays))
     }
 
-    fn get_tick_arrays(
+    fn calculate_input(
         &self,
-        current_tick: i32,
-        tick_spacing: u16,
+        amount_out: u64,
+        pool: &WhirlpoolState,
         a_to_b: bool,
-    ) -> Result<Vec<Pubkey>> {
-        // Simplified tick array calculation
-        let mut tick_arrays = Vec::new();
-        let array_size = 88 * tick_spacing as i32;
-        
-        let start_tick = if a_to_b {
-            current_tick - array_size
-        } else {
-            current_tick
-        };
+    ) -> Result<(u64, u16, Vec<Pubkey>)> {
+        // Tick arrays the swap instruction will traverse
+        let tick_arrays = self.get_tick_arrays(pool, a_to_b);
 
-        // Add necessary tick arrays
-        for i in 0..3 {
-            let tick_array_start = start_tick + (i * array_size);
-            tick_arrays.push(self.derive_tick_array(tick_array_start, tick_spacing)?);
+        // Simulate swap across ticks, specifying the output
+        let result = self.simulate_swap(amount_out, pool, a_to_b, false)?;
+        let (amount_in, received) = result.amounts(a_to_b);
+        if received < amount_out {
+            anyhow::bail!(
+                "Insufficient liquidity: only {} of {} could be received",
+                received,
+                amount_out,
+            );
         }
 
-        Ok(tick_arrays)
+        // Calculate price impact
+        let price_impact = self.price_impact(pool, &result, a_to_b)?;
+
+        Ok((amount_in, price_impact, tick_arrays))
+    }
+
+    /// Price impact of a simulated swap against the pool price before it,
+    /// with the fee excluded
+    fn price_impact(&self, pool: &WhirlpoolState, result: &SwapResult, a_to_b: bool) -> Result<u16> {
+        let (amount_in, amount_out) = result.amounts(a_to_b);
+        let expected_out = whirlpool_math::output_at_sqrt_price(
+            pool.sqrt_price,
+            amount_in.saturating_sub(result.fee_amount),
+            a_to_b,
+        )?;
+        Ok(math::price_impact_bps(expected_out, amount_out)?)
+    }
+
+    fn get_tick_arrays(&self, pool: &WhirlpoolState, a_to_b: bool) -> Vec<Pubkey> {
+        pool.tick_array_start_indexes(a_to_b)
+            .into_iter()
+            .map(|start| self.derive_tick_array(&pool.address, start))
+            .collect()
     }
 
     fn simulate_swap(
         &self,
-        amount_in: u128,
-        liquidity: u128,
-        current_tick: i32,
+        amount: u64,
+        pool: &WhirlpoolState,
         a_to_b: bool,
-    ) -> Result<(u128, u128)> {
-        // Simplified CL swap simulation
-        let sqrt_price_limit = if a_to_b {
-            self.tick_to_sqrt_price(current_tick - 1)?
-        } else {
-            self.tick_to_sqrt_price(current_tick + 1)?
+        amount_specified_is_input: bool,
+    ) -> Result<SwapResult> {
+        let snapshot = PoolSnapshot {
+            sqrt_price: pool.sqrt_price,
+            tick_current_index: pool.tick_current_index,
+            liquidity: pool.liquidity,
+            fee_rate: pool.fee_rate,
         };
 
-        let amount_out = amount_in * liquidity / 10_u128.pow(12);
-        
-        Ok((amount_out, sqrt_price_limit))
+        whirlpool_math::swap(
+            &snapshot,
+            &pool.tick_sequence(a_to_b),
+            amount,
+            whirlpool_math::default_sqrt_price_limit(a_to_b),
+            amount_specified_is_input,
+            a_to_b,
+        )
     }
 
-    fn derive_tick_array(&self, start_tick: i32, spacing: u16) -> Result<Pubkey> {
-        // Simplified tick array address derivation
-        Ok(Pubkey::new_unique())
+    /// Address of the tick array of `whirlpool` starting at `start_tick_index`
+    pub fn derive_tick_array(&self, whirlpool: &Pubkey, start_tick_index: i32) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                TICK_ARRAY_SEED,
+                whirlpool.as_ref(),
+                start_tick_index.to_string().as_bytes(),
+            ],
+            &self.program_id,
+        )
+        .0
     }
 
-    fn tick_to_sqrt_price(&self, tick: i32) -> Result<u128> {
-        // Simplified tick to sqrt price conversion
-        Ok(1u128 << 64)
+    /// Address of the oracle of `whirlpool`
+    pub fn derive_oracle(&self, whirlpool: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[ORACLE_SEED, whirlpool.as_ref()], &self.program_id).0
     }
 
-    fn create_swap_instruction(
+    /// Build a `swap` instruction.
+    ///
+    /// `swap` only supports SPL Token mints; use
+    /// [`Client::create_swap_v2_instruction`] for Token-2022.
+    pub fn create_swap_instruction(
         &self,
-        user: &Pubkey,
         pool: &WhirlpoolState,
-        amount_in: u64,
-        minimum_out: u64,
+        owner: &Pubkey,
+        accounts: &SwapTokenAccounts,
+        args: &SwapArgs,
         tick_arrays: &[Pubkey],
     ) -> Result<Instruction> {
-        // This is a simplified version - actual Orca instruction would be more complex
+        if accounts.token_program_a != token::ID || accounts.token_program_b != token::ID {
+            anyhow::bail!("swap only supports SPL Token mints, use swap_v2");
+        }
+        let [tick_array_0, tick_array_1, tick_array_2] = swap_tick_arrays(tick_arrays)?;
+
         Ok(Instruction {
             program_id: self.program_id,
             accounts: vec![
-                // Add necessary account metas
+                AccountMeta::new_readonly(token::ID, false),
+                AccountMeta::new_readonly(*owner, true),
+                AccountMeta::new(pool.address, false),
+                AccountMeta::new(accounts.owner_account_a, false),
+                AccountMeta::new(pool.token_vault_a, false),
+                AccountMeta::new(accounts.owner_account_b, false),
+                AccountMeta::new(pool.token_vault_b, false),
+                AccountMeta::new(tick_array_0, false),
+                AccountMeta::new(tick_array_1, false),
+                AccountMeta::new(tick_array_2, false),
+                // Writable for pools with adaptive fees, accepted by all
+                AccountMeta::new(self.derive_oracle(&pool.address), false),
             ],
-            data: vec![
-                // Add instruction data
+            data: args.encode(&SWAP_DISCRIMINATOR),
+        })
+    }
+
+    /// Build a `swap_v2` instruction, which supports Token-2022 mints
+    pub fn create_swap_v2_instruction(
+        &self,
+        pool: &WhirlpoolState,
+        owner: &Pubkey,
+        accounts: &SwapTokenAccounts,
+        args: &SwapArgs,
+        tick_arrays: &[Pubkey],
+    ) -> Result<Instruction> {
+        let [tick_array_0, tick_array_1, tick_array_2] = swap_tick_arrays(tick_arrays)?;
+
+        let mut data = args.encode(&SWAP_V2_DISCRIMINATOR);
+        // `remaining_accounts_info: Option<RemainingAccountsInfo>`, no transfer hooks
+        data.push(0);
+
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(accounts.token_program_a, false),
+                AccountMeta::new_readonly(accounts.token_program_b, false),
+                AccountMeta::new_readonly(MEMO_PROGRAM_ID, false),
+                AccountMeta::new_readonly(*owner, true),
+                AccountMeta::new(pool.address, false),
+                AccountMeta::new_readonly(pool.token_a, false),
+                AccountMeta::new_readonly(pool.token_b, false),
+                AccountMeta::new(accounts.owner_account_a, false),
+                AccountMeta::new(pool.token_vault_a, false),
+                AccountMeta::new(accounts.owner_account_b, false),
+                AccountMeta::new(pool.token_vault_b, false),
+                AccountMeta::new(tick_array_0, false),
+                AccountMeta::new(tick_array_1, false),
+                AccountMeta::new(tick_array_2, false),
+                AccountMeta::new(self.derive_oracle(&pool.address), false),
             ],
+            data,
         })
     }
 }
 
+#[async_trait::async_trait]
+impl DexAdapter for Client {
+    fn dex_type(&self) -> DexType {
+        DexType::Orca
+    }
+
+    fn supported_pairs(&self) -> Vec<(Pubkey, Pubkey)> {
+        self.whirlpools.keys().copied().collect()
+    }
+
+    async fn quote(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage_bps: u16,
+    ) -> Result<Quote> {
+        Ok(self.get_quote(token_in, token_out, amount, slippage_bps).await?.into())
+    }
+
+    async fn quote_exact_out(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_out: u64,
+        slippage_bps: u16,
+    ) -> Result<Quote> {
+        Ok(self.get_quote_exact_out(token_in, token_out, amount_out, slippage_bps).await?.into())
+    }
+
+    fn build_swap_instructions(&self, quote: &Quote, user: &Pubkey) -> Result<Vec<Instruction>> {
+        Ok(vec![self.swap_instruction(quote, user)?])
+    }
+
+    fn decode_error(&self, program_id: &Pubkey, code: u32) -> Option<SwapFailure> {
+        if *program_id != self.program_id {
+            return None;
+        }
+        Some(match code {
+            ERROR_AMOUNT_OUT_BELOW_MINIMUM | ERROR_AMOUNT_IN_ABOVE_MAXIMUM => SwapFailure::SlippageExceeded,
+            ERROR_LIQUIDITY_ZERO => SwapFailure::InsufficientLiquidity,
+            ERROR_SQRT_PRICE_OUT_OF_BOUNDS => SwapFailure::Other("SqrtPriceOutOfBounds"),
+            ERROR_INVALID_TICK_ARRAY_SEQUENCE => SwapFailure::Other("InvalidTickArraySequence"),
+            ERROR_ZERO_TRADABLE_AMOUNT => SwapFailure::Other("ZeroTradableAmount"),
+            _ => SwapFailure::Other("Unknown"),
+        })
+    }
+
+    async fn refresh(&mut self, rpc: &dyn AccountProvider) -> Result<()> {
+        self.refresh_whirlpools(rpc).await
+    }
+}
+
+/// Pad the tick arrays of a swap to the three accounts the instruction
+/// expects by repeating the last one, as the Whirlpool SDK does near the
+/// ends of the tick range
+fn swap_tick_arrays(tick_arrays: &[Pubkey]) -> Result<[Pubkey; MAX_SWAP_TICK_ARRAYS]> {
+    let Some(last) = tick_arrays.last() else {
+        anyhow::bail!("Swap requires at least one tick array");
+    };
+    if tick_arrays.len() > MAX_SWAP_TICK_ARRAYS {
+        anyhow::bail!("Swap takes at most {} tick arrays, got {}", MAX_SWAP_TICK_ARRAYS, tick_arrays.len());
+    }
+    Ok(std::array::from_fn(|i| *tick_arrays.get(i).unwrap_or(last)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
+    use crate::swap::{SwapEngine, TransferFee, DEFAULT_SLIPPAGE_BPS, TOKEN_2022_PROGRAM_ID};
+    use anchor_client::solana_sdk::message::VersionedMessage;
+
+    const SOL_USDC_TICK_ARRAYS: [&str; 3] = [
+        include_str!("../tests/fixtures/orca/sol_usdc_tick_array_m28160.json"),
+        include_str!("../tests/fixtures/orca/sol_usdc_tick_array_m22528.json"),
+        include_str!("../tests/fixtures/orca/sol_usdc_tick_array_m16896.json"),
+    ];
+
+    fn load_sol_usdc(client: &mut Client) -> WhirlpoolState {
+        let (address, data) = crate::swap::layout::load_fixture(
+            include_str!("../tests/fixtures/orca/sol_usdc_whirlpool.json"),
+        );
+        client.load_whirlpool(address, &data).unwrap().clone()
+    }
+
+    fn load_sol_usdc_tick_arrays(client: &mut Client) {
+        for json in SOL_USDC_TICK_ARRAYS {
+            let (address, data) = crate::swap::layout::load_fixture(json);
+            client.load_tick_array(address, &data).unwrap();
+        }
+    }
 
     #[tokio::test]
     async fn test_quote_calculation() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+
+        // 1 SOL at ~150 USDC/SOL less the 0.3% fee
+        let quote = client.get_quote(&pool.token_a, &pool.token_b, 1_000_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(quote.amount_out > 149_000_000 && quote.amount_out < 149_600_000);
+
+        // 150 USDC back to just under 1 SOL
+        let quote = client.get_quote(&pool.token_b, &pool.token_a, 150_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(quote.amount_out > 990_000_000 && quote.amount_out < 997_000_000);
+        // The fee alone is not price impact
+        assert_eq!(quote.price_impact_bps, 0);
+
+        // 1k SOL moves the price
+        let quote = client.get_quote(&pool.token_a, &pool.token_b, 1_000_000_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(quote.price_impact_bps > 50 && quote.price_impact_bps < 100);
+    }
+
+    #[test]
+    fn test_tick_array_start_index() {
+        assert_eq!(tick_array_start_index(0, 64), 0);
+        assert_eq!(tick_array_start_index(5631, 64), 0);
+        assert_eq!(tick_array_start_index(5632, 64), 5632);
+        assert_eq!(tick_array_start_index(-1, 64), -5632);
+        assert_eq!(tick_array_start_index(-5632, 64), -5632);
+        assert_eq!(tick_array_start_index(-5633, 64), -11264);
+        assert_eq!(tick_array_start_index(-18973, 64), -22528);
+        assert_eq!(tick_array_start_index(-88, 1), -88);
+        assert_eq!(tick_array_start_index(-89, 1), -176);
+    }
+
+    #[test]
+    fn test_derive_tick_array() {
         let client = Client::new().unwrap();
-        // Add test implementation
+        let whirlpool: Pubkey = "Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".parse().unwrap();
+
+        // Must match the fixture accounts dumped from these addresses
+        for (start, json) in [-28160, -22528, -16896].into_iter().zip(SOL_USDC_TICK_ARRAYS) {
+            let (address, _) = crate::swap::layout::load_fixture(json);
+            assert_eq!(client.derive_tick_array(&whirlpool, start), address);
+        }
     }
 
     #[test]
     fn test_tick_array_calculation() {
+        let mut client = Client::new().unwrap();
+        let mut pool = load_sol_usdc(&mut client);
+        let addresses: Vec<Pubkey> = SOL_USDC_TICK_ARRAYS
+            .iter()
+            .map(|json| crate::swap::layout::load_fixture(json).0)
+            .collect();
+
+        // Price moves down through lower arrays
+        assert_eq!(pool.tick_array_start_indexes(true), vec![-22528, -28160, -33792]);
+        let arrays = client.get_tick_arrays(&pool, true);
+        assert_eq!(arrays[..2], [addresses[1], addresses[0]]);
+        assert_eq!(arrays[2], client.derive_tick_array(&pool.address, -33792));
+
+        // Price moves up through higher arrays
+        assert_eq!(pool.tick_array_start_indexes(false), vec![-22528, -16896, -11264]);
+        let arrays = client.get_tick_arrays(&pool, false);
+        assert_eq!(arrays[..2], [addresses[1], addresses[2]]);
+
+        // In the last tick of an array a b_to_a swap starts in the next one
+        pool.tick_current_index = -16897;
+        assert_eq!(pool.tick_array_start_indexes(true)[0], -22528);
+        assert_eq!(pool.tick_array_start_indexes(false)[0], -16896);
+
+        // No arrays beyond the tick range
+        pool.tick_current_index = 440_000;
+        assert_eq!(pool.tick_array_start_indexes(false), vec![439296]);
+        pool.tick_current_index = -443_000;
+        assert_eq!(pool.tick_array_start_indexes(true), vec![-444928]);
+    }
+
+    #[test]
+    fn test_decode_error() {
         let client = Client::new().unwrap();
-        let arrays = client.get_tick_arrays(0, 8, true).unwrap();
-        assert_eq!(arrays.len(), 3);
+        let program = *client.program_id();
+        assert_eq!(client.decode_error(&program, 6036), Some(SwapFailure::SlippageExceeded));
+        assert_eq!(client.decode_error(&program, 6037), Some(SwapFailure::SlippageExceeded));
+        assert_eq!(client.decode_error(&program, 6012), Some(SwapFailure::InsufficientLiquidity));
+        assert_eq!(client.decode_error(&program, 6023), Some(SwapFailure::Other("InvalidTickArraySequence")));
+        assert_eq!(client.decode_error(&Pubkey::new_unique(), 6036), None);
+    }
+
+    #[test]
+    fn test_decode_whirlpool() {
+        let (address, data) = crate::swap::layout::load_fixture(
+            include_str!("../tests/fixtures/orca/sol_usdc_whirlpool.json"),
+        );
+        let pool = WhirlpoolState::decode(address, &data).unwrap();
+
+        assert_eq!(pool.token_a.to_string(), "So11111111111111111111111111111111111111112");
+        assert_eq!(pool.token_b.to_string(), "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        assert_eq!(pool.tick_spacing, 64);
+        assert_eq!(pool.fee_rate, 3000);
+        assert_eq!(pool.tick_current_index, -18973);
+        assert_eq!(pool.sqrt_price, 7144393258922745856);
+        assert_eq!(pool.token_vault_a.to_string(), "E5vRzP43ktVdRjd1AkvuWzssGhHRs1EhvbdUQyYncwRX");
+        assert_eq!(pool.token_vault_b.to_string(), "3sxmYeBtCjixkh3Z3z1FieQK1JhZaVq8WdrR1dXu9zSo");
+        assert_eq!(pool.fee_growth_global_a, 123456789 << 64);
+        assert_eq!(pool.reward_last_updated_timestamp, 1700000000);
+        assert!(pool.reward_infos.iter().all(|r| !r.initialized()));
+        assert!(pool.tick_arrays.is_empty());
+
+        let mut corrupted = data.clone();
+        corrupted[0] ^= 0xff;
+        assert!(WhirlpoolState::decode(address, &corrupted).is_err());
+        assert!(WhirlpoolState::decode(address, &data[..WHIRLPOOL_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_tick_array() {
+        let (address, data) = crate::swap::layout::load_fixture(SOL_USDC_TICK_ARRAYS[1]);
+        let tick_array = TickArray::decode(address, &data, 64).unwrap();
+
+        assert_eq!(tick_array.start_tick_index, -22528);
+        assert_eq!(tick_array.whirlpool.to_string(), "Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE");
+        assert_eq!(tick_array.ticks, vec![
+            Tick { index: -19968, liquidity_net: 30_000_000_000_000 },
+            Tick { index: -17984, liquidity_net: -30_000_000_000_000 },
+        ]);
+
+        // Whirlpool accounts are not tick arrays
+        let (_, whirlpool) = crate::swap::layout::load_fixture(
+            include_str!("../tests/fixtures/orca/sol_usdc_whirlpool.json"),
+        );
+        assert!(TickArray::decode(address, &whirlpool, 64).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_skips_undecodable_whirlpool() {
+        use crate::swap::discovery::tests::FixtureRpc;
+        use crate::swap::layout::{load_fixture, read_u128};
+
+        let mut rpc = FixtureRpc::new();
+        let mut client = Client::new().unwrap();
+        let sol_usdc = load_sol_usdc(&mut client);
+
+        // A second whirlpool trading SOL for RAY, whose liquidity has
+        // since doubled on chain
+        let (_, mut data) = load_fixture(include_str!("../tests/fixtures/orca/sol_usdc_whirlpool.json"));
+        let ray: Pubkey = "4k3Dyjzvzp8eMZWUXbBCjEvwSkkk59S5iCNLY3QrkX6R".parse().unwrap();
+        data[WHIRLPOOL_TOKEN_MINT_B_OFFSET..][..32].copy_from_slice(ray.as_ref());
+        let sol_ray = Pubkey::new_unique();
+        client.load_whirlpool(sol_ray, &data).unwrap();
+        let liquidity = read_u128(&data, WHIRLPOOL_LIQUIDITY_OFFSET).unwrap() * 2;
+        data[WHIRLPOOL_LIQUIDITY_OFFSET..][..16].copy_from_slice(&liquidity.to_le_bytes());
+        rpc.insert_account(sol_ray, client.program_id, data);
+
+        // The SOL/USDC tick array holding the current tick is wiped
+        let (tick_array, _) = load_fixture(SOL_USDC_TICK_ARRAYS[1]);
+        rpc.insert_account(tick_array, client.program_id, vec![0; TICK_ARRAY_LEN]);
+
+        client.refresh_whirlpools(&rpc).await.unwrap();
+        assert!(client.whirlpools().all(|p| p.address != sol_usdc.address));
+        let refreshed = client.whirlpools().find(|p| p.address == sol_ray).unwrap();
+        assert_eq!(refreshed.liquidity, liquidity);
+    }
+
+    #[test]
+    fn test_load_tick_array_requires_whirlpool() {
+        let mut client = Client::new().unwrap();
+        let (address, data) = crate::swap::layout::load_fixture(SOL_USDC_TICK_ARRAYS[0]);
+        assert!(client.load_tick_array(address, &data).is_err());
+
+        load_sol_usdc(&mut client);
+        load_sol_usdc_tick_arrays(&mut client);
+
+        // Reloading the whirlpool keeps its tick arrays
+        let pool = load_sol_usdc(&mut client);
+        assert_eq!(pool.tick_arrays.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_quote_with_tick_arrays() {
+        let mut flat = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut flat);
+
+        let mut client = Client::new().unwrap();
+        load_sol_usdc(&mut client);
+        load_sol_usdc_tick_arrays(&mut client);
+
+        // Small swaps stay within the current liquidity range
+        let amount = 1_000_000_000;
+        assert_eq!(
+            client.get_quote(&pool.token_a, &pool.token_b, amount, DEFAULT_SLIPPAGE_BPS).await.unwrap().amount_out,
+            flat.get_quote(&pool.token_a, &pool.token_b, amount, DEFAULT_SLIPPAGE_BPS).await.unwrap().amount_out,
+        );
+
+        // 10k SOL crosses into the thinner range below tick -19968
+        let amount = 10_000_000_000_000;
+        let deep = flat.get_quote(&pool.token_a, &pool.token_b, amount, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        let real = client.get_quote(&pool.token_a, &pool.token_b, amount, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(real.amount_out < deep.amount_out);
+
+        assert!(real.price_impact_bps > deep.price_impact_bps);
+
+        // Liquidity runs out before the end of the loaded tick arrays
+        let amount = 100_000_000_000_000;
+        assert!(client.get_quote(&pool.token_a, &pool.token_b, amount, DEFAULT_SLIPPAGE_BPS).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_quote_exact_out() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+        load_sol_usdc_tick_arrays(&mut client);
+
+        // Exactly 150 USDC out of SOL, and back
+        for (token_in, token_out) in [(pool.token_a, pool.token_b), (pool.token_b, pool.token_a)] {
+            let quote = client.get_quote_exact_out(&token_in, &token_out, 150_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+            assert_eq!(quote.mode, SwapMode::ExactOut);
+            assert_eq!(quote.amount_out, 150_000_000);
+            assert!(quote.maximum_in > quote.amount_in);
+
+            let exact_in = client.get_quote(&token_in, &token_out, quote.amount_in, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+            assert!(exact_in.amount_out >= quote.amount_out);
+        }
+
+        // More than the loaded tick arrays hold
+        assert!(client.get_quote_exact_out(&pool.token_a, &pool.token_b, u64::MAX / 2, DEFAULT_SLIPPAGE_BPS).await.is_err());
+    }
+
+    /// Message of the v0 transaction the engine prepares for `quote`,
+    /// with the accounts and data of its whirlpool instruction
+    fn prepared_swap(client: Client, quote: &OrcaQuote, user: &Pubkey) -> (VersionedMessage, Vec<Pubkey>, Vec<u8>) {
+        let program_id = client.program_id;
+        let engine = SwapEngine::with_adapters(vec![Box::new(client)]).unwrap();
+        let message = engine.prepare_swap(&quote.clone().into(), user).unwrap().message;
+        let keys = message.static_account_keys();
+        let ix = message.instructions().iter()
+            .find(|ix| *ix.program_id(keys) == program_id)
+            .unwrap();
+        let accounts = ix.accounts.iter().map(|&i| keys[i as usize]).collect();
+        let data = ix.data.clone();
+        (message, accounts, data)
+    }
+
+    #[tokio::test]
+    async fn test_swap_exact_out_instruction() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+        let user = Pubkey::new_unique();
+
+        let quote = client.get_quote_exact_out(&pool.token_a, &pool.token_b, 150_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        let (_, _, data) = prepared_swap(client, &quote, &user);
+
+        assert_eq!(data[8..16], 150_000_000u64.to_le_bytes());
+        assert_eq!(data[16..24], quote.maximum_in.to_le_bytes());
+        // amount_specified_is_input, a_to_b
+        assert_eq!(data[40..], [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_swap_instruction() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+        let user = Pubkey::new_unique();
+
+        let quote = client.get_quote(&pool.token_a, &pool.token_b, 1_000_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        let (message, keys, data) = prepared_swap(client, &quote, &user);
+
+        assert_eq!(keys.len(), 11);
+        assert_eq!(keys[0], token::ID);
+        assert_eq!(keys[1], user);
+        let user_index = message.static_account_keys().iter().position(|k| *k == user).unwrap();
+        assert!(message.is_signer(user_index));
+        assert_eq!(keys[2], pool.address);
+        assert_eq!(keys[3], get_associated_token_address(&user, &pool.token_a));
+        assert_eq!(keys[4], pool.token_vault_a);
+        assert_eq!(keys[5], get_associated_token_address(&user, &pool.token_b));
+        assert_eq!(keys[6], pool.token_vault_b);
+        assert_eq!(keys[7..10], quote.tick_arrays[..]);
+        assert_eq!(keys[10].to_string(), "FoKYKtRpD25TKzBMndysKpgPqbj8AdLXjfpYHXn9PGTX");
+
+        assert_eq!(data.len(), 42);
+        assert_eq!(data[..8], SWAP_DISCRIMINATOR);
+        assert_eq!(data[8..16], 1_000_000_000u64.to_le_bytes());
+        assert_eq!(data[16..24], quote.minimum_out.to_le_bytes());
+        assert_eq!(data[24..40], whirlpool_math::MIN_SQRT_PRICE_X64.to_le_bytes());
+        assert_eq!(data[40..], [1, 1]);
+    }
+
+    #[test]
+    fn test_swap_v2_instruction() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+        let owner = Pubkey::new_unique();
+        let token_2022 = Pubkey::new_unique();
+        let accounts = SwapTokenAccounts {
+            owner_account_a: Pubkey::new_unique(),
+            owner_account_b: Pubkey::new_unique(),
+            token_program_a: token::ID,
+            token_program_b: token_2022,
+        };
+        let args = SwapArgs {
+            amount: 150_000_000,
+            other_amount_threshold: 1_000_000_000,
+            sqrt_price_limit: whirlpool_math::MAX_SQRT_PRICE_X64,
+            amount_specified_is_input: false,
+            a_to_b: false,
+        };
+        let tick_arrays = client.get_tick_arrays(&pool, false);
+
+        // Token-2022 mints need swap_v2
+        assert!(client.create_swap_instruction(&pool, &owner, &accounts, &args, &tick_arrays).is_err());
+
+        let ix = client.create_swap_v2_instruction(&pool, &owner, &accounts, &args, &tick_arrays).unwrap();
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys, vec![
+            token::ID,
+            token_2022,
+            MEMO_PROGRAM_ID,
+            owner,
+            pool.address,
+            pool.token_a,
+            pool.token_b,
+            accounts.owner_account_a,
+            pool.token_vault_a,
+            accounts.owner_account_b,
+            pool.token_vault_b,
+            tick_arrays[0],
+            tick_arrays[1],
+            tick_arrays[2],
+            client.derive_oracle(&pool.address),
+        ]);
+        assert!(ix.accounts[3].is_signer);
+        assert!(!ix.accounts[5].is_writable && ix.accounts[7].is_writable);
+
+        assert_eq!(ix.data.len(), 43);
+        assert_eq!(ix.data[..8], SWAP_V2_DISCRIMINATOR);
+        assert_eq!(ix.data[24..40], whirlpool_math::MAX_SQRT_PRICE_X64.to_le_bytes());
+        assert_eq!(ix.data[40..], [0, 0, 0]);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_transfer_fees() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+        load_sol_usdc_tick_arrays(&mut client);
+        let (sol, usdc) = (pool.token_a, pool.token_b);
+        let sol_in = client.get_quote(&sol, &usdc, 1_000_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        let usdc_in = client.get_quote(&usdc, &sol, 150_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+
+        // USDC as a Token-2022 mint withholding 1% of every transfer
+        let fee = TransferFee { epoch: 0, maximum_fee: u64::MAX, basis_points: 100 };
+        client.insert_mint(usdc, MintInfo { token_program: TOKEN_2022_PROGRAM_ID, transfer_fee: Some(fee) });
+
+        let quote = client.get_quote(&sol, &usdc, 1_000_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert_eq!(quote.amount_out, sol_in.amount_out - fee.fee(sol_in.amount_out));
+        assert_eq!(quote.token_program_out, TOKEN_2022_PROGRAM_ID);
+
+        let quote = client.get_quote(&usdc, &sol, 150_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        let reference = client.calculate_output(148_500_000, &pool, false).unwrap().0;
+        assert_eq!(quote.amount_out, reference);
+        assert!(quote.amount_out < usdc_in.amount_out);
+
+        // Exact output covers the fee on the way out
+        let quote = client.get_quote_exact_out(&sol, &usdc, 100_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        let reference = client.calculate_input(fee.pre_fee_amount(100_000_000).unwrap(), &pool, true).unwrap().0;
+        assert_eq!(quote.amount_in, reference);
+
+        // Token-2022 mints swap through swap_v2 with their own accounts
+        let user = Pubkey::new_unique();
+        let ix = client.swap_instruction(&quote.into(), &user).unwrap();
+        assert_eq!(ix.data[..8], SWAP_V2_DISCRIMINATOR);
+        assert_eq!(ix.accounts[0].pubkey, token::ID);
+        assert_eq!(ix.accounts[1].pubkey, TOKEN_2022_PROGRAM_ID);
+        let usdc_account = get_associated_token_address_with_program_id(&user, &usdc, &TOKEN_2022_PROGRAM_ID);
+        assert_eq!(ix.accounts[9].pubkey, usdc_account);
+    }
+
+    #[test]
+    fn test_swap_tick_arrays() {
+        let [a, b, c, d] = [(); 4].map(|_| Pubkey::new_unique());
+        assert_eq!(swap_tick_arrays(&[a, b, c]).unwrap(), [a, b, c]);
+        // Near the end of the tick range fewer arrays exist
+        assert_eq!(swap_tick_arrays(&[a]).unwrap(), [a, a, a]);
+        assert_eq!(swap_tick_arrays(&[a, b]).unwrap(), [a, b, b]);
+        assert!(swap_tick_arrays(&[]).is_err());
+        assert!(swap_tick_arrays(&[a, b, c, d]).is_err());
+    }
+}
@@ -4,15 +4,67 @@
 //! quote calculation and swap execution.
 
 use anchor_client::solana_sdk::{
-    instruction::Instruction,
+    instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
-    system_program,
-    sysvar,
-    transaction::Transaction,
 };
-use anchor_spl::token::{self, Token};
+use anchor_spl::{associated_token::get_associated_token_address, token};
 use anyhow::Result;
 use std::collections::HashMap;
+use tracing::debug;
+
+use super::{
+    adapter::{DexAdapter, SwapFailure},
+    discovery::{self, AccountProvider, PoolFilter},
+    layout::{read_pubkey, read_token_amount, read_u64},
+    math::{self, Rounding},
+    slippage,
+    DexType, Quote, SwapMode,
+};
+
+/// Instruction tag for `swapBaseIn` in the AMM v4 program
+const SWAP_BASE_IN_TAG: u8 = 9;
+
+/// Instruction tag for `swapBaseOut` in the AMM v4 program
+const SWAP_BASE_OUT_TAG: u8 = 11;
+
+/// `AmmError` codes of the AMM v4 program
+const AMM_ERROR_INVALID_STATUS: u32 = 22;
+const AMM_ERROR_EXCEEDED_SLIPPAGE: u32 = 30;
+const AMM_ERROR_EMPTY_FUNDS: u32 = 36;
+const AMM_ERROR_INSUFFICIENT_FUNDS: u32 = 40;
+
+/// Seed of the AMM authority PDA
+const AMM_AUTHORITY_SEED: &[u8] = b"amm authority";
+
+/// Size of an AMM v4 `AmmInfo` account
+pub const AMM_INFO_LEN: usize = 752;
+
+// `AmmInfo` field offsets
+const AMM_STATUS_OFFSET: usize = 0;
+const AMM_NONCE_OFFSET: usize = 8;
+const AMM_SWAP_FEE_NUMERATOR_OFFSET: usize = 176;
+const AMM_SWAP_FEE_DENOMINATOR_OFFSET: usize = 184;
+const AMM_NEED_TAKE_PNL_COIN_OFFSET: usize = 192;
+const AMM_NEED_TAKE_PNL_PC_OFFSET: usize = 200;
+const AMM_COIN_VAULT_OFFSET: usize = 336;
+const AMM_PC_VAULT_OFFSET: usize = 368;
+pub(crate) const AMM_COIN_MINT_OFFSET: usize = 400;
+pub(crate) const AMM_PC_MINT_OFFSET: usize = 432;
+const AMM_OPEN_ORDERS_OFFSET: usize = 496;
+const AMM_MARKET_OFFSET: usize = 528;
+const AMM_MARKET_PROGRAM_OFFSET: usize = 560;
+const AMM_TARGET_ORDERS_OFFSET: usize = 592;
+
+/// Size of a Serum/OpenBook `MarketState` account
+const MARKET_STATE_LEN: usize = 388;
+
+// `MarketState` field offsets (after the 5-byte "serum" head padding)
+const MARKET_VAULT_SIGNER_NONCE_OFFSET: usize = 45;
+const MARKET_COIN_VAULT_OFFSET: usize = 117;
+const MARKET_PC_VAULT_OFFSET: usize = 165;
+const MARKET_EVENT_QUEUE_OFFSET: usize = 253;
+const MARKET_BIDS_OFFSET: usize = 285;
+const MARKET_ASKS_OFFSET: usize = 317;
 
 /// Raydium pool state information
 #[derive(Debug, Clone)]
@@ -29,11 +81,225 @@ pub struct PoolState {
     pub reserve_b: u64,
     /// Pool fees (in basis points)
     pub fees_bps: u16,
+    /// Accounts referenced by swap instructions
+    pub keys: PoolKeys,
+}
+
+/// Accounts required to build Raydium AMM v4 swap instructions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolKeys {
+    /// AMM authority PDA
+    pub authority: Pubkey,
+    /// AMM open orders account
+    pub open_orders: Pubkey,
+    /// AMM target orders account
+    pub target_orders: Pubkey,
+    /// Pool coin (token A) vault
+    pub coin_vault: Pubkey,
+    /// Pool pc (token B) vault
+    pub pc_vault: Pubkey,
+    /// Serum/OpenBook program ID
+    pub market_program: Pubkey,
+    /// Serum/OpenBook market
+    pub market: Pubkey,
+    /// Market bids
+    pub market_bids: Pubkey,
+    /// Market asks
+    pub market_asks: Pubkey,
+    /// Market event queue
+    pub market_event_queue: Pubkey,
+    /// Market coin vault
+    pub market_coin_vault: Pubkey,
+    /// Market pc vault
+    pub market_pc_vault: Pubkey,
+    /// Market vault signer PDA
+    pub market_vault_signer: Pubkey,
+}
+
+/// Raw account data needed to decode a Raydium pool
+#[derive(Debug, Clone, Copy)]
+pub struct PoolAccounts<'a> {
+    /// `AmmInfo` account data
+    pub amm: &'a [u8],
+    /// Pool coin vault token account data
+    pub coin_vault: &'a [u8],
+    /// Pool pc vault token account data
+    pub pc_vault: &'a [u8],
+    /// Serum/OpenBook market account data
+    pub market: &'a [u8],
+}
+
+/// Fields of the AMM v4 `AmmInfo` account used for quoting and swaps
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmmInfo {
+    /// AMM status
+    pub status: u64,
+    /// Bump used to derive the AMM authority
+    pub nonce: u64,
+    /// Swap fee numerator
+    pub swap_fee_numerator: u64,
+    /// Swap fee denominator
+    pub swap_fee_denominator: u64,
+    /// Coin owed to the protocol, still held in the vault
+    pub need_take_pnl_coin: u64,
+    /// Pc owed to the protocol, still held in the vault
+    pub need_take_pnl_pc: u64,
+    /// Pool coin vault
+    pub coin_vault: Pubkey,
+    /// Pool pc vault
+    pub pc_vault: Pubkey,
+    /// Coin mint
+    pub coin_mint: Pubkey,
+    /// Pc mint
+    pub pc_mint: Pubkey,
+    /// AMM open orders account
+    pub open_orders: Pubkey,
+    /// Serum/OpenBook market
+    pub market: Pubkey,
+    /// Serum/OpenBook program ID
+    pub market_program: Pubkey,
+    /// AMM target orders account
+    pub target_orders: Pubkey,
+}
+
+impl AmmInfo {
+    /// Decode an `AmmInfo` account
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() != AMM_INFO_LEN {
+            anyhow::bail!("Invalid AmmInfo account: expected {} bytes, got {}", AMM_INFO_LEN, data.len());
+        }
+
+        let status = read_u64(data, AMM_STATUS_OFFSET)?;
+        if status == 0 {
+            anyhow::bail!("AMM account is not initialized");
+        }
+
+        Ok(Self {
+            status,
+            nonce: read_u64(data, AMM_NONCE_OFFSET)?,
+            swap_fee_numerator: read_u64(data, AMM_SWAP_FEE_NUMERATOR_OFFSET)?,
+            swap_fee_denominator: read_u64(data, AMM_SWAP_FEE_DENOMINATOR_OFFSET)?,
+            need_take_pnl_coin: read_u64(data, AMM_NEED_TAKE_PNL_COIN_OFFSET)?,
+            need_take_pnl_pc: read_u64(data, AMM_NEED_TAKE_PNL_PC_OFFSET)?,
+            coin_vault: read_pubkey(data, AMM_COIN_VAULT_OFFSET)?,
+            pc_vault: read_pubkey(data, AMM_PC_VAULT_OFFSET)?,
+            coin_mint: read_pubkey(data, AMM_COIN_MINT_OFFSET)?,
+            pc_mint: read_pubkey(data, AMM_PC_MINT_OFFSET)?,
+            open_orders: read_pubkey(data, AMM_OPEN_ORDERS_OFFSET)?,
+            market: read_pubkey(data, AMM_MARKET_OFFSET)?,
+            market_program: read_pubkey(data, AMM_MARKET_PROGRAM_OFFSET)?,
+            target_orders: read_pubkey(data, AMM_TARGET_ORDERS_OFFSET)?,
+        })
+    }
+
+    /// Swap fee in basis points
+    pub fn fees_bps(&self) -> Result<u16> {
+        if self.swap_fee_denominator == 0 {
+            anyhow::bail!("Invalid AMM fee: zero denominator");
+        }
+        let bps = self.swap_fee_numerator as u128 * 10_000 / self.swap_fee_denominator as u128;
+        u16::try_from(bps).map_err(|_| anyhow::anyhow!("Invalid AMM fee: {} bps", bps))
+    }
+}
+
+/// Fields of a Serum/OpenBook market needed by AMM swaps
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketInfo {
+    /// Bump used to derive the vault signer
+    pub vault_signer_nonce: u64,
+    /// Market coin vault
+    pub coin_vault: Pubkey,
+    /// Market pc vault
+    pub pc_vault: Pubkey,
+    /// Event queue
+    pub event_queue: Pubkey,
+    /// Bids
+    pub bids: Pubkey,
+    /// Asks
+    pub asks: Pubkey,
+}
+
+impl MarketInfo {
+    /// Decode a `MarketState` account
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() != MARKET_STATE_LEN {
+            anyhow::bail!("Invalid market account: expected {} bytes, got {}", MARKET_STATE_LEN, data.len());
+        }
+
+        Ok(Self {
+            vault_signer_nonce: read_u64(data, MARKET_VAULT_SIGNER_NONCE_OFFSET)?,
+            coin_vault: read_pubkey(data, MARKET_COIN_VAULT_OFFSET)?,
+            pc_vault: read_pubkey(data, MARKET_PC_VAULT_OFFSET)?,
+            event_queue: read_pubkey(data, MARKET_EVENT_QUEUE_OFFSET)?,
+            bids: read_pubkey(data, MARKET_BIDS_OFFSET)?,
+            asks: read_pubkey(data, MARKET_ASKS_OFFSET)?,
+        })
+    }
+}
+
+impl PoolState {
+    /// Decode a pool from its `AmmInfo`, vault and market accounts
+    pub fn decode(
+        address: Pubkey,
+        program_id: &Pubkey,
+        accounts: &PoolAccounts<'_>,
+    ) -> Result<Self> {
+        let amm = AmmInfo::decode(accounts.amm)?;
+        let market = MarketInfo::decode(accounts.market)?;
+
+        let authority = Pubkey::create_program_address(
+            &[AMM_AUTHORITY_SEED, &[amm.nonce as u8]],
+            program_id,
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid AMM authority nonce: {}", e))?;
+        let market_vault_signer = Pubkey::create_program_address(
+            &[amm.market.as_ref(), &market.vault_signer_nonce.to_le_bytes()],
+            &amm.market_program,
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid market vault signer nonce: {}", e))?;
+
+        // Vault balances include protocol fees that have not been
+        // collected yet; these are not available to swappers.
+        let reserve_a = read_token_amount(accounts.coin_vault)?
+            .saturating_sub(amm.need_take_pnl_coin);
+        let reserve_b = read_token_amount(accounts.pc_vault)?
+            .saturating_sub(amm.need_take_pnl_pc);
+
+        Ok(Self {
+            address,
+            token_a: amm.coin_mint,
+            token_b: amm.pc_mint,
+            reserve_a,
+            reserve_b,
+            fees_bps: amm.fees_bps()?,
+            keys: PoolKeys {
+                authority,
+                open_orders: amm.open_orders,
+                target_orders: amm.target_orders,
+                coin_vault: amm.coin_vault,
+                pc_vault: amm.pc_vault,
+                market_program: amm.market_program,
+                market: amm.market,
+                market_bids: market.bids,
+                market_asks: market.asks,
+                market_event_queue: market.event_queue,
+                market_coin_vault: market.coin_vault,
+                market_pc_vault: market.pc_vault,
+                market_vault_signer,
+            },
+        })
+    }
 }
 
 /// Quote information from Raydium
 #[derive(Debug, Clone)]
 pub struct RaydiumQuote {
+    /// Which side of the swap is fixed
+    pub mode: SwapMode,
+    /// Source token mint
+    pub token_in: Pubkey,
+    /// Destination token mint
+    pub token_out: Pubkey,
     /// Input amount
     pub amount_in: u64,
     /// Expected output amount
@@ -44,9 +310,33 @@ pub struct RaydiumQuote {
     pub pool: Pubkey,
     /// Minimum output amount (with slippage)
     pub minimum_out: u64,
+    /// Maximum input amount (with slippage)
+    pub maximum_in: u64,
+}
+
+impl From<RaydiumQuote> for Quote {
+    fn from(quote: RaydiumQuote) -> Self {
+        Self {
+            dex_type: DexType::Raydium,
+            mode: quote.mode,
+            token_in: quote.token_in,
+            token_out: quote.token_out,
+            // AMM v4 pools only hold SPL Token mints
+            token_program_in: token::ID,
+            token_program_out: token::ID,
+            pool: quote.pool,
+            amount_in: quote.amount_in,
+            amount_out: quote.amount_out,
+            price_impact_bps: quote.price_impact_bps,
+            minimum_out: quote.minimum_out,
+            maximum_in: quote.maximum_in,
+            extra_accounts: Vec::new(),
+        }
+    }
 }
 
 /// Raydium DEX client
+#[derive(Clone)]
 pub struct Client {
     /// Pool cache
     pools: HashMap<(Pubkey, Pubkey), PoolState>,
@@ -76,56 +366,169 @@ impl Client {
         token_in: &Pubkey,
         token_out: &Pubkey,
         amount: u64,
+        slippage_bps: u16,
     ) -> Result<RaydiumQuote> {
         // Find pool for token pair
         let pool = self.get_pool(token_in, token_out)?;
+        let (reserve_in, reserve_out) = if token_in == &pool.token_a {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
         
         // Calculate output amount using AMM formula
         let (amount_out, price_impact) = self.calculate_output(
             amount,
-            pool.reserve_a,
-            pool.reserve_b,
+            reserve_in,
+            reserve_out,
             pool.fees_bps,
         )?;
 
-        // Calculate minimum output with 1% slippage
-        let minimum_out = amount_out * 99 / 100;
+        // Calculate minimum output with slippage
+        let minimum_out = slippage::minimum_out(amount_out, slippage_bps)?;
 
         Ok(RaydiumQuote {
+            mode: SwapMode::ExactIn,
+            token_in: *token_in,
+            token_out: *token_out,
             amount_in: amount,
             amount_out,
             price_impact_bps: price_impact,
             pool: pool.address,
             minimum_out,
+            maximum_in: amount,
         })
     }
 
-    /// Prepare swap transaction
-    pub fn prepare_swap(
+    /// Get quote for receiving exactly `amount_out`
+    pub async fn get_quote_exact_out(
         &self,
-        quote: &RaydiumQuote,
-        user: &Pubkey,
-    ) -> Result<Transaction> {
-        let pool = self.pools.values()
-            .find(|p| p.address == quote.pool)
-            .ok_or_else(|| anyhow::anyhow!("Pool not found"))?;
-
-        // Create swap instruction
-        let swap_ix = self.create_swap_instruction(
-            user,
-            &pool,
-            quote.amount_in,
-            quote.minimum_out,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_out: u64,
+        slippage_bps: u16,
+    ) -> Result<RaydiumQuote> {
+        // Find pool for token pair
+        let pool = self.get_pool(token_in, token_out)?;
+        let (reserve_in, reserve_out) = if token_in == &pool.token_a {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+
+        let (amount_in, price_impact) = self.calculate_input(
+            amount_out,
+            reserve_in,
+            reserve_out,
+            pool.fees_bps,
         )?;
 
-        // Create transaction
-        Ok(Transaction::new_with_payer(
-            &[swap_ix],
-            Some(user),
-        ))
+        // Calculate maximum input with slippage
+        let maximum_in = slippage::maximum_in(amount_in, slippage_bps)?;
+
+        Ok(RaydiumQuote {
+            mode: SwapMode::ExactOut,
+            token_in: *token_in,
+            token_out: *token_out,
+            amount_in,
+            amount_out,
+            price_impact_bps: price_impact,
+            pool: pool.address,
+            minimum_out: amount_out,
+            maximum_in,
+        })
+    }
+
+    /// Decode a pool from raw account data and add it to the pool cache.
+    ///
+    /// Returns the cached pool for the pool's mint pair, which is a
+    /// deeper pool if one is already loaded (see [`Client::insert_pool`]).
+    pub fn load_pool(
+        &mut self,
+        address: Pubkey,
+        accounts: &PoolAccounts<'_>,
+    ) -> Result<&PoolState> {
+        let pool = PoolState::decode(address, &self.program_id, accounts)?;
+        let key = (pool.token_a, pool.token_b);
+        self.insert_pool(pool);
+        Ok(&self.pools[&key])
+    }
+
+    /// Program ID
+    pub fn program_id(&self) -> &Pubkey {
+        &self.program_id
+    }
+
+    /// Add a pool to the pool cache.
+    ///
+    /// Only the deepest pool per mint pair is kept, since quotes are
+    /// taken from a single pool.
+    pub fn insert_pool(&mut self, pool: PoolState) {
+        let depth = |p: &PoolState| p.reserve_a as u128 * p.reserve_b as u128;
+        let key = (pool.token_a, pool.token_b);
+        match self.pools.get(&key) {
+            Some(existing) if existing.address != pool.address && depth(existing) >= depth(&pool) => {}
+            _ => {
+                self.pools.insert(key, pool);
+            }
+        }
+    }
+
+    /// Discover pools on-chain and add them to the pool cache.
+    ///
+    /// Returns the number of pools found.
+    pub async fn discover_pools(
+        &mut self,
+        rpc: &dyn AccountProvider,
+        filter: &PoolFilter,
+    ) -> Result<usize> {
+        let pools = discovery::discover_raydium_pools(rpc, &self.program_id, filter).await?;
+        let found = pools.len();
+        for pool in pools {
+            self.insert_pool(pool);
+        }
+        Ok(found)
+    }
+
+    /// Cached pools
+    pub fn pools(&self) -> impl Iterator<Item = &PoolState> {
+        self.pools.values()
+    }
+
+    /// Reload cached pools from chain, dropping pools that no longer exist
+    /// or no longer decode
+    pub async fn refresh_pools(&mut self, rpc: &dyn AccountProvider) -> Result<()> {
+        let pools: Vec<[Pubkey; 4]> = self
+            .pools
+            .values()
+            .map(|p| [p.address, p.keys.coin_vault, p.keys.pc_vault, p.keys.market])
+            .collect();
+        let addresses: Vec<Pubkey> = pools.iter().flatten().copied().collect();
+        let accounts = discovery::get_multiple_accounts_chunked(rpc, &addresses).await?;
+
+        for (keys, accounts) in pools.iter().zip(accounts.chunks(4)) {
+            let address = keys[0];
+            let [Some(amm), Some(coin_vault), Some(pc_vault), Some(market)] = accounts else {
+                debug!("Dropping Raydium pool {}: missing accounts", address);
+                self.pools.retain(|_, p| p.address != address);
+                continue;
+            };
+            let accounts = PoolAccounts { amm, coin_vault, pc_vault, market };
+            if let Err(e) = self.load_pool(address, &accounts) {
+                debug!("Dropping Raydium pool {}: {}", address, e);
+                self.pools.retain(|_, p| p.address != address);
+            }
+        }
+        Ok(())
     }
 
     // Private helper methods
+    fn find_pool(&self, address: &Pubkey) -> Result<&PoolState> {
+        self.pools.values()
+            .find(|p| p.address == *address)
+            .ok_or_else(|| anyhow::anyhow!("Pool not found"))
+    }
+
     fn get_pool(&self, token_a: &Pubkey, token_b: &Pubkey) -> Result<&PoolState> {
         self.pools
             .get(&(*token_a, *token_b))
@@ -140,44 +543,215 @@ impl Client {
         reserve_out: u64,
         fees_bps: u16,
     ) -> Result<(u64, u16)> {
-        // Apply fees
-        let amount_with_fees = amount_in * (10000 - fees_bps as u64) / 10000;
-
         // Calculate output using constant product formula
-        let numerator = amount_with_fees * reserve_out;
-        let denominator = reserve_in + amount_with_fees;
-        let amount_out = numerator / denominator;
+        let amount_out = math::constant_product_output(amount_in, reserve_in, reserve_out, fees_bps)?;
 
         // Calculate price impact
-        let price_impact = ((amount_in as f64 / reserve_in as f64) * 10000.0) as u16;
+        let price_impact = price_impact(amount_in, amount_out, reserve_in, reserve_out, fees_bps)?;
 
         Ok((amount_out, price_impact))
     }
 
+    fn calculate_input(
+        &self,
+        amount_out: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        fees_bps: u16,
+    ) -> Result<(u64, u16)> {
+        if amount_out >= reserve_out {
+            anyhow::bail!(
+                "Insufficient liquidity: {} requested, {} in reserve",
+                amount_out,
+                reserve_out,
+            );
+        }
+
+        // Invert the constant product formula, rounding up so the
+        // resulting input never falls short of `amount_out`
+        let amount_in = math::constant_product_input(amount_out, reserve_in, reserve_out, fees_bps)?;
+
+        // Calculate price impact
+        let price_impact = price_impact(amount_in, amount_out, reserve_in, reserve_out, fees_bps)?;
+
+        Ok((amount_in, price_impact))
+    }
+
+    /// Build a `swapBaseIn` instruction.
+    ///
+    /// The swap direction is inferred by the program from the mint of
+    /// `user_source`, so the same account list serves both directions.
     fn create_swap_instruction(
         &self,
-        user: &Pubkey,
         pool: &PoolState,
+        user_source: &Pubkey,
+        user_destination: &Pubkey,
+        owner: &Pubkey,
         amount_in: u64,
         minimum_out: u64,
     ) -> Result<Instruction> {
-        // This is a simplified version - actual Raydium instruction would be more complex
+        let mut data = Vec::with_capacity(17);
+        data.push(SWAP_BASE_IN_TAG);
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&minimum_out.to_le_bytes());
+
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: swap_accounts(pool, user_source, user_destination, owner),
+            data,
+        })
+    }
+
+    /// Build a `swapBaseOut` instruction, spending at most `max_amount_in`
+    /// to receive exactly `amount_out`.
+    fn create_swap_base_out_instruction(
+        &self,
+        pool: &PoolState,
+        user_source: &Pubkey,
+        user_destination: &Pubkey,
+        owner: &Pubkey,
+        max_amount_in: u64,
+        amount_out: u64,
+    ) -> Result<Instruction> {
+        let mut data = Vec::with_capacity(17);
+        data.push(SWAP_BASE_OUT_TAG);
+        data.extend_from_slice(&max_amount_in.to_le_bytes());
+        data.extend_from_slice(&amount_out.to_le_bytes());
+
         Ok(Instruction {
             program_id: self.program_id,
-            accounts: vec![
-                // Add necessary account metas
-            ],
-            data: vec![
-                // Add instruction data
-            ],
+            accounts: swap_accounts(pool, user_source, user_destination, owner),
+            data,
         })
     }
 }
 
+/// Price impact of a swap against the pool price before it, with the
+/// fee excluded
+fn price_impact(
+    amount_in: u64,
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fees_bps: u16,
+) -> Result<u16> {
+    let amount_after_fees = math::deduct_fee_bps(amount_in, fees_bps)?;
+    let expected_out = math::mul_div(
+        amount_after_fees as u128,
+        reserve_out as u128,
+        reserve_in as u128,
+        Rounding::Down,
+    )?;
+    Ok(math::price_impact_bps(expected_out, amount_out)?)
+}
+
+/// Accounts shared by `swapBaseIn` and `swapBaseOut`
+fn swap_accounts(
+    pool: &PoolState,
+    user_source: &Pubkey,
+    user_destination: &Pubkey,
+    owner: &Pubkey,
+) -> Vec<AccountMeta> {
+    let keys = &pool.keys;
+    vec![
+        AccountMeta::new_readonly(token::ID, false),
+        AccountMeta::new(pool.address, false),
+        AccountMeta::new_readonly(keys.authority, false),
+        AccountMeta::new(keys.open_orders, false),
+        AccountMeta::new(keys.target_orders, false),
+        AccountMeta::new(keys.coin_vault, false),
+        AccountMeta::new(keys.pc_vault, false),
+        AccountMeta::new_readonly(keys.market_program, false),
+        AccountMeta::new(keys.market, false),
+        AccountMeta::new(keys.market_bids, false),
+        AccountMeta::new(keys.market_asks, false),
+        AccountMeta::new(keys.market_event_queue, false),
+        AccountMeta::new(keys.market_coin_vault, false),
+        AccountMeta::new(keys.market_pc_vault, false),
+        AccountMeta::new_readonly(keys.market_vault_signer, false),
+        AccountMeta::new(*user_source, false),
+        AccountMeta::new(*user_destination, false),
+        AccountMeta::new_readonly(*owner, true),
+    ]
+}
+
+#[async_trait::async_trait]
+impl DexAdapter for Client {
+    fn dex_type(&self) -> DexType {
+        DexType::Raydium
+    }
+
+    fn supported_pairs(&self) -> Vec<(Pubkey, Pubkey)> {
+        self.pools.keys().copied().collect()
+    }
+
+    async fn quote(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount: u64,
+        slippage_bps: u16,
+    ) -> Result<Quote> {
+        Ok(self.get_quote(token_in, token_out, amount, slippage_bps).await?.into())
+    }
+
+    async fn quote_exact_out(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_out: u64,
+        slippage_bps: u16,
+    ) -> Result<Quote> {
+        Ok(self.get_quote_exact_out(token_in, token_out, amount_out, slippage_bps).await?.into())
+    }
+
+    fn build_swap_instructions(&self, quote: &Quote, user: &Pubkey) -> Result<Vec<Instruction>> {
+        let pool = self.find_pool(&quote.pool)?;
+        let user_source = get_associated_token_address(user, &quote.token_in);
+        let user_destination = get_associated_token_address(user, &quote.token_out);
+        let ix = match quote.mode {
+            SwapMode::ExactIn => self.create_swap_instruction(
+                pool,
+                &user_source,
+                &user_destination,
+                user,
+                quote.amount_in,
+                quote.minimum_out,
+            )?,
+            SwapMode::ExactOut => self.create_swap_base_out_instruction(
+                pool,
+                &user_source,
+                &user_destination,
+                user,
+                quote.maximum_in,
+                quote.amount_out,
+            )?,
+        };
+        Ok(vec![ix])
+    }
+
+    fn decode_error(&self, program_id: &Pubkey, code: u32) -> Option<SwapFailure> {
+        if *program_id != self.program_id {
+            return None;
+        }
+        Some(match code {
+            AMM_ERROR_EXCEEDED_SLIPPAGE => SwapFailure::SlippageExceeded,
+            AMM_ERROR_EMPTY_FUNDS => SwapFailure::InsufficientLiquidity,
+            AMM_ERROR_INVALID_STATUS => SwapFailure::Other("InvalidStatus"),
+            AMM_ERROR_INSUFFICIENT_FUNDS => SwapFailure::Other("InsufficientFunds"),
+            _ => SwapFailure::Other("Unknown"),
+        })
+    }
+
+    async fn refresh(&mut self, rpc: &dyn AccountProvider) -> Result<()> {
+        self.refresh_pools(rpc).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::signature::Keypair;
+    use crate::swap::DEFAULT_SLIPPAGE_BPS;
 
     #[tokio::test]
     async fn test_quote_calculation() {
@@ -195,5 +769,318 @@ mod tests {
             30,  // 0.3% fee
         ).unwrap();
         assert!(impact < 100); // Less than 1% impact
+
+        // The fee alone is not price impact
+        let (_, impact) = client.calculate_output(1_000_000, 1_000_000_000_000, 1_000_000_000_000, 30).unwrap();
+        assert_eq!(impact, 0);
+
+        // Constant product impact is `net_in / (reserve_in + net_in)`,
+        // independent of the price
+        for reserve_out in [1_000_000_000, 150_000_000_000] {
+            let (_, impact) = client.calculate_output(100_000_000, 1_000_000_000, reserve_out, 0).unwrap();
+            assert_eq!(impact, 909);
+            let (_, impact) = client.calculate_output(1_000_000_000, 1_000_000_000, reserve_out, 0).unwrap();
+            assert_eq!(impact, 5_000);
+        }
+
+        // Well past the reserve, impact approaches but never exceeds 100%
+        let (_, impact) = client.calculate_output(u64::MAX, 1_000, 1_000, 30).unwrap();
+        assert!(impact > 9_990 && impact <= 10_000);
+    }
+
+    #[test]
+    fn test_math_errors() {
+        let client = Client::new().unwrap();
+        // Reserves this deep overflowed the old u64 product
+        let (amount_out, _) = client.calculate_output(u64::MAX / 2, u64::MAX / 2, u64::MAX, 25).unwrap();
+        assert!(amount_out > u64::MAX / 4);
+
+        let err = client.calculate_output(1_000, 1_000, 1_000, 10_001).unwrap_err();
+        assert_eq!(err.downcast_ref::<math::MathError>(), Some(&math::MathError::InvalidFee(10_001)));
+        let err = client.calculate_output(1_000, 0, 1_000, 25).unwrap_err();
+        assert_eq!(err.downcast_ref::<math::MathError>(), Some(&math::MathError::DivisionByZero));
+    }
+
+    fn test_pool() -> PoolState {
+        let key = |b: u8| Pubkey::new_from_array([b; 32]);
+        PoolState {
+            address: key(1),
+            token_a: key(20),
+            token_b: key(21),
+            reserve_a: 0,
+            reserve_b: 0,
+            fees_bps: 25,
+            keys: PoolKeys {
+                authority: key(2),
+                open_orders: key(3),
+                target_orders: key(4),
+                coin_vault: key(5),
+                pc_vault: key(6),
+                market_program: key(7),
+                market: key(8),
+                market_bids: key(9),
+                market_asks: key(10),
+                market_event_queue: key(11),
+                market_coin_vault: key(12),
+                market_pc_vault: key(13),
+                market_vault_signer: key(14),
+            },
+        }
+    }
+
+    #[test]
+    fn test_swap_base_in_data() {
+        let client = Client::new().unwrap();
+        let key = |b: u8| Pubkey::new_from_array([b; 32]);
+        let ix = client.create_swap_instruction(
+            &test_pool(),
+            &key(15),
+            &key(16),
+            &key(17),
+            1_000_000,
+            24_750,
+        ).unwrap();
+
+        assert_eq!(ix.data, vec![
+            9,
+            0x40, 0x42, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xae, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        assert_eq!(ix.accounts.len(), 18);
+        assert_eq!(ix.accounts[0].pubkey, token::ID);
+        assert!(ix.accounts[17].is_signer);
+        assert!(ix.accounts.iter().take(17).all(|meta| !meta.is_signer));
+    }
+
+    /// Checks `swapBaseIn` against the instruction Raydium's SDK builds
+    /// (`makeSwapFixedInInstruction`) for the mainnet SOL/USDC pool,
+    /// serialized as a web3.js `TransactionInstruction`
+    #[test]
+    fn test_swap_base_in_sdk() {
+        use base64::Engine;
+
+        let expected: serde_json::Value = serde_json::from_str(
+            include_str!("../tests/fixtures/raydium/swap_base_in.json"),
+        ).unwrap();
+        let key = |s: &str| s.parse::<Pubkey>().unwrap();
+        let pool = PoolState {
+            address: key("58oQChx4yWmvKdwLLZzBi4ChoCc2fqCUWBkwMihLYQo2"),
+            token_a: key("So11111111111111111111111111111111111111112"),
+            token_b: key("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+            reserve_a: 0,
+            reserve_b: 0,
+            fees_bps: 25,
+            keys: PoolKeys {
+                authority: key("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"),
+                open_orders: key("HmiHHzq4Fym9e1D4qzLS6LDDM3tNsCTBPDWHTLZ763jY"),
+                target_orders: key("CZza3Ej4Mc58MnxWA385itCC9jCo3L1D7zc3LKy1bZMR"),
+                coin_vault: key("DQyrAcCrDXQ7NeoqGgDCZwBvWDcYmFCjSb9JtteuvPpz"),
+                pc_vault: key("HLmqeL62xR1QoZ1HKKbXRrdN1p3phKpxRMb2VVopvBBz"),
+                market_program: key("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX"),
+                market: key("8BnEgHoWFysVcuFFX7QztDmzuH8r5ZFvyP3sYwn1XTh6"),
+                market_bids: key("5jWUncPNBMZJ3sTHKmMLszypVkoRK6bfEQMQUHweeQnh"),
+                market_asks: key("EaXdHx7x3mdGA38j5RSmKYSXMzAFzzUXCLNBEDXDn1d5"),
+                market_event_queue: key("8CvwxZ9Db6XbLD46NZwwmVDZZRDy7eydFcAGkXKh9axa"),
+                market_coin_vault: key("CKxTHwM9fPMRRvZmFnFoqKNd9pQR21c5Aq9bh5h9oghX"),
+                market_pc_vault: key("6A5NHCj1yF6urc9wZNe6Bcjj4LVszQNj5DwAWG97yzMu"),
+                market_vault_signer: key("CTz5UMLQm2SRWHzQnU62Pi4yJqbNGjgRBHqqp6oDHfF7"),
+            },
+        };
+        let client = Client::new().unwrap();
+        let ix = client.create_swap_instruction(
+            &pool,
+            &key("5Rtvwg6C7fnCFDSaLQmQJYp8kvVxLVeubPTN8o4yapQc"),
+            &key("DEb5yphxEaPc5BN118svVN4R3GFu9jKs31Gcv5yekjZx"),
+            &key("67vHA8qZGCJKw1UNGUJZME4MwEWDRGWzp7MGvsut43A8"),
+            1_000_000_000,
+            145_000_000,
+        ).unwrap();
+
+        assert_eq!(ix.program_id, key(expected["programId"].as_str().unwrap()));
+        let accounts: Vec<AccountMeta> = expected["keys"].as_array().unwrap().iter()
+            .map(|meta| AccountMeta {
+                pubkey: key(meta["pubkey"].as_str().unwrap()),
+                is_signer: meta["isSigner"].as_bool().unwrap(),
+                is_writable: meta["isWritable"].as_bool().unwrap(),
+            })
+            .collect();
+        assert_eq!(ix.accounts, accounts);
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(expected["data"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(ix.data, data);
+    }
+
+    #[test]
+    fn test_swap_base_out_data() {
+        let client = Client::new().unwrap();
+        let key = |b: u8| Pubkey::new_from_array([b; 32]);
+        let base_in = client.create_swap_instruction(
+            &test_pool(),
+            &key(15),
+            &key(16),
+            &key(17),
+            1_000_000,
+            24_750,
+        ).unwrap();
+        let ix = client.create_swap_base_out_instruction(
+            &test_pool(),
+            &key(15),
+            &key(16),
+            &key(17),
+            1_000_000,
+            24_750,
+        ).unwrap();
+
+        assert_eq!(ix.data, vec![
+            11,
+            0x40, 0x42, 0x0f, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xae, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        assert_eq!(ix.accounts, base_in.accounts);
+    }
+
+    #[test]
+    fn test_calculate_input() {
+        let client = Client::new().unwrap();
+        let (reserve_in, reserve_out) = (1_000_000_000, 2_000_000_000);
+        for amount_out in [1, 999, 1_000_000, 500_000_000] {
+            let (amount_in, _) = client.calculate_input(amount_out, reserve_in, reserve_out, 25).unwrap();
+            // Enough input to cover `amount_out`, without overshooting by a unit
+            let (out, _) = client.calculate_output(amount_in, reserve_in, reserve_out, 25).unwrap();
+            assert!(out >= amount_out);
+            let (short, _) = client.calculate_output(amount_in - 1, reserve_in, reserve_out, 25).unwrap();
+            assert!(short <= amount_out);
+        }
+
+        assert!(client.calculate_input(reserve_out, reserve_in, reserve_out, 25).is_err());
+    }
+
+    /// Load the synthetic SOL/USDC pool fixture. Its accounts live at
+    /// `Fixture...` addresses so they cannot be mistaken for mainnet dumps.
+    fn load_sol_usdc(client: &mut Client) -> PoolState {
+        use crate::swap::layout::load_fixture;
+
+        let (address, amm) = load_fixture(include_str!("../tests/fixtures/raydium/sol_usdc_amm.json"));
+        let (_, coin_vault) = load_fixture(include_str!("../tests/fixtures/raydium/sol_usdc_coin_vault.json"));
+        let (_, pc_vault) = load_fixture(include_str!("../tests/fixtures/raydium/sol_usdc_pc_vault.json"));
+        let (_, market) = load_fixture(include_str!("../tests/fixtures/raydium/sol_usdc_market.json"));
+
+        client.load_pool(address, &PoolAccounts {
+            amm: &amm,
+            coin_vault: &coin_vault,
+            pc_vault: &pc_vault,
+            market: &market,
+        }).unwrap().clone()
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let client = Client::new().unwrap();
+        let program = *client.program_id();
+        assert_eq!(client.decode_error(&program, 30), Some(SwapFailure::SlippageExceeded));
+        assert_eq!(client.decode_error(&program, 36), Some(SwapFailure::InsufficientLiquidity));
+        assert_eq!(client.decode_error(&program, 22), Some(SwapFailure::Other("InvalidStatus")));
+        assert_eq!(client.decode_error(&Pubkey::new_unique(), 30), None);
+    }
+
+    #[test]
+    fn test_decode_amm_info() {
+        let (_, amm) = crate::swap::layout::load_fixture(
+            include_str!("../tests/fixtures/raydium/sol_usdc_amm.json"),
+        );
+        let info = AmmInfo::decode(&amm).unwrap();
+
+        assert_eq!(info.nonce, 254);
+        assert_eq!(info.fees_bps().unwrap(), 25);
+        assert_eq!(info.coin_mint.to_string(), "So11111111111111111111111111111111111111112");
+        assert_eq!(info.pc_mint.to_string(), "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+        assert!(AmmInfo::decode(&amm[..AMM_INFO_LEN - 1]).is_err());
+        assert!(AmmInfo::decode(&[0u8; AMM_INFO_LEN]).is_err());
+    }
+
+    #[test]
+    fn test_load_pool() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+
+        assert_eq!(pool.address.to_string(), "FixtureRaydiumSo1UsdcAmm11111111111111111111");
+        assert_eq!(pool.reserve_a, 150_000_000_000_000 - 1_000_000_000);
+        assert_eq!(pool.reserve_b, 22_500_000_000_000 - 150_000_000);
+        assert_eq!(pool.fees_bps, 25);
+        assert_eq!(pool.keys.authority.to_string(), "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1");
+        assert_eq!(pool.keys.market_vault_signer.to_string(), "xgjUxGQjHxErWezvWpsDTy5v7ktz5WjrT43tifgzJrh");
+        assert_eq!(pool.keys.market_bids.to_string(), "FixtureRaydiumSo1UsdcBids1111111111111111111");
+        assert_eq!(pool.keys.market_event_queue.to_string(), "FixtureRaydiumSo1UsdcEventQueue1111111111111");
+        assert_eq!(pool.keys.target_orders.to_string(), "FixtureRaydiumSo1UsdcTargetorders11111111111");
+
+        assert!(client.get_pool(&pool.token_b, &pool.token_a).is_ok());
+
+        // A deeper pool for the same pair is not displaced by reloading
+        let deeper = PoolState {
+            address: Pubkey::new_unique(),
+            reserve_a: pool.reserve_a * 2,
+            ..pool.clone()
+        };
+        client.insert_pool(deeper.clone());
+        assert_eq!(load_sol_usdc(&mut client).address, deeper.address);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_skips_undecodable_pool() {
+        use crate::swap::discovery::tests::FixtureRpc;
+        use crate::swap::layout::{load_fixture, token_account};
+
+        let mut rpc = FixtureRpc::new();
+        let mut client = Client::new().unwrap();
+        client.discover_pools(&rpc, &PoolFilter::All).await.unwrap();
+        let (sol_usdc, _) = load_fixture(include_str!("../tests/fixtures/raydium/sol_usdc_amm.json"));
+        let ray_usdc = client.pools().find(|p| p.address != sol_usdc).unwrap().clone();
+
+        // The SOL/USDC AMM is wiped while the RAY/USDC vault moves
+        rpc.insert_account(sol_usdc, *client.program_id(), vec![0; AMM_INFO_LEN]);
+        rpc.insert_account(ray_usdc.keys.coin_vault, token::ID, token_account(ray_usdc.reserve_a / 2));
+
+        client.refresh_pools(&rpc).await.unwrap();
+        assert!(client.find_pool(&sol_usdc).is_err());
+        let refreshed = client.find_pool(&ray_usdc.address).unwrap();
+        assert!(refreshed.reserve_a < ray_usdc.reserve_a);
+    }
+
+    #[tokio::test]
+    async fn test_quote_direction() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+
+        // 0.0001 SOL at ~150 USDC/SOL
+        let quote = client.get_quote(&pool.token_a, &pool.token_b, 100_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(quote.amount_out > 14_900 && quote.amount_out < 15_000);
+
+        // 0.01 USDC back to SOL
+        let quote = client.get_quote(&pool.token_b, &pool.token_a, 10_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(quote.amount_out > 66_000 && quote.amount_out < 66_700);
+    }
+
+    #[tokio::test]
+    async fn test_quote_exact_out() {
+        let mut client = Client::new().unwrap();
+        let pool = load_sol_usdc(&mut client);
+
+        // Exactly 15 USDC out of SOL
+        let quote = client.get_quote_exact_out(&pool.token_a, &pool.token_b, 15_000_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert_eq!(quote.mode, SwapMode::ExactOut);
+        assert_eq!(quote.amount_out, 15_000_000);
+        assert_eq!(quote.minimum_out, 15_000_000);
+        assert!(quote.maximum_in > quote.amount_in);
+        assert!(quote.amount_in > 100_000_000 && quote.amount_in < 101_000_000);
+
+        let exact_in = client.get_quote(&pool.token_a, &pool.token_b, quote.amount_in, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(exact_in.amount_out >= 15_000_000);
+
+        let ix = client.build_swap_instructions(&quote.into(), &Pubkey::new_unique()).unwrap();
+        assert_eq!(ix[0].data[0], SWAP_BASE_OUT_TAG);
+
+        assert!(client.get_quote_exact_out(&pool.token_a, &pool.token_b, pool.reserve_b, DEFAULT_SLIPPAGE_BPS).await.is_err());
     }
 }
\ No newline at end of file
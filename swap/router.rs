@@ -0,0 +1,227 @@
+//! Multi-hop routing
+//!
+//! Builds a graph of the mint pairs known to every adapter and searches
+//! it for paths of up to [`MAX_HOPS`] swaps, so tokens without a direct
+//! pool can still be routed through common intermediates like USDC or SOL.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    adapter::DexAdapter,
+    math::{self, MathError, Rounding},
+    Quote,
+};
+
+/// Maximum number of swaps in a route
+pub const MAX_HOPS: usize = 3;
+
+/// Maximum number of token paths quoted per route search, shortest first
+pub const MAX_CANDIDATE_PATHS: usize = 32;
+
+/// A swap through one or more pools
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// Swaps in execution order; each leg spends the previous leg's output
+    pub legs: Vec<Quote>,
+}
+
+impl Route {
+    /// Source token mint
+    pub fn token_in(&self) -> Pubkey {
+        self.legs[0].token_in
+    }
+
+    /// Destination token mint
+    pub fn token_out(&self) -> Pubkey {
+        self.legs[self.legs.len() - 1].token_out
+    }
+
+    /// Input amount
+    pub fn amount_in(&self) -> u64 {
+        self.legs[0].amount_in
+    }
+
+    /// Expected output amount
+    pub fn amount_out(&self) -> u64 {
+        self.legs[self.legs.len() - 1].amount_out
+    }
+
+    /// Minimum output amount of the final leg
+    pub fn minimum_out(&self) -> u64 {
+        self.legs[self.legs.len() - 1].minimum_out
+    }
+
+    /// Combined price impact of all legs (in basis points)
+    pub fn price_impact_bps(&self) -> u16 {
+        let remaining = self.legs.iter().fold(10_000u64, |remaining, leg| {
+            remaining * (10_000 - leg.price_impact_bps.min(10_000) as u64) / 10_000
+        });
+        (10_000 - remaining) as u16
+    }
+
+    /// Number of swaps
+    pub fn hops(&self) -> usize {
+        self.legs.len()
+    }
+
+    /// Mints visited, from `token_in` to `token_out`
+    pub fn path(&self) -> Vec<Pubkey> {
+        std::iter::once(self.token_in())
+            .chain(self.legs.iter().map(|leg| leg.token_out))
+            .collect()
+    }
+
+    /// Legs as executed. A leg may deliver as little as its minimum output,
+    /// so each later leg spends only the minimum of the leg before it, with
+    /// its own minimum output scaled down to match.
+    pub fn bounded_legs(&self) -> Result<Vec<Quote>, MathError> {
+        let mut legs = self.legs.clone();
+        for i in 1..legs.len() {
+            let available = legs[i - 1].minimum_out;
+            let leg = &mut legs[i];
+            if leg.amount_in > available {
+                leg.minimum_out = math::mul_div_u64(leg.minimum_out, available, leg.amount_in, Rounding::Down)?;
+                leg.amount_in = available;
+                leg.maximum_in = available;
+            }
+        }
+        Ok(legs)
+    }
+}
+
+/// Undirected graph of mints connected by at least one pool
+#[derive(Debug, Clone, Default)]
+pub struct TokenGraph {
+    /// Mints reachable with a single swap from each mint
+    edges: BTreeMap<Pubkey, BTreeSet<Pubkey>>,
+}
+
+impl TokenGraph {
+    /// Build the graph from the pools known to `adapters`
+    pub fn build<'a>(adapters: impl IntoIterator<Item = &'a dyn DexAdapter>) -> Self {
+        let mut graph = Self::default();
+        for adapter in adapters {
+            for (a, b) in adapter.supported_pairs() {
+                graph.add_pair(a, b);
+            }
+        }
+        graph
+    }
+
+    /// Connect two mints
+    pub fn add_pair(&mut self, a: Pubkey, b: Pubkey) {
+        self.edges.entry(a).or_default().insert(b);
+        self.edges.entry(b).or_default().insert(a);
+    }
+
+    /// Loop-free token paths from `from` to `to` with at most `max_hops`
+    /// swaps, shortest first
+    pub fn paths(&self, from: &Pubkey, to: &Pubkey, max_hops: usize) -> Vec<Vec<Pubkey>> {
+        let mut paths = Vec::new();
+        let mut path = vec![*from];
+        self.search(to, max_hops, &mut path, &mut paths);
+        // Stable, so equally long paths keep their deterministic order
+        paths.sort_by_key(|p| p.len());
+        paths
+    }
+
+    fn search(&self, to: &Pubkey, max_hops: usize, path: &mut Vec<Pubkey>, paths: &mut Vec<Vec<Pubkey>>) {
+        let Some(neighbors) = self.edges.get(&path[path.len() - 1]) else {
+            return;
+        };
+        for next in neighbors {
+            if next == to {
+                let mut found = path.clone();
+                found.push(*to);
+                paths.push(found);
+            } else if path.len() < max_hops && !path.contains(next) {
+                path.push(*next);
+                self.search(to, max_hops, path, paths);
+                path.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::adapter::tests::quote;
+
+    fn leg(token_in: Pubkey, token_out: Pubkey, amount_in: u64, amount_out: u64, impact: u16) -> Quote {
+        Quote {
+            amount_in,
+            amount_out,
+            price_impact_bps: impact,
+            minimum_out: amount_out * 99 / 100,
+            maximum_in: amount_in,
+            ..quote(token_in, token_out)
+        }
+    }
+
+    #[test]
+    fn test_paths() {
+        let [ray, usdc, sol, bonk, orphan] = [(); 5].map(|_| Pubkey::new_unique());
+        let mut graph = TokenGraph::default();
+        graph.add_pair(ray, usdc);
+        graph.add_pair(usdc, sol);
+        graph.add_pair(sol, bonk);
+        graph.add_pair(ray, sol);
+
+        let paths = graph.paths(&ray, &bonk, MAX_HOPS);
+        assert_eq!(paths, vec![vec![ray, sol, bonk], vec![ray, usdc, sol, bonk]]);
+
+        // Direct pools come first
+        assert_eq!(graph.paths(&ray, &sol, MAX_HOPS)[0], vec![ray, sol]);
+
+        // Hop limit
+        assert_eq!(graph.paths(&usdc, &bonk, 1), Vec::<Vec<Pubkey>>::new());
+        assert_eq!(graph.paths(&usdc, &bonk, 2), vec![vec![usdc, sol, bonk]]);
+
+        assert!(graph.paths(&ray, &orphan, MAX_HOPS).is_empty());
+    }
+
+    #[test]
+    fn test_route_totals() {
+        let [ray, usdc, sol] = [(); 3].map(|_| Pubkey::new_unique());
+        let route = Route {
+            legs: vec![
+                leg(ray, usdc, 1_000, 2_000, 100),
+                leg(usdc, sol, 2_000, 13, 200),
+            ],
+        };
+
+        assert_eq!(route.token_in(), ray);
+        assert_eq!(route.token_out(), sol);
+        assert_eq!(route.amount_in(), 1_000);
+        assert_eq!(route.amount_out(), 13);
+        assert_eq!(route.hops(), 2);
+        assert_eq!(route.path(), vec![ray, usdc, sol]);
+        // 1 - 0.99 * 0.98
+        assert_eq!(route.price_impact_bps(), 298);
+    }
+
+    #[test]
+    fn test_bounded_legs() {
+        let [ray, usdc, sol, bonk] = [(); 4].map(|_| Pubkey::new_unique());
+        let route = Route {
+            legs: vec![
+                leg(ray, usdc, 1_000, 2_000, 0),
+                leg(usdc, sol, 2_000, 1_000, 0),
+                leg(sol, bonk, 1_000, 5_000, 0),
+            ],
+        };
+
+        let legs = route.bounded_legs().unwrap();
+        assert_eq!(legs[0].amount_in, 1_000);
+        assert_eq!(legs[0].minimum_out, 1_980);
+        // Each leg spends what the one before it guarantees
+        assert_eq!((legs[1].amount_in, legs[1].maximum_in), (1_980, 1_980));
+        assert_eq!(legs[1].minimum_out, 980);
+        assert_eq!(legs[2].amount_in, 980);
+        assert_eq!(legs[2].minimum_out, 4_851);
+        // Expected amounts are left as quoted
+        assert_eq!(legs[1].amount_out, 1_000);
+    }
+}
@@ -0,0 +1,179 @@
+//! Pre-flight simulation
+//!
+//! Reads what a simulated swap would deliver from the user's destination
+//! token account and turns program errors into typed
+//! [`AgentSwapError`](crate::AgentSwapError) variants, using the failing
+//! program named in the logs to pick the venue that decodes the code.
+
+use anchor_client::solana_sdk::{
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    transaction::{TransactionError, VersionedTransaction},
+};
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anyhow::Result;
+use std::sync::Arc;
+
+use super::{
+    adapter::{DexAdapter, SwapFailure},
+    execution::Simulation,
+    layout::read_token_amount,
+    mint::TOKEN_2022_PROGRAM_ID,
+    Quote, SwapMode,
+};
+use crate::AgentSwapError;
+
+/// Custom error of both token programs when a transfer exceeds the
+/// balance
+const TOKEN_ERROR_INSUFFICIENT_FUNDS: u32 = 1;
+
+/// The user's source and destination token accounts for `quote`, in the
+/// order simulations report them
+pub fn swap_token_accounts(quote: &Quote, user: &Pubkey) -> [Pubkey; 2] {
+    [
+        get_associated_token_address_with_program_id(user, &quote.token_in, &quote.token_program_in),
+        get_associated_token_address_with_program_id(user, &quote.token_out, &quote.token_program_out),
+    ]
+}
+
+/// Tokens the destination account gained during a successful simulation
+pub fn simulated_output(simulation: &Simulation) -> Result<u64> {
+    let post = match simulation.post_accounts.get(1) {
+        Some(Some(data)) => read_token_amount(data)?,
+        _ => anyhow::bail!("Simulation did not return the destination token account"),
+    };
+    // A destination account the swap creates starts empty
+    let pre = token_balance(simulation.pre_accounts.get(1))?;
+    Ok(post.saturating_sub(pre))
+}
+
+/// Innermost program whose failure the logs report
+pub fn failing_program(logs: &[String]) -> Option<Pubkey> {
+    // A failing CPI is logged before the programs that invoked it
+    logs.iter().find_map(|line| {
+        let rest = line.strip_prefix("Program ")?;
+        let (program, reason) = rest.split_once(' ')?;
+        if !reason.starts_with("failed") {
+            return None;
+        }
+        program.parse().ok()
+    })
+}
+
+/// Typed error for `error`, which failed the simulation of `transaction`
+/// executing `quote`
+pub fn decode_failure(
+    error: TransactionError,
+    simulation: &Simulation,
+    quote: &Quote,
+    transaction: &VersionedTransaction,
+    adapters: &[Arc<dyn DexAdapter>],
+) -> AgentSwapError {
+    let TransactionError::InstructionError(index, InstructionError::Custom(code)) = error else {
+        return simulation_failed(simulation, error);
+    };
+    let program = failing_program(&simulation.logs).or_else(|| {
+        let instruction = transaction.message.instructions().get(index as usize)?;
+        Some(*instruction.program_id(transaction.message.static_account_keys()))
+    });
+    let Some(program) = program else {
+        return simulation_failed(simulation, error);
+    };
+
+    let token_program = program == anchor_spl::token::ID || program == TOKEN_2022_PROGRAM_ID;
+    if token_program && code == TOKEN_ERROR_INSUFFICIENT_FUNDS {
+        let required = match quote.mode {
+            SwapMode::ExactIn => quote.amount_in,
+            SwapMode::ExactOut => quote.maximum_in,
+        };
+        let available = token_balance(simulation.pre_accounts.first()).unwrap_or(0);
+        return AgentSwapError::InsufficientFunds { required, available };
+    }
+
+    let decoded = adapters
+        .iter()
+        .find_map(|adapter| Some((adapter.dex_type(), adapter.decode_error(&program, code)?)));
+    match decoded {
+        Some((dex_type, SwapFailure::SlippageExceeded)) => AgentSwapError::SlippageToleranceExceeded { dex_type },
+        Some((dex_type, SwapFailure::InsufficientLiquidity)) => AgentSwapError::InsufficientLiquidity { dex_type },
+        Some((dex_type, SwapFailure::Other(reason))) => AgentSwapError::ProgramError { dex_type, code, reason },
+        None => simulation_failed(simulation, error),
+    }
+}
+
+fn simulation_failed(simulation: &Simulation, error: TransactionError) -> AgentSwapError {
+    AgentSwapError::SimulationFailed {
+        error,
+        logs: simulation.logs.clone(),
+    }
+}
+
+/// Balance of a token account, zero if it does not exist
+fn token_balance(account: Option<&Option<Vec<u8>>>) -> Result<u64> {
+    match account {
+        Some(Some(data)) => read_token_amount(data),
+        _ => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::{adapter::tests::quote, layout::token_account};
+
+    fn custom(code: u32) -> TransactionError {
+        TransactionError::InstructionError(0, InstructionError::Custom(code))
+    }
+
+    fn failed(logs: &[String]) -> Simulation {
+        Simulation {
+            error: None,
+            logs: logs.to_vec(),
+            units_consumed: None,
+            pre_accounts: vec![Some(token_account(400)), None],
+            post_accounts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_simulated_output() {
+        let mut simulation = Simulation {
+            pre_accounts: vec![Some(token_account(1_000)), Some(token_account(50))],
+            post_accounts: vec![Some(token_account(0)), Some(token_account(2_050))],
+            ..Simulation::default()
+        };
+        assert_eq!(simulated_output(&simulation).unwrap(), 2_000);
+
+        simulation.pre_accounts[1] = None;
+        assert_eq!(simulated_output(&simulation).unwrap(), 2_050);
+
+        simulation.post_accounts.clear();
+        assert!(simulated_output(&simulation).is_err());
+    }
+
+    #[test]
+    fn test_decode_failure() {
+        let quote = quote(Pubkey::new_unique(), Pubkey::new_unique());
+        let transaction = VersionedTransaction::default();
+        let token = anchor_spl::token::ID;
+        let logs = [
+            format!("Program {} invoke [2]", token),
+            format!("Program {} failed: custom program error: 0x1", token),
+            "Program 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8 failed: custom program error: 0x1".to_string(),
+        ];
+        assert_eq!(failing_program(&logs), Some(token));
+
+        let err = decode_failure(custom(1), &failed(&logs), &quote, &transaction, &[]);
+        assert!(matches!(err, AgentSwapError::InsufficientFunds { required: 1_000, available: 400 }));
+
+        // Codes no venue claims stay opaque, with the logs attached
+        let err = decode_failure(custom(30), &failed(&logs[2..]), &quote, &transaction, &[]);
+        match err {
+            AgentSwapError::SimulationFailed { logs, .. } => assert_eq!(logs.len(), 1),
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        let err = decode_failure(TransactionError::BlockhashNotFound, &failed(&[]), &quote, &transaction, &[]);
+        assert!(matches!(err, AgentSwapError::SimulationFailed { error: TransactionError::BlockhashNotFound, .. }));
+    }
+}
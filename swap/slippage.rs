@@ -0,0 +1,147 @@
+//! Slippage tolerance
+//!
+//! Quotes carry a `minimum_out` (exact input) or `maximum_in` (exact
+//! output) bound derived from a per-request [`Slippage`]. Tolerance is
+//! either fixed or scaled with the volatility a [`VolatilitySource`],
+//! such as the agent's swap memory, has observed for the pair.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::Result;
+use std::sync::RwLock;
+
+use super::math::{self, Rounding, BPS_DENOMINATOR};
+
+/// Tolerance used when none is configured (1%)
+pub const DEFAULT_SLIPPAGE_BPS: u16 = 100;
+
+/// Dynamic tolerance as a multiple of observed volatility
+pub const VOLATILITY_MULTIPLIER: u16 = 2;
+
+/// Slippage tolerance for a quote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slippage {
+    /// Fixed tolerance (in basis points)
+    Fixed(u16),
+    /// Tolerance following the pair's observed volatility
+    Dynamic {
+        /// Tolerance while nothing has been observed for the pair
+        base_bps: u16,
+        /// Lower bound for calm markets
+        min_bps: u16,
+        /// Upper bound for volatile markets
+        max_bps: u16,
+    },
+}
+
+impl Default for Slippage {
+    fn default() -> Self {
+        Self::Fixed(DEFAULT_SLIPPAGE_BPS)
+    }
+}
+
+impl Slippage {
+    /// Tolerance in basis points for swapping `token_in` into `token_out`
+    pub fn resolve(
+        &self,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        volatility: Option<&dyn VolatilitySource>,
+    ) -> Result<u16> {
+        let bps = match *self {
+            Self::Fixed(bps) => bps,
+            Self::Dynamic { base_bps, min_bps, max_bps } => {
+                if min_bps > max_bps {
+                    anyhow::bail!("Slippage bounds inverted: {} > {}", min_bps, max_bps);
+                }
+                match volatility.and_then(|v| v.volatility_bps(token_in, token_out)) {
+                    Some(observed) => observed
+                        .saturating_mul(VOLATILITY_MULTIPLIER)
+                        .clamp(min_bps, max_bps),
+                    None => base_bps,
+                }
+            }
+        };
+        check_bps(bps)?;
+        Ok(bps)
+    }
+}
+
+/// Observed price volatility per token pair
+pub trait VolatilitySource: Send + Sync {
+    /// Typical deviation of the pair's execution rate (in basis points),
+    /// or `None` without enough observations
+    fn volatility_bps(&self, token_in: &Pubkey, token_out: &Pubkey) -> Option<u16>;
+}
+
+impl<T: VolatilitySource> VolatilitySource for RwLock<T> {
+    fn volatility_bps(&self, token_in: &Pubkey, token_out: &Pubkey) -> Option<u16> {
+        // A poisoned source has no trustworthy observations
+        self.read().ok()?.volatility_bps(token_in, token_out)
+    }
+}
+
+/// Least output accepted for `amount_out` with `slippage_bps` tolerance
+pub fn minimum_out(amount_out: u64, slippage_bps: u16) -> Result<u64> {
+    check_bps(slippage_bps)?;
+    let bps = BPS_DENOMINATOR - slippage_bps as u64;
+    Ok(math::mul_div_u64(amount_out, bps, BPS_DENOMINATOR, Rounding::Down)?)
+}
+
+/// Most input spent for `amount_in` with `slippage_bps` tolerance
+pub fn maximum_in(amount_in: u64, slippage_bps: u16) -> Result<u64> {
+    check_bps(slippage_bps)?;
+    let bps = BPS_DENOMINATOR + slippage_bps as u64;
+    Ok(math::mul_div_u64(amount_in, bps, BPS_DENOMINATOR, Rounding::Up)?)
+}
+
+fn check_bps(slippage_bps: u16) -> Result<()> {
+    if slippage_bps as u64 > BPS_DENOMINATOR {
+        anyhow::bail!("Slippage of {} bps exceeds 100%", slippage_bps);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Observed(Option<u16>);
+
+    impl VolatilitySource for Observed {
+        fn volatility_bps(&self, _token_in: &Pubkey, _token_out: &Pubkey) -> Option<u16> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        assert_eq!(minimum_out(1_000_000, 100).unwrap(), 990_000);
+        assert_eq!(minimum_out(1_000_000, 0).unwrap(), 1_000_000);
+        assert_eq!(minimum_out(999, 100).unwrap(), 989);
+        assert_eq!(maximum_in(1_000_000, 50).unwrap(), 1_005_000);
+        assert_eq!(maximum_in(999, 100).unwrap(), 1_009);
+        assert_eq!(minimum_out(u64::MAX, 1).unwrap(), u64::MAX - u64::MAX / 10_000 - 1);
+
+        assert!(minimum_out(1_000, 10_001).is_err());
+        assert!(maximum_in(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(Slippage::default().resolve(&a, &b, None).unwrap(), DEFAULT_SLIPPAGE_BPS);
+        assert_eq!(Slippage::Fixed(30).resolve(&a, &b, Some(&Observed(Some(500)))).unwrap(), 30);
+        assert!(Slippage::Fixed(10_001).resolve(&a, &b, None).is_err());
+
+        let dynamic = Slippage::Dynamic { base_bps: 100, min_bps: 20, max_bps: 300 };
+        assert_eq!(dynamic.resolve(&a, &b, None).unwrap(), 100);
+        assert_eq!(dynamic.resolve(&a, &b, Some(&Observed(None))).unwrap(), 100);
+        assert_eq!(dynamic.resolve(&a, &b, Some(&Observed(Some(40)))).unwrap(), 80);
+        assert_eq!(dynamic.resolve(&a, &b, Some(&Observed(Some(1)))).unwrap(), 20);
+        assert_eq!(dynamic.resolve(&a, &b, Some(&Observed(Some(u16::MAX)))).unwrap(), 300);
+        assert_eq!(dynamic.resolve(&a, &b, Some(&RwLock::new(Observed(Some(40))))).unwrap(), 80);
+
+        let inverted = Slippage::Dynamic { base_bps: 100, min_bps: 300, max_bps: 20 };
+        assert!(inverted.resolve(&a, &b, None).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Split-order routing
+//!
+//! Divides one swap across the pools of several venues. The input is
+//! handed out in equal chunks, each to the venue whose output grows the
+//! most from it, which converges on equal marginal prices across pools.
+//! Venues quote from the one pool they cache per pair, so each venue
+//! takes at most one leg.
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::Result;
+use std::time::Duration;
+
+use super::{
+    adapter::DexAdapter,
+    fanout::{self, NoQuotes, VenueFailure},
+    DexType, Quote,
+};
+
+/// Number of chunks the input is divided into
+pub const DEFAULT_SPLIT_CHUNKS: u64 = 20;
+
+/// A swap divided across several pools of the same pair
+#[derive(Debug, Clone)]
+pub struct SplitQuote {
+    /// Source token mint
+    pub token_in: Pubkey,
+    /// Destination token mint
+    pub token_out: Pubkey,
+    /// One swap per pool, each quoted for its share of the input
+    pub legs: Vec<Quote>,
+}
+
+impl SplitQuote {
+    /// Total input amount
+    pub fn amount_in(&self) -> u64 {
+        self.legs.iter().map(|leg| leg.amount_in).sum()
+    }
+
+    /// Total expected output amount
+    pub fn amount_out(&self) -> u64 {
+        self.legs.iter().map(|leg| leg.amount_out).sum()
+    }
+
+    /// Total minimum output amount (with slippage)
+    pub fn minimum_out(&self) -> u64 {
+        self.legs.iter().map(|leg| leg.minimum_out).sum()
+    }
+
+    /// Price impact of the legs weighted by input (in basis points)
+    pub fn price_impact_bps(&self) -> u16 {
+        let amount_in = self.amount_in() as u128;
+        if amount_in == 0 {
+            return 0;
+        }
+        let weighted: u128 = self
+            .legs
+            .iter()
+            .map(|leg| leg.amount_in as u128 * leg.price_impact_bps as u128)
+            .sum();
+        (weighted / amount_in) as u16
+    }
+}
+
+/// A venue taking part in a split
+struct Venue<'a> {
+    adapter: &'a dyn DexAdapter,
+    allocated: u64,
+    quote: Option<Quote>,
+    /// Whether the venue still quotes larger amounts
+    active: bool,
+}
+
+/// Split `amount` of `token_in` across the pools of `adapters` in
+/// `chunks` steps, each leg accepting `slippage_bps` less output.
+///
+/// Venues are quoted concurrently, each given `timeout`. A venue that
+/// fails or stalls takes no further chunks but keeps those it has.
+/// Never does worse than swapping everything in the best single pool.
+pub async fn optimize(
+    adapters: &[&dyn DexAdapter],
+    token_in: &Pubkey,
+    token_out: &Pubkey,
+    amount: u64,
+    chunks: u64,
+    slippage_bps: u16,
+    timeout: Duration,
+) -> Result<SplitQuote> {
+    if chunks == 0 {
+        anyhow::bail!("Split needs at least one chunk");
+    }
+    let mut venues: Vec<Venue> = adapters
+        .iter()
+        .filter(|a| a.supports_pair(token_in, token_out))
+        .map(|&adapter| Venue { adapter, allocated: 0, quote: None, active: true })
+        .collect();
+    if venues.is_empty() {
+        anyhow::bail!("No pool for {} -> {}", token_in, token_out);
+    }
+
+    let mut failures: Vec<VenueFailure> = Vec::new();
+    for i in 0..chunks {
+        // Spreads the remainder so chunks differ by at most one unit
+        let chunk = (amount as u128 * (i + 1) as u128 / chunks as u128
+            - amount as u128 * i as u128 / chunks as u128) as u64;
+        if chunk == 0 {
+            continue;
+        }
+
+        let (quotes, failed) = fanout::quote_all(
+            venues.iter().filter(|v| v.active).map(|v| v.adapter),
+            token_in,
+            token_out,
+            timeout,
+            |adapter| {
+                let allocated = venue_of(&venues, adapter.dex_type()).map_or(0, |v| venues[v].allocated);
+                adapter.quote(token_in, token_out, allocated + chunk, slippage_bps)
+            },
+        )
+        .await;
+        // Pools that cannot absorb more input drop out, as do stalled venues
+        for failure in &failed {
+            if let Some(v) = venue_of(&venues, failure.dex_type) {
+                venues[v].active = false;
+            }
+        }
+        failures.extend(failed);
+
+        let mut best: Option<(usize, u64, Quote)> = None;
+        for quote in quotes {
+            let Some(v) = venue_of(&venues, quote.dex_type) else {
+                continue;
+            };
+            let current = venues[v].quote.as_ref().map_or(0, |q| q.amount_out);
+            let gain = quote.amount_out.saturating_sub(current);
+            if !matches!(&best, Some((_, best_gain, _)) if *best_gain >= gain) {
+                best = Some((v, gain, quote));
+            }
+        }
+
+        let Some((v, _, quote)) = best else {
+            let split = venues.iter().map(|v| v.allocated).sum::<u64>();
+            if split == 0 {
+                return Err(NoQuotes { failures }.into());
+            }
+            anyhow::bail!("Insufficient liquidity: only {} of {} could be split", split, amount);
+        };
+        venues[v].allocated += chunk;
+        venues[v].quote = Some(quote);
+    }
+
+    // Chunking can miss the optimum when a single pool wins outright
+    let (singles, _) = fanout::quote_all(
+        venues.iter().filter(|v| v.active).map(|v| v.adapter),
+        token_in,
+        token_out,
+        timeout,
+        |adapter| adapter.quote(token_in, token_out, amount, slippage_bps),
+    )
+    .await;
+    let split = SplitQuote {
+        token_in: *token_in,
+        token_out: *token_out,
+        legs: venues.into_iter().filter_map(|v| v.quote).collect(),
+    };
+    // The first venue wins ties, matching registration order
+    let single = singles.into_iter().reduce(|best, quote| if quote.amount_out > best.amount_out { quote } else { best });
+    match single {
+        Some(quote) if quote.amount_out >= split.amount_out() => Ok(SplitQuote {
+            token_in: *token_in,
+            token_out: *token_out,
+            legs: vec![quote],
+        }),
+        _ => Ok(split),
+    }
+}
+
+/// Position of the venue for `dex_type`
+fn venue_of(venues: &[Venue], dex_type: DexType) -> Option<usize> {
+    venues.iter().position(|v| v.adapter.dex_type() == dex_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::{
+        adapter::tests::{Behaviour, Stub},
+        DEFAULT_SLIPPAGE_BPS,
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn pool(name: &'static str, pair: (Pubkey, Pubkey), reserve: u64) -> Stub {
+        Stub { name, pair, behaviour: Behaviour::Pool(reserve) }
+    }
+
+    #[tokio::test]
+    async fn test_split_equal_pools() {
+        let pair = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (a, b) = (pool("a", pair, 1_000_000), pool("b", pair, 1_000_000));
+
+        let split = optimize(&[&a, &b], &pair.0, &pair.1, 100_000, DEFAULT_SPLIT_CHUNKS, DEFAULT_SLIPPAGE_BPS, TIMEOUT).await.unwrap();
+        assert_eq!(split.legs.len(), 2);
+        assert_eq!(split.amount_in(), 100_000);
+        assert_eq!(split.legs[0].amount_in, 50_000);
+
+        let single = a.quote(&pair.0, &pair.1, 100_000, DEFAULT_SLIPPAGE_BPS).await.unwrap();
+        assert!(split.amount_out() > single.amount_out);
+    }
+
+    #[tokio::test]
+    async fn test_split_follows_depth() {
+        let pair = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (deep, shallow) = (pool("deep", pair, 3_000_000), pool("shallow", pair, 1_000_000));
+
+        let split = optimize(&[&deep, &shallow], &pair.0, &pair.1, 400_000, DEFAULT_SPLIT_CHUNKS, DEFAULT_SLIPPAGE_BPS, TIMEOUT).await.unwrap();
+        assert_eq!(split.amount_in(), 400_000);
+        // Marginal prices equalize with three quarters in the deeper pool
+        assert_eq!(split.legs[0].amount_in, 300_000);
+        assert_eq!(split.legs[1].amount_in, 100_000);
+    }
+
+    #[tokio::test]
+    async fn test_small_trade_stays_single() {
+        let pair = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (deep, shallow) = (pool("deep", pair, 1_000_000_000), pool("shallow", pair, 1_000));
+
+        let split = optimize(&[&shallow, &deep], &pair.0, &pair.1, 1_000, DEFAULT_SPLIT_CHUNKS, DEFAULT_SLIPPAGE_BPS, TIMEOUT).await.unwrap();
+        assert_eq!(split.legs.len(), 1);
+        assert_eq!(split.legs[0].dex_type, DexType::Custom("deep"));
+        assert_eq!(split.amount_in(), 1_000);
+    }
+
+    #[tokio::test]
+    async fn test_split_without_pool() {
+        let pair = (Pubkey::new_unique(), Pubkey::new_unique());
+        let a = pool("a", pair, 1_000_000);
+        let other = Pubkey::new_unique();
+        assert!(optimize(&[&a], &pair.0, &other, 1_000, DEFAULT_SPLIT_CHUNKS, DEFAULT_SLIPPAGE_BPS, TIMEOUT).await.is_err());
+        assert!(optimize(&[&a], &pair.0, &pair.1, 1_000, 0, DEFAULT_SLIPPAGE_BPS, TIMEOUT).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_split_skips_stalled_venue() {
+        let pair = (Pubkey::new_unique(), Pubkey::new_unique());
+        let a = pool("a", pair, 1_000_000);
+        let slow = Stub { name: "slow", pair, behaviour: Behaviour::Stall };
+        let broken = Stub { name: "broken", pair, behaviour: Behaviour::Fail };
+
+        let split = optimize(&[&slow, &a, &broken], &pair.0, &pair.1, 100_000, DEFAULT_SPLIT_CHUNKS, DEFAULT_SLIPPAGE_BPS, TIMEOUT).await.unwrap();
+        assert_eq!(split.legs.len(), 1);
+        assert_eq!(split.legs[0].dex_type, DexType::Custom("a"));
+        assert_eq!(split.amount_in(), 100_000);
+
+        let err = optimize(&[&slow, &broken], &pair.0, &pair.1, 100_000, DEFAULT_SPLIT_CHUNKS, DEFAULT_SLIPPAGE_BPS, TIMEOUT).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<NoQuotes>().unwrap().failures.len(), 2);
+    }
+}
@@ -0,0 +1,214 @@
+//! Token account setup around swaps
+//!
+//! Swap transactions create the user's missing token accounts themselves
+//! rather than in a separate transaction. Swap programs only move SPL
+//! tokens, so native SOL is wrapped into the user's wrapped SOL account
+//! before the swap and unwrapped by closing that account afterwards.
+
+use anchor_client::solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_instruction, system_program,
+};
+use anchor_spl::{
+    associated_token::{self, get_associated_token_address},
+    token::{self, spl_token},
+};
+
+use super::{Quote, SwapMode};
+
+/// Mint of wrapped SOL
+pub const NATIVE_MINT: Pubkey = spl_token::native_mint::ID;
+
+/// Rent-exempt minimum of a token account, in lamports
+pub const TOKEN_ACCOUNT_RENT: u64 = 2_039_280;
+
+/// `CreateIdempotent` tag of the associated token account program
+const CREATE_IDEMPOTENT_TAG: u8 = 1;
+
+/// Instructions a swap transaction runs around its swap instructions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountSetup {
+    /// Create and fund the accounts the swaps use
+    pub setup: Vec<Instruction>,
+    /// Close accounts the swaps no longer need
+    pub cleanup: Vec<Instruction>,
+}
+
+/// Setup and cleanup letting `user` swap `legs`, creating the accounts
+/// the legs deliver to unless listed in `existing` and using native SOL in
+/// place of wrapped SOL. Only SOL the user spends is wrapped, not SOL an
+/// earlier leg of a route delivers.
+///
+/// Unused wrapped input, e.g. below the maximum of an exact-out swap, is
+/// unwrapped along with the output, as is any wrapped SOL the account
+/// already held.
+pub fn account_setup(legs: &[Quote], user: &Pubkey, existing: &[Pubkey]) -> AccountSetup {
+    let mut setup = native_setup(legs, user);
+    let mut created = Vec::new();
+    for leg in legs {
+        let destination = associated_token::get_associated_token_address_with_program_id(
+            user,
+            &leg.token_out,
+            &leg.token_program_out,
+        );
+        let exists = existing.contains(&destination) || created.contains(&destination);
+        if leg.token_out == NATIVE_MINT || exists {
+            continue;
+        }
+        created.push(destination);
+        setup.setup.push(create_associated_token_account_idempotent(user, user, &leg.token_out, &leg.token_program_out));
+    }
+    setup
+}
+
+/// Rent of the token accounts a route through `path` opens for its
+/// intermediate tokens, in lamports. Wrapped SOL is unwrapped afterwards,
+/// refunding its rent.
+pub fn intermediate_rent(path: &[Pubkey]) -> u64 {
+    let Some(intermediates) = path.get(1..path.len().saturating_sub(1)) else {
+        return 0;
+    };
+    intermediates.iter().filter(|mint| **mint != NATIVE_MINT).count() as u64 * TOKEN_ACCOUNT_RENT
+}
+
+fn native_setup(legs: &[Quote], user: &Pubkey) -> AccountSetup {
+    let mut lamports_in = 0;
+    let mut native_out = false;
+    for leg in legs {
+        // Wrapped SOL an earlier leg of a route delivers needs no wrapping
+        if leg.token_in == NATIVE_MINT && !native_out {
+            lamports_in += match leg.mode {
+                SwapMode::ExactIn => leg.amount_in,
+                SwapMode::ExactOut => leg.maximum_in,
+            };
+        }
+        native_out |= leg.token_out == NATIVE_MINT;
+    }
+    if lamports_in == 0 && !native_out {
+        return AccountSetup::default();
+    }
+
+    let wsol = get_associated_token_address(user, &NATIVE_MINT);
+    let mut setup = vec![create_associated_token_account_idempotent(user, user, &NATIVE_MINT, &token::ID)];
+    if lamports_in > 0 {
+        setup.push(system_instruction::transfer(user, &wsol, lamports_in));
+        setup.push(sync_native(&wsol));
+    }
+    AccountSetup {
+        setup,
+        cleanup: vec![close_account(&wsol, user)],
+    }
+}
+
+/// Instruction creating `owner`'s account for `mint` under `token_program`
+/// unless it exists, paid by `payer`
+pub fn create_associated_token_account_idempotent(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let address = associated_token::get_associated_token_address_with_program_id(owner, mint, token_program);
+    Instruction::new_with_bytes(associated_token::ID, &[CREATE_IDEMPOTENT_TAG], vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(address, false),
+        AccountMeta::new_readonly(*owner, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(*token_program, false),
+    ])
+}
+
+fn sync_native(account: &Pubkey) -> Instruction {
+    // Only fails for a wrong token program id
+    spl_token::instruction::sync_native(&token::ID, account).expect("valid token program")
+}
+
+fn close_account(account: &Pubkey, owner: &Pubkey) -> Instruction {
+    spl_token::instruction::close_account(&token::ID, account, owner, owner, &[]).expect("valid token program")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::adapter::tests::quote;
+
+    #[test]
+    fn test_wrap_input() {
+        let (user, usdc) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let wsol = get_associated_token_address(&user, &NATIVE_MINT);
+
+        let accounts = account_setup(&[quote(NATIVE_MINT, usdc)], &user, &[]);
+        assert_eq!(accounts.setup.len(), 4);
+        assert_eq!(accounts.setup[0].program_id, associated_token::ID);
+        assert_eq!(accounts.setup[0].accounts[1].pubkey, wsol);
+        assert_eq!(accounts.setup[1], system_instruction::transfer(&user, &wsol, 1_000));
+        assert_eq!(accounts.setup[2].program_id, token::ID);
+        assert_eq!(accounts.cleanup, vec![close_account(&wsol, &user)]);
+
+        // Split legs wrap once, exact-out legs up to their maximum
+        let legs = [
+            quote(NATIVE_MINT, usdc),
+            Quote { mode: SwapMode::ExactOut, maximum_in: 1_010, ..quote(NATIVE_MINT, usdc) },
+        ];
+        let accounts = account_setup(&legs, &user, &[]);
+        assert_eq!(accounts.setup[1], system_instruction::transfer(&user, &wsol, 2_010));
+        assert_eq!(accounts.setup.len(), 4);
+    }
+
+    #[test]
+    fn test_unwrap_output() {
+        let (user, usdc) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let wsol = get_associated_token_address(&user, &NATIVE_MINT);
+
+        let accounts = account_setup(&[quote(usdc, NATIVE_MINT)], &user, &[]);
+        assert_eq!(accounts.setup, vec![create_associated_token_account_idempotent(&user, &user, &NATIVE_MINT, &token::ID)]);
+        assert_eq!(accounts.cleanup, vec![close_account(&wsol, &user)]);
+    }
+
+    #[test]
+    fn test_intermediate_sol() {
+        let (user, usdc, ray) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let wsol = get_associated_token_address(&user, &NATIVE_MINT);
+
+        // The second leg spends SOL the first delivers
+        let route = [quote(usdc, NATIVE_MINT), quote(NATIVE_MINT, ray)];
+        let accounts = account_setup(&route, &user, &[]);
+        assert_eq!(accounts.setup, vec![
+            create_associated_token_account_idempotent(&user, &user, &NATIVE_MINT, &token::ID),
+            create_associated_token_account_idempotent(&user, &user, &ray, &token::ID),
+        ]);
+        assert_eq!(accounts.cleanup, vec![close_account(&wsol, &user)]);
+    }
+
+    #[test]
+    fn test_create_destinations() {
+        let (user, usdc, ray) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let destination = get_associated_token_address(&user, &ray);
+
+        let accounts = account_setup(&[quote(usdc, ray)], &user, &[]);
+        assert_eq!(accounts.setup, vec![create_associated_token_account_idempotent(&user, &user, &ray, &token::ID)]);
+        assert_eq!(accounts.setup[0].accounts[1].pubkey, destination);
+        assert!(accounts.cleanup.is_empty());
+
+        // Existing accounts are left alone
+        let accounts = account_setup(&[quote(usdc, ray)], &user, &[destination]);
+        assert_eq!(accounts, AccountSetup::default());
+
+        // Routes also open accounts for intermediate tokens
+        let route = [quote(NATIVE_MINT, usdc), quote(usdc, ray)];
+        assert_eq!(account_setup(&route, &user, &[]).setup.len(), 5);
+        assert_eq!(intermediate_rent(&[NATIVE_MINT, usdc, ray]), TOKEN_ACCOUNT_RENT);
+        assert_eq!(intermediate_rent(&[usdc, NATIVE_MINT, ray]), 0);
+        assert_eq!(intermediate_rent(&[usdc, ray]), 0);
+
+        // Token-2022 accounts derive from and belong to their program
+        let token_2022 = crate::swap::TOKEN_2022_PROGRAM_ID;
+        let leg = Quote { token_program_out: token_2022, ..quote(usdc, ray) };
+        let accounts = account_setup(&[leg], &user, &[destination]);
+        assert_eq!(accounts.setup, vec![create_associated_token_account_idempotent(&user, &user, &ray, &token_2022)]);
+        assert_ne!(accounts.setup[0].accounts[1].pubkey, destination);
+        assert_eq!(accounts.setup[0].accounts[5].pubkey, token_2022);
+    }
+}
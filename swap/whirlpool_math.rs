@@ -0,0 +1,828 @@
+//! Whirlpool concentrated liquidity math
+//!
+//! Port of the Whirlpool program's Q64.64 sqrt-price math: tick/price
+//! conversion, single-step swap computation and the swap loop that
+//! crosses initialized ticks. Rounding matches the on-chain program so
+//! quotes agree with what the swap instruction will actually do.
+
+use anyhow::Result;
+
+use super::math::{self, shl_q64, to_u128, to_u64, Rounding, Q64_RESOLUTION};
+pub use super::math::U256;
+
+/// Lowest tick index supported by Whirlpools
+pub const MIN_TICK_INDEX: i32 = -443636;
+/// Highest tick index supported by Whirlpools
+pub const MAX_TICK_INDEX: i32 = 443636;
+
+/// Sqrt price at `MIN_TICK_INDEX`
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+/// Sqrt price at `MAX_TICK_INDEX`
+pub const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+
+/// Denominator of the pool fee rate (hundredths of a basis point)
+pub const FEE_RATE_MUL_VALUE: u128 = 1_000_000;
+
+
+// Constants for `tick_index_from_sqrt_price`
+const BIT_PRECISION: u32 = 14;
+const LOG_B_2_X32: i128 = 59543866431248;
+const LOG_B_P_ERR_MARGIN_LOWER_X64: i128 = 184467440737095516;
+const LOG_B_P_ERR_MARGIN_UPPER_X64: i128 = 15793534762490258745;
+
+/// Convert a tick index to a Q64.64 sqrt price
+pub fn sqrt_price_from_tick_index(tick: i32) -> Result<u128> {
+    if !(MIN_TICK_INDEX..=MAX_TICK_INDEX).contains(&tick) {
+        anyhow::bail!("Tick index {} out of bounds", tick);
+    }
+
+    Ok(sqrt_price_at(tick))
+}
+
+fn sqrt_price_at(tick: i32) -> u128 {
+    if tick >= 0 {
+        sqrt_price_positive_tick(tick)
+    } else {
+        sqrt_price_negative_tick(tick)
+    }
+}
+
+fn sqrt_price_positive_tick(tick: i32) -> u128 {
+    // Q32.96 factors of sqrt(1.0001)^(2^i)
+    const FACTORS: [(i32, u128); 18] = [
+        (0x2, 79236085330515764027303304731),
+        (0x4, 79244008939048815603706035061),
+        (0x8, 79259858533276714757314932305),
+        (0x10, 79291567232598584799939703904),
+        (0x20, 79355022692464371645785046466),
+        (0x40, 79482085999252804386437311141),
+        (0x80, 79736823300114093921829183326),
+        (0x100, 80248749790819932309965073892),
+        (0x200, 81282483887344747381513967011),
+        (0x400, 83390072131320151908154831281),
+        (0x800, 87770609709833776024991924138),
+        (0x1000, 97234110755111693312479820773),
+        (0x2000, 119332217159966728226237229890),
+        (0x4000, 179736315981702064433883588727),
+        (0x8000, 407748233172238350107850275304),
+        (0x10000, 2098478828474011932436660412517),
+        (0x20000, 55581415166113811149459800483533),
+        (0x40000, 38992368544603139932233054999993551),
+    ];
+
+    let mut ratio = if tick & 1 != 0 {
+        U256::from(79232123823359799118286999567u128)
+    } else {
+        U256::from(79228162514264337593543950336u128)
+    };
+    for (bit, factor) in FACTORS {
+        if tick & bit != 0 {
+            ratio = (ratio * U256::from(factor)) >> 96;
+        }
+    }
+
+    (ratio >> 32).as_u128()
+}
+
+fn sqrt_price_negative_tick(tick: i32) -> u128 {
+    // Q64.64 factors of 1 / sqrt(1.0001)^(2^i)
+    const FACTORS: [(i32, u128); 18] = [
+        (0x2, 18444899583751176498),
+        (0x4, 18443055278223354162),
+        (0x8, 18439367220385604838),
+        (0x10, 18431993317065449817),
+        (0x20, 18417254355718160513),
+        (0x40, 18387811781193591352),
+        (0x80, 18329067761203520168),
+        (0x100, 18212142134806087854),
+        (0x200, 17980523815641551639),
+        (0x400, 17526086738831147013),
+        (0x800, 16651378430235024244),
+        (0x1000, 15030750278693429944),
+        (0x2000, 12247334978882834399),
+        (0x4000, 8131365268884726200),
+        (0x8000, 3584323654723342297),
+        (0x10000, 696457651847595233),
+        (0x20000, 26294789957452057),
+        (0x40000, 37481735321082),
+    ];
+
+    let abs_tick = tick.abs();
+    let mut ratio: u128 = if abs_tick & 1 != 0 {
+        18445821805675392311
+    } else {
+        18446744073709551616
+    };
+    for (bit, factor) in FACTORS {
+        if abs_tick & bit != 0 {
+            ratio = (ratio * factor) >> 64;
+        }
+    }
+
+    ratio
+}
+
+/// Convert a Q64.64 sqrt price to the greatest tick index whose sqrt
+/// price is less than or equal to it
+pub fn tick_index_from_sqrt_price(sqrt_price: u128) -> Result<i32> {
+    if !(MIN_SQRT_PRICE_X64..=MAX_SQRT_PRICE_X64).contains(&sqrt_price) {
+        anyhow::bail!("Sqrt price {} out of bounds", sqrt_price);
+    }
+
+    // Integer part of log2(sqrt_price)
+    let msb = 128 - sqrt_price.leading_zeros() - 1;
+    let log2p_integer_x32 = (msb as i128 - 64) << 32;
+
+    // Fractional part, one bit at a time by repeated squaring
+    let mut bit: i128 = 0x8000_0000_0000_0000;
+    let mut precision = 0;
+    let mut log2p_fraction_x64 = 0;
+    let mut r = if msb >= 64 {
+        sqrt_price >> (msb - 63)
+    } else {
+        sqrt_price << (63 - msb)
+    };
+    while bit > 0 && precision < BIT_PRECISION {
+        r *= r;
+        let is_r_more_than_two = (r >> 127) as u32;
+        r >>= 63 + is_r_more_than_two;
+        log2p_fraction_x64 += bit * is_r_more_than_two as i128;
+        bit >>= 1;
+        precision += 1;
+    }
+    let log2p_x32 = log2p_integer_x32 + (log2p_fraction_x64 >> 32);
+
+    // Change of base, then resolve the error margin against the exact price
+    let logbp_x64 = log2p_x32 * LOG_B_2_X32;
+    let tick_low = ((logbp_x64 - LOG_B_P_ERR_MARGIN_LOWER_X64) >> 64) as i32;
+    let tick_high = ((logbp_x64 + LOG_B_P_ERR_MARGIN_UPPER_X64) >> 64) as i32;
+
+    if tick_low == tick_high || sqrt_price_at(tick_high) > sqrt_price {
+        Ok(tick_low)
+    } else {
+        Ok(tick_high)
+    }
+}
+
+/// Default sqrt price limit, i.e. no limit, for a swap direction
+pub fn default_sqrt_price_limit(a_to_b: bool) -> u128 {
+    if a_to_b {
+        MIN_SQRT_PRICE_X64
+    } else {
+        MAX_SQRT_PRICE_X64
+    }
+}
+
+/// Output for `amount_in` if the whole swap filled at `sqrt_price`,
+/// i.e. with no price impact and no fee
+pub fn output_at_sqrt_price(sqrt_price: u128, amount_in: u64, a_to_b: bool) -> Result<u128> {
+    // The price is `sqrt_price^2`, applied in two steps so products fit
+    let amount = amount_in as u128;
+    Ok(if a_to_b {
+        math::mul_q64(math::mul_q64(amount, sqrt_price, Rounding::Down)?, sqrt_price, Rounding::Down)?
+    } else {
+        math::div_q64(math::div_q64(amount, sqrt_price, Rounding::Down)?, sqrt_price, Rounding::Down)?
+    })
+}
+
+/// Amount of token A between two sqrt prices:
+/// `liquidity * (upper - lower) / (upper * lower)`
+pub fn get_amount_delta_a(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u128> {
+    let (lower, upper) = if sqrt_price_0 < sqrt_price_1 {
+        (sqrt_price_0, sqrt_price_1)
+    } else {
+        (sqrt_price_1, sqrt_price_0)
+    };
+
+    let numerator = shl_q64(U256::from(liquidity) * U256::from(upper - lower))?;
+    let denominator = U256::from(upper) * U256::from(lower);
+    Ok(to_u128(math::div(numerator, denominator, Rounding::up_if(round_up))?)?)
+}
+
+/// Amount of token B between two sqrt prices:
+/// `liquidity * (upper - lower)`
+pub fn get_amount_delta_b(
+    sqrt_price_0: u128,
+    sqrt_price_1: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u128> {
+    let diff = sqrt_price_0.abs_diff(sqrt_price_1);
+    if liquidity == 0 || diff == 0 {
+        return Ok(0);
+    }
+
+    Ok(math::mul_q64(liquidity, diff, Rounding::up_if(round_up))?)
+}
+
+/// Sqrt price after adding or removing `amount` of token A:
+/// `sqrt_price * liquidity / (liquidity +- amount * sqrt_price)`
+fn get_next_sqrt_price_from_a_round_up(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u64,
+    amount_specified_is_input: bool,
+) -> Result<u128> {
+    if amount == 0 {
+        return Ok(sqrt_price);
+    }
+
+    let product = U256::from(sqrt_price) * U256::from(amount);
+    let numerator = shl_q64(U256::from(liquidity) * U256::from(sqrt_price))?;
+    let liquidity_x64 = U256::from(liquidity) << Q64_RESOLUTION;
+
+    let denominator = if amount_specified_is_input {
+        liquidity_x64 + product
+    } else {
+        if liquidity_x64 <= product {
+            anyhow::bail!("Insufficient liquidity for output amount");
+        }
+        liquidity_x64 - product
+    };
+
+    let price = to_u128(math::div(numerator, denominator, Rounding::Up)?)?;
+    if !(MIN_SQRT_PRICE_X64..=MAX_SQRT_PRICE_X64).contains(&price) {
+        anyhow::bail!("Sqrt price {} out of bounds", price);
+    }
+    Ok(price)
+}
+
+/// Sqrt price after adding or removing `amount` of token B:
+/// `sqrt_price +- amount / liquidity`
+fn get_next_sqrt_price_from_b_round_down(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u64,
+    amount_specified_is_input: bool,
+) -> Result<u128> {
+    let delta = math::div_q64(amount as u128, liquidity, Rounding::up_if(!amount_specified_is_input))?;
+
+    if amount_specified_is_input {
+        sqrt_price.checked_add(delta)
+    } else {
+        sqrt_price.checked_sub(delta)
+    }
+    .ok_or_else(|| anyhow::anyhow!("Sqrt price out of bounds"))
+}
+
+fn get_next_sqrt_price(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u64,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Result<u128> {
+    if amount_specified_is_input == a_to_b {
+        get_next_sqrt_price_from_a_round_up(sqrt_price, liquidity, amount, amount_specified_is_input)
+    } else {
+        get_next_sqrt_price_from_b_round_down(sqrt_price, liquidity, amount, amount_specified_is_input)
+    }
+}
+
+/// Amount of the token the caller specified (input or output)
+fn get_amount_fixed_delta(
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Result<u128> {
+    if a_to_b == amount_specified_is_input {
+        get_amount_delta_a(sqrt_price_current, sqrt_price_target, liquidity, amount_specified_is_input)
+    } else {
+        get_amount_delta_b(sqrt_price_current, sqrt_price_target, liquidity, amount_specified_is_input)
+    }
+}
+
+/// Amount of the other token
+fn get_amount_unfixed_delta(
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Result<u128> {
+    if a_to_b == amount_specified_is_input {
+        get_amount_delta_b(sqrt_price_current, sqrt_price_target, liquidity, !amount_specified_is_input)
+    } else {
+        get_amount_delta_a(sqrt_price_current, sqrt_price_target, liquidity, !amount_specified_is_input)
+    }
+}
+
+/// Result of a single swap step within one liquidity range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapStep {
+    /// Input consumed, excluding fees
+    pub amount_in: u64,
+    /// Output produced
+    pub amount_out: u64,
+    /// Sqrt price after the step
+    pub next_sqrt_price: u128,
+    /// Fee charged on the input
+    pub fee_amount: u64,
+}
+
+/// Swap within a single liquidity range, moving the price from
+/// `sqrt_price_current` towards `sqrt_price_target`
+pub fn compute_swap_step(
+    amount_remaining: u64,
+    fee_rate: u16,
+    liquidity: u128,
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Result<SwapStep> {
+    let fee_rate = fee_rate as u128;
+    let mut amount_fixed_delta = get_amount_fixed_delta(
+        sqrt_price_current,
+        sqrt_price_target,
+        liquidity,
+        amount_specified_is_input,
+        a_to_b,
+    )?;
+
+    let amount_calc = if amount_specified_is_input {
+        (amount_remaining as u128 * (FEE_RATE_MUL_VALUE - fee_rate) / FEE_RATE_MUL_VALUE) as u64
+    } else {
+        amount_remaining
+    };
+
+    let next_sqrt_price = if amount_calc as u128 >= amount_fixed_delta {
+        sqrt_price_target
+    } else {
+        get_next_sqrt_price(
+            sqrt_price_current,
+            liquidity,
+            amount_calc,
+            amount_specified_is_input,
+            a_to_b,
+        )?
+    };
+    let is_max_swap = next_sqrt_price == sqrt_price_target;
+
+    let amount_unfixed_delta = get_amount_unfixed_delta(
+        sqrt_price_current,
+        next_sqrt_price,
+        liquidity,
+        amount_specified_is_input,
+        a_to_b,
+    )?;
+    if !is_max_swap {
+        amount_fixed_delta = get_amount_fixed_delta(
+            sqrt_price_current,
+            next_sqrt_price,
+            liquidity,
+            amount_specified_is_input,
+            a_to_b,
+        )?;
+    }
+
+    let (amount_in, mut amount_out) = if amount_specified_is_input {
+        (amount_fixed_delta, amount_unfixed_delta)
+    } else {
+        (amount_unfixed_delta, amount_fixed_delta)
+    };
+    let amount_in = u64::try_from(amount_in).map_err(|_| math::MathError::AmountExceedsU64)?;
+    if !amount_specified_is_input && amount_out > amount_remaining as u128 {
+        amount_out = amount_remaining as u128;
+    }
+    let amount_out = u64::try_from(amount_out).map_err(|_| math::MathError::AmountExceedsU64)?;
+
+    let fee_amount = if amount_specified_is_input && !is_max_swap {
+        amount_remaining - amount_in
+    } else {
+        to_u64(math::div(
+            U256::from(amount_in) * U256::from(fee_rate),
+            U256::from(FEE_RATE_MUL_VALUE - fee_rate),
+            Rounding::Up,
+        )?)?
+    };
+
+    Ok(SwapStep {
+        amount_in,
+        amount_out,
+        next_sqrt_price,
+        fee_amount,
+    })
+}
+
+/// An initialized tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    /// Tick index
+    pub index: i32,
+    /// Liquidity added when the price crosses this tick upwards
+    pub liquidity_net: i128,
+}
+
+/// Initialized ticks available to a swap.
+///
+/// The program can only traverse the tick arrays passed to the swap
+/// instruction, so the sequence is bounded; a swap that runs past the
+/// bounds fails like it would on-chain.
+#[derive(Debug, Clone, Default)]
+pub struct TickSequence {
+    /// Initialized ticks, sorted by index
+    ticks: Vec<Tick>,
+    /// Lowest tick index a swap may reach
+    lower_bound: i32,
+    /// Highest tick index a swap may reach
+    upper_bound: i32,
+}
+
+impl TickSequence {
+    /// Create a tick sequence covering `lower_bound..=upper_bound`
+    pub fn new(mut ticks: Vec<Tick>, lower_bound: i32, upper_bound: i32) -> Self {
+        ticks.sort_by_key(|tick| tick.index);
+        Self {
+            ticks,
+            lower_bound: lower_bound.max(MIN_TICK_INDEX),
+            upper_bound: upper_bound.min(MAX_TICK_INDEX),
+        }
+    }
+
+    /// A sequence without initialized ticks spanning every tick
+    pub fn unbounded() -> Self {
+        Self::new(Vec::new(), MIN_TICK_INDEX, MAX_TICK_INDEX)
+    }
+
+    /// Next tick to stop at in the swap direction, and the initialized
+    /// tick at that index, if any
+    fn next_tick(&self, tick_current_index: i32, a_to_b: bool) -> Result<(i32, Option<&Tick>)> {
+        if a_to_b {
+            if tick_current_index < self.lower_bound {
+                anyhow::bail!("Swap exceeds the loaded tick arrays");
+            }
+            // The current tick itself is crossed first when moving down
+            let end = self.ticks.partition_point(|tick| tick.index <= tick_current_index);
+            Ok(match self.ticks[..end].last() {
+                Some(tick) if tick.index >= self.lower_bound => (tick.index, Some(tick)),
+                _ => (self.lower_bound, None),
+            })
+        } else {
+            if tick_current_index >= self.upper_bound {
+                anyhow::bail!("Swap exceeds the loaded tick arrays");
+            }
+            let start = self.ticks.partition_point(|tick| tick.index <= tick_current_index);
+            Ok(match self.ticks.get(start) {
+                Some(tick) if tick.index <= self.upper_bound => (tick.index, Some(tick)),
+                _ => (self.upper_bound, None),
+            })
+        }
+    }
+}
+
+/// Pool state consumed by a swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSnapshot {
+    /// Current sqrt price (Q64.64)
+    pub sqrt_price: u128,
+    /// Current tick index
+    pub tick_current_index: i32,
+    /// Active liquidity
+    pub liquidity: u128,
+    /// Fee rate (in hundredths of a basis point)
+    pub fee_rate: u16,
+}
+
+/// Result of a full swap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapResult {
+    /// Change in token A (paid in when `a_to_b`)
+    pub amount_a: u64,
+    /// Change in token B (paid in when `!a_to_b`)
+    pub amount_b: u64,
+    /// Total fee charged on the input
+    pub fee_amount: u64,
+    /// Sqrt price after the swap
+    pub next_sqrt_price: u128,
+    /// Tick index after the swap
+    pub next_tick_index: i32,
+    /// Active liquidity after the swap
+    pub next_liquidity: u128,
+}
+
+impl SwapResult {
+    /// Input and output amounts for the swap direction
+    pub fn amounts(&self, a_to_b: bool) -> (u64, u64) {
+        if a_to_b {
+            (self.amount_a, self.amount_b)
+        } else {
+            (self.amount_b, self.amount_a)
+        }
+    }
+}
+
+/// Simulate a swap, crossing initialized ticks until `amount` is
+/// exhausted or `sqrt_price_limit` is reached
+pub fn swap(
+    pool: &PoolSnapshot,
+    ticks: &TickSequence,
+    amount: u64,
+    sqrt_price_limit: u128,
+    amount_specified_is_input: bool,
+    a_to_b: bool,
+) -> Result<SwapResult> {
+    if !(MIN_SQRT_PRICE_X64..=MAX_SQRT_PRICE_X64).contains(&sqrt_price_limit) {
+        anyhow::bail!("Sqrt price limit {} out of bounds", sqrt_price_limit);
+    }
+    if a_to_b && sqrt_price_limit > pool.sqrt_price
+        || !a_to_b && sqrt_price_limit < pool.sqrt_price
+    {
+        anyhow::bail!("Sqrt price limit is on the wrong side of the current price");
+    }
+    if amount == 0 {
+        anyhow::bail!("Swap amount must be greater than zero");
+    }
+
+    let mut amount_remaining = amount;
+    let mut amount_calculated = 0u64;
+    let mut fee_amount = 0u64;
+    let mut curr_sqrt_price = pool.sqrt_price;
+    let mut curr_tick_index = pool.tick_current_index;
+    let mut curr_liquidity = pool.liquidity;
+
+    while amount_remaining > 0 && sqrt_price_limit != curr_sqrt_price {
+        let (next_tick_index, next_tick) = ticks.next_tick(curr_tick_index, a_to_b)?;
+        let next_tick_sqrt_price = sqrt_price_from_tick_index(next_tick_index)?;
+        let sqrt_price_target = if a_to_b {
+            sqrt_price_limit.max(next_tick_sqrt_price)
+        } else {
+            sqrt_price_limit.min(next_tick_sqrt_price)
+        };
+
+        let step = compute_swap_step(
+            amount_remaining,
+            pool.fee_rate,
+            curr_liquidity,
+            curr_sqrt_price,
+            sqrt_price_target,
+            amount_specified_is_input,
+            a_to_b,
+        )?;
+
+        let overflow = || anyhow::anyhow!("Swap amount overflow");
+        if amount_specified_is_input {
+            amount_remaining = amount_remaining
+                .checked_sub(step.amount_in)
+                .and_then(|a| a.checked_sub(step.fee_amount))
+                .ok_or_else(overflow)?;
+            amount_calculated = amount_calculated.checked_add(step.amount_out).ok_or_else(overflow)?;
+        } else {
+            amount_remaining = amount_remaining.checked_sub(step.amount_out).ok_or_else(overflow)?;
+            amount_calculated = amount_calculated
+                .checked_add(step.amount_in)
+                .and_then(|a| a.checked_add(step.fee_amount))
+                .ok_or_else(overflow)?;
+        }
+        fee_amount = fee_amount.checked_add(step.fee_amount).ok_or_else(overflow)?;
+
+        if step.next_sqrt_price == next_tick_sqrt_price {
+            if let Some(tick) = next_tick {
+                let delta = if a_to_b { -tick.liquidity_net } else { tick.liquidity_net };
+                curr_liquidity = curr_liquidity
+                    .checked_add_signed(delta)
+                    .ok_or_else(|| anyhow::anyhow!("Liquidity overflow crossing tick {}", tick.index))?;
+            }
+            curr_tick_index = if a_to_b { next_tick_index - 1 } else { next_tick_index };
+        } else if step.next_sqrt_price != curr_sqrt_price {
+            curr_tick_index = tick_index_from_sqrt_price(step.next_sqrt_price)?;
+        }
+        curr_sqrt_price = step.next_sqrt_price;
+    }
+
+    let (amount_a, amount_b) = if a_to_b == amount_specified_is_input {
+        (amount - amount_remaining, amount_calculated)
+    } else {
+        (amount_calculated, amount - amount_remaining)
+    };
+
+    Ok(SwapResult {
+        amount_a,
+        amount_b,
+        fee_amount,
+        next_sqrt_price: curr_sqrt_price,
+        next_tick_index: curr_tick_index,
+        next_liquidity: curr_liquidity,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const Q64: u128 = 1 << 64;
+
+    #[test]
+    fn test_tick_bounds() {
+        // Reference values from the Whirlpool program
+        assert_eq!(sqrt_price_from_tick_index(MAX_TICK_INDEX).unwrap(), MAX_SQRT_PRICE_X64);
+        assert_eq!(sqrt_price_from_tick_index(MIN_TICK_INDEX).unwrap(), MIN_SQRT_PRICE_X64);
+        assert_eq!(sqrt_price_from_tick_index(0).unwrap(), Q64);
+        assert_eq!(tick_index_from_sqrt_price(MAX_SQRT_PRICE_X64).unwrap(), MAX_TICK_INDEX);
+        assert_eq!(tick_index_from_sqrt_price(MIN_SQRT_PRICE_X64).unwrap(), MIN_TICK_INDEX);
+        assert_eq!(tick_index_from_sqrt_price(Q64).unwrap(), 0);
+
+        assert!(sqrt_price_from_tick_index(MAX_TICK_INDEX + 1).is_err());
+        assert!(sqrt_price_from_tick_index(MIN_TICK_INDEX - 1).is_err());
+        assert!(tick_index_from_sqrt_price(MIN_SQRT_PRICE_X64 - 1).is_err());
+    }
+
+    #[test]
+    fn test_tick_reference_vectors() {
+        assert_eq!(sqrt_price_from_tick_index(1).unwrap(), 18447666387855959850);
+        assert_eq!(sqrt_price_from_tick_index(-1).unwrap(), 18445821805675392311);
+        assert_eq!(sqrt_price_from_tick_index(-18973).unwrap(), 7144089069857984829);
+        assert_eq!(tick_index_from_sqrt_price(7144393258922745856).unwrap(), -18973);
+    }
+
+    #[test]
+    fn test_amount_deltas() {
+        // One unit of liquidity between prices 1 and 4 (sqrt 1 and 2)
+        let liquidity = 1_000_000;
+        assert_eq!(get_amount_delta_b(Q64, 2 * Q64, liquidity, false).unwrap(), 1_000_000);
+        assert_eq!(get_amount_delta_a(Q64, 2 * Q64, liquidity, false).unwrap(), 500_000);
+
+        // Rounding direction only matters for inexact results
+        let third = Q64 + Q64 / 3;
+        let down = get_amount_delta_b(Q64, third, 10, false).unwrap();
+        let up = get_amount_delta_b(Q64, third, 10, true).unwrap();
+        assert_eq!(up, down + 1);
+    }
+
+    #[test]
+    fn test_swap_step_within_range() {
+        // Exact input, 0.3% fee, small enough not to reach the target
+        let step = compute_swap_step(
+            1_000_000,
+            3000,
+            1_000_000_000_000,
+            Q64,
+            sqrt_price_from_tick_index(-64).unwrap(),
+            true,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(step.amount_in + step.fee_amount, 1_000_000);
+        assert_eq!(step.fee_amount, 3000);
+        assert_eq!(step.amount_out, 996_999);
+        assert!(step.next_sqrt_price < Q64);
+    }
+
+    #[test]
+    fn test_swap_step_reaches_target() {
+        let target = sqrt_price_from_tick_index(64).unwrap();
+        let step = compute_swap_step(u64::MAX, 3000, 1_000_000, Q64, target, true, false).unwrap();
+
+        assert_eq!(step.next_sqrt_price, target);
+        assert_eq!(step.amount_in, get_amount_delta_b(Q64, target, 1_000_000, true).unwrap() as u64);
+        assert_eq!(step.amount_out, get_amount_delta_a(Q64, target, 1_000_000, false).unwrap() as u64);
+    }
+
+    #[test]
+    fn test_swap_crosses_ticks() {
+        let pool = PoolSnapshot {
+            sqrt_price: Q64,
+            tick_current_index: 0,
+            liquidity: 1_000_000_000,
+            fee_rate: 3000,
+        };
+        // Positions covering [-128, 128) and a deeper [-1024, -128)
+        let ticks = TickSequence::new(
+            vec![
+                Tick { index: -1024, liquidity_net: 5_000_000_000 },
+                Tick { index: -128, liquidity_net: -4_000_000_000 },
+                Tick { index: 128, liquidity_net: -1_000_000_000 },
+            ],
+            -2048,
+            2048,
+        );
+
+        let amount = 50_000_000;
+        let result = swap(&pool, &ticks, amount, default_sqrt_price_limit(true), true, true).unwrap();
+        assert_eq!(result.amount_a, amount);
+        assert!(result.next_tick_index < -128);
+        assert_eq!(result.next_liquidity, 5_000_000_000);
+
+        // Without the deeper position the swap runs off the end of the range
+        let shallow = TickSequence::new(
+            vec![
+                Tick { index: -128, liquidity_net: 1_000_000_000 },
+                Tick { index: 128, liquidity_net: -1_000_000_000 },
+            ],
+            -2048,
+            2048,
+        );
+        assert!(swap(&pool, &shallow, amount, default_sqrt_price_limit(true), true, true).is_err());
+    }
+
+    #[test]
+    fn test_swap_price_limit() {
+        let pool = PoolSnapshot {
+            sqrt_price: Q64,
+            tick_current_index: 0,
+            liquidity: 1_000_000_000,
+            fee_rate: 3000,
+        };
+        let limit = sqrt_price_from_tick_index(-10).unwrap();
+        let result = swap(&pool, &TickSequence::unbounded(), u64::MAX / 2, limit, true, true).unwrap();
+
+        assert_eq!(result.next_sqrt_price, limit);
+        assert!(result.amount_a < u64::MAX / 2);
+
+        // Limits on the wrong side of the price are rejected
+        assert!(swap(&pool, &TickSequence::unbounded(), 1_000, limit, true, false).is_err());
+    }
+
+    #[test]
+    fn test_output_at_sqrt_price() {
+        // Price 4 (sqrt 2)
+        assert_eq!(output_at_sqrt_price(2 * Q64, 1_000, true).unwrap(), 4_000);
+        assert_eq!(output_at_sqrt_price(2 * Q64, 1_000, false).unwrap(), 250);
+        assert_eq!(output_at_sqrt_price(Q64, u64::MAX, true).unwrap(), u64::MAX as u128);
+        assert!(output_at_sqrt_price(MAX_SQRT_PRICE_X64, u64::MAX, true).is_ok());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_tick_round_trip(tick in MIN_TICK_INDEX..=MAX_TICK_INDEX) {
+            let sqrt_price = sqrt_price_from_tick_index(tick).unwrap();
+            prop_assert_eq!(tick_index_from_sqrt_price(sqrt_price).unwrap(), tick);
+        }
+
+        #[test]
+        fn prop_tick_between_prices(tick in MIN_TICK_INDEX..MAX_TICK_INDEX, frac in 0.0f64..1.0) {
+            let lower = sqrt_price_from_tick_index(tick).unwrap();
+            let upper = sqrt_price_from_tick_index(tick + 1).unwrap();
+            prop_assert!(lower < upper);
+
+            let price = lower + ((upper - lower - 1) as f64 * frac) as u128;
+            prop_assert_eq!(tick_index_from_sqrt_price(price).unwrap(), tick);
+        }
+
+        #[test]
+        fn prop_exact_input_never_overspends(
+            amount in 1u64..u64::MAX / 4,
+            liquidity in 1_000u128..1u128 << 100,
+            tick in -100_000i32..100_000,
+            fee_rate in 0u16..50_000,
+            a_to_b: bool,
+        ) {
+            let pool = PoolSnapshot {
+                sqrt_price: sqrt_price_from_tick_index(tick).unwrap(),
+                tick_current_index: tick,
+                liquidity,
+                fee_rate,
+            };
+            let limit = default_sqrt_price_limit(a_to_b);
+            if let Ok(result) = swap(&pool, &TickSequence::unbounded(), amount, limit, true, a_to_b) {
+                let (amount_in, amount_out) = result.amounts(a_to_b);
+                prop_assert!(amount_in <= amount);
+                prop_assert!(result.fee_amount <= amount_in);
+
+                // Output never beats the pre-trade price
+                let price = U256::from(pool.sqrt_price) * U256::from(pool.sqrt_price);
+                let (value_in, value_out) = if a_to_b {
+                    (U256::from(amount_in) * price, U256::from(amount_out) << 128)
+                } else {
+                    (U256::from(amount_in) << 128, U256::from(amount_out) * price)
+                };
+                prop_assert!(value_out <= value_in);
+            }
+        }
+
+        #[test]
+        fn prop_exact_output_covers_input(
+            amount_out in 1u64..1_000_000_000,
+            liquidity in 1_000_000_000u128..1u128 << 80,
+            tick in -50_000i32..50_000,
+            fee_rate in 0u16..50_000,
+            a_to_b: bool,
+        ) {
+            let pool = PoolSnapshot {
+                sqrt_price: sqrt_price_from_tick_index(tick).unwrap(),
+                tick_current_index: tick,
+                liquidity,
+                fee_rate,
+            };
+            let limit = default_sqrt_price_limit(a_to_b);
+            let ticks = TickSequence::unbounded();
+            if let Ok(exact_out) = swap(&pool, &ticks, amount_out, limit, false, a_to_b) {
+                let (required_in, received) = exact_out.amounts(a_to_b);
+                prop_assume!(received == amount_out && required_in > 0);
+
+                // Paying the quoted input must yield at least the requested output
+                let exact_in = swap(&pool, &ticks, required_in, limit, true, a_to_b).unwrap();
+                prop_assert!(exact_in.amounts(a_to_b).1 >= amount_out);
+            }
+        }
+    }
+}
@@ -1,13 +1,12 @@
 use agent_swap::{
     agent::{Memory, SwapAgent},
-    swap::{DexType, Quote, SwapEngine},
+    swap::{DexType, Quote, Slippage, SwapEngine, SwapMode},
     Config, Result,
 };
 
 use solana_sdk::{
     pubkey::Pubkey,
     signature::Keypair,
-    transaction::Transaction,
 };
 use tokio;
 
@@ -19,11 +18,18 @@ const SOL: &str = "So11111111111111111111111111111111111111112";
 fn create_test_quote(amount_in: u64, amount_out: u64) -> Quote {
     Quote {
         dex_type: DexType::Raydium,
+        mode: SwapMode::ExactIn,
+        token_in: USDC.parse().unwrap(),
+        token_out: SOL.parse().unwrap(),
+        token_program_in: anchor_spl::token::ID,
+        token_program_out: anchor_spl::token::ID,
+        pool: Pubkey::new_unique(),
         amount_in,
         amount_out,
         price_impact_bps: 50,
         minimum_out: amount_out * 99 / 100,
-        transaction: Transaction::default(),
+        maximum_in: amount_in,
+        extra_accounts: Vec::new(),
     }
 }
 
@@ -77,7 +83,7 @@ async fn test_slippage_protection() {
     let token_in = USDC.parse::<Pubkey>().unwrap();
     let token_out = SOL.parse::<Pubkey>().unwrap();
     
-    let quote = engine.get_best_quote(&token_in, &token_out, 1_000_000)
+    let quote = engine.get_best_quote(&token_in, &token_out, 1_000_000, Slippage::default())
         .await
         .unwrap();
     
@@ -151,6 +157,7 @@ async fn test_concurrent_operations() {
                 &Pubkey::new_unique(),
                 &Pubkey::new_unique(),
                 1_000_000,
+                Slippage::default(),
             ).await.unwrap();
             
             agent_clone.evaluate_route(&quote).await.unwrap()
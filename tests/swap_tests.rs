@@ -1,12 +1,12 @@
 use agent_swap::{
-    swap::{DexType, Quote, SwapEngine},
+    swap::{DexType, Quote, Slippage, SwapEngine},
     utils::{self, format_amount, parse_amount},
     Config, Result,
 };
 
 use solana_sdk::{
     pubkey::Pubkey,
-    signature::Keypair,
+    signature::{Keypair, Signer},
     transaction::Transaction,
 };
 use tokio;
@@ -35,7 +35,8 @@ async fn test_engine_initialization() {
     assert!(engine.get_best_quote(
         &Pubkey::from_str(USDC).unwrap(),
         &Pubkey::from_str(SOL).unwrap(),
-        1_000_000
+        1_000_000,
+        Slippage::default()
     ).await.is_ok());
 }
 
@@ -47,7 +48,7 @@ async fn test_quote_comparison() {
     let sol = Pubkey::from_str(SOL).unwrap();
     let amount = parse_amount("100", 6).unwrap(); // 100 USDC
     
-    let quote = engine.get_best_quote(&usdc, &sol, amount).await.unwrap();
+    let quote = engine.get_best_quote(&usdc, &sol, amount, Slippage::default()).await.unwrap();
     
     // Verify quote properties
     assert!(quote.amount_out > 0);
@@ -64,15 +65,15 @@ async fn test_multiple_routes() {
     let amount = parse_amount("100", 6).unwrap();
     
     // Get quotes from different DEXes
-    let raydium_quote = engine.get_quote(DexType::Raydium, &usdc, &ray, amount)
+    let raydium_quote = engine.get_quote(DexType::Raydium, &usdc, &ray, amount, Slippage::default())
         .await
         .unwrap();
-    let orca_quote = engine.get_quote(DexType::Orca, &usdc, &ray, amount)
+    let orca_quote = engine.get_quote(DexType::Orca, &usdc, &ray, amount, Slippage::default())
         .await
         .unwrap();
     
     // Best quote should be better than or equal to individual quotes
-    let best_quote = engine.get_best_quote(&usdc, &ray, amount).await.unwrap();
+    let best_quote = engine.get_best_quote(&usdc, &ray, amount, Slippage::default()).await.unwrap();
     assert!(best_quote.amount_out >= raydium_quote.amount_out);
     assert!(best_quote.amount_out >= orca_quote.amount_out);
 }
@@ -85,7 +86,7 @@ async fn test_slippage_calculation() {
     let usdt = Pubkey::from_str(USDT).unwrap();
     let amount = parse_amount("1000", 6).unwrap();
     
-    let quote = engine.get_best_quote(&usdc, &usdt, amount).await.unwrap();
+    let quote = engine.get_best_quote(&usdc, &usdt, amount, Slippage::default()).await.unwrap();
     
     // Verify slippage protection
     assert!(quote.minimum_out >= quote.amount_out * 99 / 100); // 1% max slippage
@@ -103,8 +104,8 @@ async fn test_price_impact() {
     let small_amount = parse_amount("10", 6).unwrap();
     let large_amount = parse_amount("10000", 6).unwrap();
     
-    let small_quote = engine.get_best_quote(&usdc, &sol, small_amount).await.unwrap();
-    let large_quote = engine.get_best_quote(&usdc, &sol, large_amount).await.unwrap();
+    let small_quote = engine.get_best_quote(&usdc, &sol, small_amount, Slippage::default()).await.unwrap();
+    let large_quote = engine.get_best_quote(&usdc, &sol, large_amount, Slippage::default()).await.unwrap();
     
     // Larger amounts should have higher price impact
     assert!(large_quote.price_impact_bps > small_quote.price_impact_bps);
@@ -119,8 +120,8 @@ async fn test_quote_caching() {
     let amount = parse_amount("100", 6).unwrap();
     
     // Get quote twice
-    let quote1 = engine.get_best_quote(&usdc, &sol, amount).await.unwrap();
-    let quote2 = engine.get_best_quote(&usdc, &sol, amount).await.unwrap();
+    let quote1 = engine.get_best_quote(&usdc, &sol, amount, Slippage::default()).await.unwrap();
+    let quote2 = engine.get_best_quote(&usdc, &sol, amount, Slippage::default()).await.unwrap();
     
     // Should get same result from cache
     assert_eq!(quote1.amount_out, quote2.amount_out);
@@ -135,11 +136,13 @@ async fn test_transaction_building() {
     let sol = Pubkey::from_str(SOL).unwrap();
     let amount = parse_amount("100", 6).unwrap();
     
-    let quote = engine.get_best_quote(&usdc, &sol, amount).await.unwrap();
+    let quote = engine.get_best_quote(&usdc, &sol, amount, Slippage::default()).await.unwrap();
+    let transaction = engine.prepare_swap(&quote, &wallet.pubkey()).unwrap();
     
     // Verify transaction
-    assert!(!quote.transaction.message.instructions.is_empty());
-    assert!(quote.transaction.message.header.num_required_signatures > 0);
+    assert!(!transaction.message.instructions().is_empty());
+    assert!(transaction.message.header().num_required_signatures > 0);
+    assert_eq!(transaction.message.static_account_keys()[0], wallet.pubkey());
 }
 
 #[tokio::test]
@@ -152,7 +155,7 @@ async fn test_concurrent_quotes() {
         let sol = Pubkey::from_str(SOL).unwrap();
         
         tokio::spawn(async move {
-            engine_clone.get_best_quote(&usdc, &sol, 1_000_000).await
+            engine_clone.get_best_quote(&usdc, &sol, 1_000_000, Slippage::default()).await
         })
     }).collect();
     
@@ -170,7 +173,7 @@ async fn test_error_handling() {
     let invalid_token = Pubkey::new_unique();
     let sol = Pubkey::from_str(SOL).unwrap();
     
-    let result = engine.get_best_quote(&invalid_token, &sol, 1_000_000).await;
+    let result = engine.get_best_quote(&invalid_token, &sol, 1_000_000, Slippage::default()).await;
     assert!(result.is_err());
 }
 
@@ -182,6 +185,6 @@ async fn test_minimum_amount() {
     let sol = Pubkey::from_str(SOL).unwrap();
     let small_amount = 100; // Too small
     
-    let result = engine.get_best_quote(&usdc, &sol, small_amount).await;
+    let result = engine.get_best_quote(&usdc, &sol, small_amount, Slippage::default()).await;
     assert!(result.is_err());
 }
\ No newline at end of file
@@ -8,11 +8,14 @@ pub mod swap;
 pub mod utils;
 
 use solana_sdk::pubkey::Pubkey;
+use std::sync::{Arc, RwLock};
 use thiserror::Error;
 
+use crate::SwapConfig;
+
 /// Re-export main components
 pub use agent::{SwapAgent, Memory};
-pub use swap::{SwapEngine, DexType, Quote};
+pub use swap::{SwapEngine, DexType, Quote, Slippage};
 pub use utils::{setup_client, setup_wallet};
 
 /// Version information
@@ -66,25 +69,58 @@ pub type Result<T> = std::result::Result<T, AgentSwapError>;
 pub struct Config {
     /// Maximum acceptable slippage (in basis points)
     pub max_slippage_bps: u16,
+    /// Whether to scale slippage with the volatility the agent has
+    /// observed for the pair, up to `max_slippage_bps`
+    pub dynamic_slippage: bool,
     /// Maximum acceptable price impact (in basis points)
     pub max_price_impact_bps: u16,
     /// Minimum amount to swap (in USDC)
     pub min_amount_usdc: u64,
     /// Whether to use AI optimization
     pub use_ai_optimization: bool,
+    /// Timeout for swap execution (in seconds)
+    pub timeout_seconds: u64,
     /// RPC endpoint URL
     pub rpc_url: String,
     /// Commitment level
     pub commitment: solana_sdk::commitment_config::CommitmentConfig,
 }
 
+impl Config {
+    /// Slippage tolerance for quotes made under this configuration
+    pub fn slippage(&self) -> Slippage {
+        if self.dynamic_slippage {
+            // Calm pairs may tighten to a quarter of the maximum
+            Slippage::Dynamic {
+                base_bps: self.max_slippage_bps,
+                min_bps: self.max_slippage_bps / 4,
+                max_bps: self.max_slippage_bps,
+            }
+        } else {
+            Slippage::Fixed(self.max_slippage_bps)
+        }
+    }
+
+    /// Swap settings this configuration implies
+    pub fn swap_config(&self) -> SwapConfig {
+        SwapConfig {
+            slippage_bps: self.max_slippage_bps,
+            min_amount: self.min_amount_usdc,
+            timeout_seconds: self.timeout_seconds,
+            use_ai: self.use_ai_optimization,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             max_slippage_bps: 100,    // 1%
+            dynamic_slippage: false,
             max_price_impact_bps: 300, // 3%
             min_amount_usdc: 1_000_000, // 1 USDC
             use_ai_optimization: true,
+            timeout_seconds: 60,
             rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
             commitment: solana_sdk::commitment_config::CommitmentConfig::confirmed(),
         }
@@ -96,13 +132,21 @@ pub async fn init(config: Config) -> Result<(SwapAgent, SwapEngine)> {
     // Setup Solana client
     let client = utils::setup_client(&config.rpc_url, config.commitment)?;
 
-    // Initialize swap engine
+    // Initialize swap engine, splitting the swap timeout between
+    // quoting and confirmation
     let swap_engine = SwapEngine::new()?;
+    let swap_config = config.swap_config();
+    swap_engine.set_quote_timeout(swap_config.quote_timeout());
+    swap_engine.set_confirm_timeout(swap_config.confirm_timeout());
+
+    // Dynamic slippage follows the volatility in the agent's memory
+    let memory = Arc::new(RwLock::new(Memory::default()));
+    swap_engine.set_volatility_source(memory.clone());
 
     // Initialize agent
     let agent = SwapAgent::new(
         client,
-        Memory::default(),
+        memory,
         Pubkey::default(), // Replace with actual wallet
     )?;
 
@@ -123,7 +167,8 @@ mod tests {
         assert!(engine.get_best_quote(
             &Pubkey::new_unique(),
             &Pubkey::new_unique(),
-            1_000_000
+            1_000_000,
+            Slippage::default()
         ).await.is_ok());
     }
 
@@ -131,8 +176,39 @@ mod tests {
     fn test_config_defaults() {
         let config = Config::default();
         assert_eq!(config.max_slippage_bps, 100);
+        assert_eq!(config.slippage(), Slippage::Fixed(100));
+        assert!(!config.dynamic_slippage);
         assert_eq!(config.max_price_impact_bps, 300);
         assert_eq!(config.min_amount_usdc, 1_000_000);
         assert!(config.use_ai_optimization);
+        assert_eq!(config.timeout_seconds, 60);
+        assert_eq!(config.swap_config().quote_timeout(), swap::DEFAULT_QUOTE_TIMEOUT);
+        assert_eq!(config.swap_config().confirm_timeout(), swap::DEFAULT_CONFIRM_TIMEOUT);
+    }
+
+    #[test]
+    fn test_swap_config() {
+        let config = Config {
+            max_slippage_bps: 50,
+            timeout_seconds: 30,
+            ..Config::default()
+        };
+        let swap_config = config.swap_config();
+        assert_eq!(swap_config.slippage_bps, 50);
+        assert_eq!(swap_config.quote_timeout(), std::time::Duration::from_secs(3));
+        assert_eq!(swap_config.confirm_timeout(), std::time::Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_dynamic_slippage() {
+        let config = Config {
+            max_slippage_bps: 200,
+            dynamic_slippage: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            config.slippage(),
+            Slippage::Dynamic { base_bps: 200, min_bps: 50, max_bps: 200 }
+        );
     }
 }
\ No newline at end of file
@@ -85,7 +85,10 @@ pub async fn ensure_sol_for_fees(
     Ok(())
 }
 
-/// Create associated token account if needed
+/// Create associated token account if needed, in a transaction of its own.
+///
+/// Swap transactions create their destination accounts themselves; this
+/// is for accounts needed outside a swap.
 pub async fn create_associated_token_account_idempotent(
     client: &Client,
     wallet: &Keypair,